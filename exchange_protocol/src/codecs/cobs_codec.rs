@@ -0,0 +1,107 @@
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::codecs::codec::{check_frame_size, Codec, CodecKind, DEFAULT_MAX_FRAME_SIZE};
+use crate::error::ExchangeError;
+
+const DELIMITER: u8 = 0;
+
+/// Consistent Overhead Byte Stuffing: the payload is encoded without zero bytes and every
+/// frame is terminated by a single zero, at most one extra byte per 254 payload bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CobsCodec {
+    max_frame_size: usize,
+}
+
+impl Default for CobsCodec {
+    fn default() -> Self {
+        CobsCodec::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl CobsCodec {
+    pub fn new(max_frame_size: usize) -> CobsCodec {
+        CobsCodec { max_frame_size }
+    }
+
+    fn stuff(bytes: &[u8], dst: &mut BytesMut) {
+        dst.reserve(bytes.len() + bytes.len() / 254 + 2);
+        let mut code_index = dst.len();
+        let mut code = 1u8;
+        dst.put_u8(0);
+
+        for &byte in bytes {
+            if byte != DELIMITER {
+                dst.put_u8(byte);
+                code += 1;
+            }
+            if byte == DELIMITER || code == 0xFF {
+                dst[code_index] = code;
+                code_index = dst.len();
+                code = 1;
+                dst.put_u8(0);
+            }
+        }
+        dst[code_index] = code;
+        dst.put_u8(DELIMITER);
+    }
+
+    fn unstuff(encoded: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let mut bytes = Vec::with_capacity(encoded.len());
+        let mut index = 0;
+
+        while index < encoded.len() {
+            let code = encoded[index] as usize;
+            let block_end = index + code;
+            if code == 0 || block_end > encoded.len() {
+                return Err(ExchangeError::MalformedFrame(
+                    "invalid cobs block".to_string(),
+                ));
+            }
+            bytes.extend_from_slice(&encoded[index + 1..block_end]);
+            index = block_end;
+            if code < 0xFF && index < encoded.len() {
+                bytes.push(DELIMITER);
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl Codec for CobsCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Cobs
+    }
+
+    fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn with_max_frame_size(self, max_frame_size: usize) -> Self {
+        CobsCodec::new(max_frame_size)
+    }
+
+    fn encode(&self, bytes: &[u8], dst: &mut BytesMut) -> Result<(), ExchangeError> {
+        check_frame_size(bytes.len(), self.max_frame_size)?;
+        Self::stuff(bytes, dst);
+        Ok(())
+    }
+
+    fn decode(&self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ExchangeError> {
+        let end = match src.iter().position(|b| *b == DELIMITER) {
+            Some(end) => end,
+            None => {
+                let max_encoded_size = self.max_frame_size + self.max_frame_size / 254 + 1;
+                if src.len() > max_encoded_size {
+                    return Err(ExchangeError::FrameTooLarge(src.len(), self.max_frame_size));
+                }
+                return Ok(None);
+            }
+        };
+
+        let encoded = src.split_to(end);
+        src.advance(1);
+        let bytes = Self::unstuff(&encoded)?;
+        check_frame_size(bytes.len(), self.max_frame_size)?;
+        Ok(Some(bytes))
+    }
+}
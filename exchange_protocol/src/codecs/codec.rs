@@ -0,0 +1,92 @@
+use bytes::BytesMut;
+
+use crate::error::ExchangeError;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest payload of a single IPv4 UDP datagram.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// Codec identifiers exchanged in the handshake.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CodecKind {
+    LengthPrefixed = 1,
+    JsonLines = 2,
+    Cobs = 3,
+    Slip = 4,
+}
+
+impl TryFrom<u8> for CodecKind {
+    type Error = ExchangeError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(CodecKind::LengthPrefixed),
+            2 => Ok(CodecKind::JsonLines),
+            3 => Ok(CodecKind::Cobs),
+            4 => Ok(CodecKind::Slip),
+            _ => Err(ExchangeError::HandshakeFailed(format!(
+                "unknown codec {id}"
+            ))),
+        }
+    }
+}
+
+impl CodecKind {
+    /// Text codecs can't carry compressed payloads.
+    pub fn is_text(&self) -> bool {
+        matches!(self, CodecKind::JsonLines)
+    }
+}
+
+/// Splits a byte stream into frames and back.
+///
+/// Payloads above `max_frame_size` are rejected on both sides.
+pub trait Codec: Clone + Send + Sync + 'static {
+    fn kind(&self) -> CodecKind;
+
+    fn max_frame_size(&self) -> usize;
+
+    fn with_max_frame_size(self, max_frame_size: usize) -> Self;
+
+    /// Appends the frame carrying `bytes` to `dst`.
+    fn encode(&self, bytes: &[u8], dst: &mut BytesMut) -> Result<(), ExchangeError>;
+
+    /// Takes the first complete frame off `src`, `None` when more bytes are needed.
+    fn decode(&self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ExchangeError>;
+
+    /// Both peers have to accept the frames, so the smaller maximum wins.
+    fn negotiate(self, peer_max_frame_size: usize) -> Self {
+        let max_frame_size = self.max_frame_size().min(peer_max_frame_size);
+        self.with_max_frame_size(max_frame_size)
+    }
+
+    fn encode_frame(&self, bytes: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let mut dst = BytesMut::new();
+        self.encode(bytes, &mut dst)?;
+        Ok(dst.to_vec())
+    }
+
+    /// Decodes a frame which must span the whole datagram.
+    fn decode_datagram(&self, datagram: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let mut src = BytesMut::from(datagram);
+        match self.decode(&mut src)? {
+            Some(bytes) if src.is_empty() => Ok(bytes),
+            Some(_) => Err(ExchangeError::MalformedFrame(format!(
+                "{} trailing bytes in datagram",
+                src.len()
+            ))),
+            None => Err(ExchangeError::MalformedFrame(
+                "incomplete frame in datagram".to_string(),
+            )),
+        }
+    }
+}
+
+pub(crate) fn check_frame_size(size: usize, max_frame_size: usize) -> Result<(), ExchangeError> {
+    if size > max_frame_size {
+        Err(ExchangeError::FrameTooLarge(size, max_frame_size))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::codecs::codec::Codec;
+use crate::error::ExchangeError;
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Buffered frame decoding over any async byte stream (tcp, tls, unix sockets, pipes).
+///
+/// Bytes are read in chunks and kept in the reader between calls, so `read_frame` is
+/// cancel safe: dropping its future (e.g. in `select!`) loses no data.
+pub struct FrameReader<R, C> {
+    reader: R,
+    codec: C,
+    buffer: BytesMut,
+    read_timeout: Option<Duration>,
+}
+
+impl<R: AsyncRead + Unpin, C: Codec> FrameReader<R, C> {
+    pub fn new(reader: R, codec: C) -> FrameReader<R, C> {
+        FrameReader {
+            reader,
+            codec,
+            buffer: BytesMut::with_capacity(READ_CHUNK_SIZE),
+            read_timeout: None,
+        }
+    }
+
+    /// Fails `read_frame` with `ReadTimeout` when the peer stays silent for `read_timeout`.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = Some(read_timeout);
+        self
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the stream together with bytes already read but not decoded yet.
+    pub fn into_parts(self) -> (R, BytesMut) {
+        (self.reader, self.buffer)
+    }
+
+    /// Reads the next frame, `None` once the peer closed the stream between frames.
+    pub async fn read_frame(&mut self) -> Result<Option<Vec<u8>>, ExchangeError> {
+        loop {
+            if let Some(frame) = self.codec.decode(&mut self.buffer)? {
+                return Ok(Some(frame));
+            }
+
+            if self.read_chunk().await? == 0 {
+                return if self.buffer.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(ExchangeError::PartialFrame(self.buffer.len()))
+                };
+            }
+        }
+    }
+
+    async fn read_chunk(&mut self) -> Result<usize, ExchangeError> {
+        self.buffer.reserve(READ_CHUNK_SIZE);
+        let read = self.reader.read_buf(&mut self.buffer);
+        match self.read_timeout {
+            Some(read_timeout) => tokio::time::timeout(read_timeout, read)
+                .await
+                .map_err(|_| ExchangeError::ReadTimeout(read_timeout))?
+                .map_err(ExchangeError::Io),
+            None => read.await.map_err(ExchangeError::Io),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::codecs::codec::{check_frame_size, Codec, CodecKind, DEFAULT_MAX_FRAME_SIZE};
+use crate::error::ExchangeError;
+
+const NEWLINE: u8 = b'\n';
+
+/// Newline delimited JSON: one JSON document per line, `\r\n` is accepted on decode.
+///
+/// Payloads must be valid single line JSON, so text tools (`nc`, `jq`, scripts) can
+/// talk to the transports directly.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct JsonLinesCodec {
+    max_frame_size: usize,
+}
+
+impl Default for JsonLinesCodec {
+    fn default() -> Self {
+        JsonLinesCodec::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl JsonLinesCodec {
+    pub fn new(max_frame_size: usize) -> JsonLinesCodec {
+        JsonLinesCodec { max_frame_size }
+    }
+
+    fn check_json(bytes: &[u8]) -> Result<(), ExchangeError> {
+        serde_json::from_slice::<serde_json::Value>(bytes)
+            .map(|_| ())
+            .map_err(|error| ExchangeError::MalformedFrame(format!("invalid json: {error}")))
+    }
+}
+
+impl Codec for JsonLinesCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::JsonLines
+    }
+
+    fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn with_max_frame_size(self, max_frame_size: usize) -> Self {
+        JsonLinesCodec::new(max_frame_size)
+    }
+
+    fn encode(&self, bytes: &[u8], dst: &mut BytesMut) -> Result<(), ExchangeError> {
+        check_frame_size(bytes.len(), self.max_frame_size)?;
+        if bytes.contains(&NEWLINE) {
+            return Err(ExchangeError::MalformedFrame(
+                "json payload spans several lines".to_string(),
+            ));
+        }
+        Self::check_json(bytes)?;
+
+        dst.reserve(bytes.len() + 1);
+        dst.put_slice(bytes);
+        dst.put_u8(NEWLINE);
+        Ok(())
+    }
+
+    fn decode(&self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ExchangeError> {
+        loop {
+            let end = match src.iter().position(|b| *b == NEWLINE) {
+                Some(end) => end,
+                None => {
+                    check_frame_size(src.len(), self.max_frame_size)?;
+                    return Ok(None);
+                }
+            };
+
+            let mut line = src.split_to(end);
+            src.advance(1);
+            if line.last() == Some(&b'\r') {
+                line.truncate(line.len() - 1);
+            }
+            if line.is_empty() {
+                continue;
+            }
+            check_frame_size(line.len(), self.max_frame_size)?;
+            Self::check_json(&line)?;
+            return Ok(Some(line.to_vec()));
+        }
+    }
+}
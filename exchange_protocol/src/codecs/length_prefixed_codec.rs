@@ -0,0 +1,77 @@
+use std::mem;
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::codecs::codec::{
+    check_frame_size, Codec, CodecKind, DEFAULT_MAX_FRAME_SIZE, MAX_DATAGRAM_SIZE,
+};
+use crate::error::ExchangeError;
+
+type LengthType = u32;
+
+pub const LENGTH_SIZE: usize = mem::size_of::<LengthType>();
+
+/// A big endian `u32` payload length followed by the payload.
+///
+/// The announced length is checked before anything is allocated for the payload.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LengthPrefixedCodec {
+    max_frame_size: usize,
+}
+
+impl Default for LengthPrefixedCodec {
+    fn default() -> Self {
+        LengthPrefixedCodec::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl LengthPrefixedCodec {
+    pub fn new(max_frame_size: usize) -> LengthPrefixedCodec {
+        LengthPrefixedCodec {
+            max_frame_size: max_frame_size.min(LengthType::MAX as usize),
+        }
+    }
+
+    /// Codec for payloads that must fit into one datagram together with the length.
+    pub fn datagram() -> LengthPrefixedCodec {
+        LengthPrefixedCodec::new(MAX_DATAGRAM_SIZE - LENGTH_SIZE)
+    }
+}
+
+impl Codec for LengthPrefixedCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::LengthPrefixed
+    }
+
+    fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn with_max_frame_size(self, max_frame_size: usize) -> Self {
+        LengthPrefixedCodec::new(max_frame_size)
+    }
+
+    fn encode(&self, bytes: &[u8], dst: &mut BytesMut) -> Result<(), ExchangeError> {
+        check_frame_size(bytes.len(), self.max_frame_size)?;
+
+        dst.reserve(LENGTH_SIZE + bytes.len());
+        dst.put_u32(bytes.len() as LengthType);
+        dst.put_slice(bytes);
+        Ok(())
+    }
+
+    fn decode(&self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ExchangeError> {
+        if src.len() < LENGTH_SIZE {
+            return Ok(None);
+        }
+        let length = (&src[..LENGTH_SIZE]).get_u32() as usize;
+        check_frame_size(length, self.max_frame_size)?;
+
+        if src.len() < LENGTH_SIZE + length {
+            src.reserve(LENGTH_SIZE + length - src.len());
+            return Ok(None);
+        }
+        src.advance(LENGTH_SIZE);
+        Ok(Some(src.split_to(length).to_vec()))
+    }
+}
@@ -0,0 +1,6 @@
+pub mod cobs_codec;
+pub mod codec;
+pub mod frame_reader;
+pub mod json_lines_codec;
+pub mod length_prefixed_codec;
+pub mod slip_codec;
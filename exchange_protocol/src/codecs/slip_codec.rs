@@ -0,0 +1,103 @@
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::codecs::codec::{check_frame_size, Codec, CodecKind, DEFAULT_MAX_FRAME_SIZE};
+use crate::error::ExchangeError;
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// SLIP framing (RFC 1055), with an `END` byte in front of every frame as well to flush
+/// line noise on the receiving side; empty frames are skipped.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SlipCodec {
+    max_frame_size: usize,
+}
+
+impl Default for SlipCodec {
+    fn default() -> Self {
+        SlipCodec::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl SlipCodec {
+    pub fn new(max_frame_size: usize) -> SlipCodec {
+        SlipCodec { max_frame_size }
+    }
+
+    fn unescape(escaped: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let mut bytes = Vec::with_capacity(escaped.len());
+        let mut iter = escaped.iter();
+
+        while let Some(&byte) = iter.next() {
+            match byte {
+                ESC => match iter.next() {
+                    Some(&ESC_END) => bytes.push(END),
+                    Some(&ESC_ESC) => bytes.push(ESC),
+                    _ => {
+                        return Err(ExchangeError::MalformedFrame(
+                            "invalid slip escape sequence".to_string(),
+                        ))
+                    }
+                },
+                byte => bytes.push(byte),
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl Codec for SlipCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Slip
+    }
+
+    fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn with_max_frame_size(self, max_frame_size: usize) -> Self {
+        SlipCodec::new(max_frame_size)
+    }
+
+    fn encode(&self, bytes: &[u8], dst: &mut BytesMut) -> Result<(), ExchangeError> {
+        check_frame_size(bytes.len(), self.max_frame_size)?;
+
+        dst.reserve(bytes.len() + 2);
+        dst.put_u8(END);
+        for &byte in bytes {
+            match byte {
+                END => dst.put_slice(&[ESC, ESC_END]),
+                ESC => dst.put_slice(&[ESC, ESC_ESC]),
+                byte => dst.put_u8(byte),
+            }
+        }
+        dst.put_u8(END);
+        Ok(())
+    }
+
+    fn decode(&self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ExchangeError> {
+        loop {
+            let end = match src.iter().position(|b| *b == END) {
+                Some(end) => end,
+                None => {
+                    // every payload byte takes two bytes at most
+                    if src.len() > self.max_frame_size * 2 {
+                        return Err(ExchangeError::FrameTooLarge(src.len(), self.max_frame_size));
+                    }
+                    return Ok(None);
+                }
+            };
+
+            let escaped = src.split_to(end);
+            src.advance(1);
+            if escaped.is_empty() {
+                continue;
+            }
+            let bytes = Self::unescape(&escaped)?;
+            check_frame_size(bytes.len(), self.max_frame_size)?;
+            return Ok(Some(bytes));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use crate::error::ExchangeError;
+use crate::handshake::CompressionKind;
+
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+const ZSTD_LEVEL: i32 = 3;
+
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 0b0000_0001;
+
+/// The flags byte in front of every frame.
+const FLAGS_SIZE: usize = 1;
+
+/// Per frame compression once the peers agreed on an algorithm.
+///
+/// Every frame then starts with a flags byte telling whether the rest is compressed.
+/// Small frames and frames that don't shrink are sent as is. The flags byte counts
+/// against the maximum frame size, a payload is one byte shorter than that at most.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Compressor {
+    compression: Option<CompressionKind>,
+    threshold: usize,
+    max_size: usize,
+}
+
+impl Compressor {
+    pub fn new(compression: Option<CompressionKind>, threshold: usize, max_size: usize) -> Self {
+        Compressor {
+            compression,
+            threshold,
+            max_size,
+        }
+    }
+
+    pub fn compression(&self) -> Option<CompressionKind> {
+        self.compression
+    }
+
+    pub fn pack(&self, bytes: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let compression = match self.compression {
+            Some(compression) => compression,
+            None => return Ok(bytes.to_vec()),
+        };
+        // the peer checks the payload as it would be sent raw, whether or not it shrinks
+        check_payload_size(bytes.len(), self.max_size)?;
+
+        if bytes.len() >= self.threshold {
+            let mut frame = vec![COMPRESSED];
+            compress(compression, bytes, &mut frame)?;
+            if frame.len() <= bytes.len() {
+                return Ok(frame);
+            }
+        }
+
+        let mut frame = Vec::with_capacity(bytes.len() + FLAGS_SIZE);
+        frame.push(RAW);
+        frame.extend_from_slice(bytes);
+        Ok(frame)
+    }
+
+    /// Restores the payload, refusing to inflate it above the maximum frame size.
+    pub fn unpack(&self, mut frame: Vec<u8>) -> Result<Vec<u8>, ExchangeError> {
+        let compression = match self.compression {
+            Some(compression) => compression,
+            None => return Ok(frame),
+        };
+
+        match frame.first() {
+            Some(&RAW) => {
+                frame.remove(0);
+                Ok(frame)
+            }
+            Some(&COMPRESSED) => decompress(compression, &frame[1..], self.max_size),
+            Some(flags) => Err(ExchangeError::MalformedFrame(format!(
+                "unknown frame flags {flags:#010b}"
+            ))),
+            None => Err(ExchangeError::MalformedFrame(
+                "frame flags are missing".to_string(),
+            )),
+        }
+    }
+}
+
+fn compress(
+    compression: CompressionKind,
+    bytes: &[u8],
+    dst: &mut Vec<u8>,
+) -> Result<(), ExchangeError> {
+    match compression {
+        CompressionKind::Zstd => zstd::stream::copy_encode(bytes, dst, ZSTD_LEVEL),
+        CompressionKind::Deflate => {
+            let mut encoder = DeflateEncoder::new(dst, flate2::Compression::default());
+            encoder
+                .write_all(bytes)
+                .and_then(|_| encoder.finish().map(|_| ()))
+        }
+    }
+    .map_err(|error| ExchangeError::CompressionError(error.to_string()))
+}
+
+fn decompress(
+    compression: CompressionKind,
+    compressed: &[u8],
+    max_size: usize,
+) -> Result<Vec<u8>, ExchangeError> {
+    let limit = max_size as u64 + 1;
+    let mut bytes = vec![];
+    match compression {
+        CompressionKind::Zstd => zstd::stream::read::Decoder::new(compressed)
+            .and_then(|decoder| decoder.take(limit).read_to_end(&mut bytes)),
+        CompressionKind::Deflate => DeflateDecoder::new(compressed)
+            .take(limit)
+            .read_to_end(&mut bytes),
+    }
+    .map_err(|error| ExchangeError::CompressionError(error.to_string()))?;
+
+    // the real size is unknown beyond the limit, reading on would defeat it
+    check_payload_size(bytes.len(), max_size)?;
+    Ok(bytes)
+}
+
+fn check_payload_size(size: usize, max_size: usize) -> Result<(), ExchangeError> {
+    let frame_size = size.saturating_add(FLAGS_SIZE);
+    if frame_size > max_size {
+        return Err(ExchangeError::FrameTooLarge(frame_size, max_size));
+    }
+    Ok(())
+}
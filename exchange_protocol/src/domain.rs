@@ -1,14 +1,80 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 
 use crate::error::ExchangeError;
 use crate::error::ExchangeError::SendNotifyError;
+use crate::handshake::HandshakeInfo;
 
 #[derive(Debug)]
 pub enum Message {
-    Connected,
+    Connected(ConnectionInfo),
     Bytes(Vec<u8>),
-    Disconnected,
+    /// A text frame, only WebSocket tells them from binary ones.
+    Text(String),
+    /// A failure the connection survived, e.g. a dropped datagram or a failed send.
+    Error(ExchangeError),
+    Disconnected(DisconnectReason),
+    /// The client waits `delay` before it tries to get its server back, a `Connected`
+    /// follows once it did.
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+}
+
+/// What is known about a peer once the connection is established.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConnectionInfo {
+    pub handshake: HandshakeInfo,
+    /// The certificate the peer presented over TLS.
+    pub peer_identity: Option<Arc<PeerIdentity>>,
+}
+
+impl ConnectionInfo {
+    pub fn new(handshake: HandshakeInfo, peer_identity: Option<Arc<PeerIdentity>>) -> Self {
+        ConnectionInfo {
+            handshake,
+            peer_identity,
+        }
+    }
+
+    pub fn version(&self) -> u16 {
+        self.handshake.version
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DisconnectReason {
+    PeerClosed,
+    /// Nothing arrived from the peer within the idle timeout, not even a heartbeat.
+    IdleTimeout(Duration),
+    /// The connection can't go on after a bad frame or a failed read.
+    ProtocolError(String),
+    /// The server is going away and closed the connection on purpose.
+    ServerShutdown,
+    /// The peer sent more than the other side could queue.
+    Overloaded,
+    /// The server closed this connection on purpose, the others stay up.
+    ClosedByServer,
+}
+
+/// Who the transport proved a peer to be.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PeerIdentity {
+    /// A client certificate verified against the trusted roots.
+    Certificate {
+        common_name: Option<String>,
+        dns_names: Vec<String>,
+        der: Vec<u8>,
+    },
+    /// A local process, as the kernel reports the other end of a unix socket.
+    Process {
+        uid: u32,
+        gid: u32,
+        pid: Option<i32>,
+    },
 }
 
 pub struct SendMessage {
@@ -19,6 +85,7 @@ pub struct SendMessage {
 pub struct NotifyMessage {
     pub message: Message,
     pub address: SocketAddr,
+    pub identity: Option<Arc<PeerIdentity>>,
     message_sender_tx: Sender<SendMessage>,
 }
 
@@ -27,10 +94,16 @@ impl NotifyMessage {
         NotifyMessage {
             message,
             address,
+            identity: None,
             message_sender_tx: tx,
         }
     }
 
+    pub fn with_identity(mut self, identity: Option<Arc<PeerIdentity>>) -> NotifyMessage {
+        self.identity = identity;
+        self
+    }
+
     pub async fn reply(&self, bytes: Vec<u8>) -> Result<(), ExchangeError> {
         self.message_sender_tx
             .send(SendMessage {
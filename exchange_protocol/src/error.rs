@@ -1,5 +1,6 @@
 use std::io;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use thiserror::Error;
 
@@ -11,4 +12,40 @@ pub enum ExchangeError {
     Io(#[from] io::Error),
     #[error("Sending notify message for '{0}' failed: {1}")]
     SendNotifyError(SocketAddr, String),
+    #[error("Frame of {0} bytes exceeds the maximum frame size of {1} bytes")]
+    FrameTooLarge(usize, usize),
+    #[error("Malformed frame: {0}")]
+    MalformedFrame(String),
+    #[error("Connection closed in the middle of a frame, {0} bytes left unread")]
+    PartialFrame(usize),
+    #[error("No data received within {0:?}")]
+    ReadTimeout(Duration),
+    #[error("Handshake failed: {0}")]
+    HandshakeFailed(String),
+    #[error("Handshake rejected: {0}")]
+    HandshakeRejected(String),
+    #[error("TLS error: {0}")]
+    TlsError(String),
+    #[error("WebSocket error: {0}")]
+    WebSocketError(String),
+    #[error("Compression failed: {0}")]
+    CompressionError(String),
+    #[error("Corrupted datagram: {0}")]
+    CorruptedDatagram(String),
+    #[error("Datagram of session {0} from a peer bound to session {1}")]
+    ForeignSession(u64, u64),
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    #[error("Datagram {1} of session {0} was replayed")]
+    ReplayedDatagram(u64, u64),
+    #[error("No more than {0} connections are allowed")]
+    ConnectionLimit(usize),
+    #[error("More than {1} messages from '{0}' are waiting")]
+    QueueOverflow(SocketAddr, usize),
+    #[error("No client is connected from '{0}'")]
+    UnknownClient(SocketAddr),
+    #[error("Local user {0} (group {1}) is not allowed to connect")]
+    LocalAccessDenied(u32, u32),
+    #[error("No more than {0} messages can wait for the connection to come back")]
+    PendingOverflow(usize),
 }
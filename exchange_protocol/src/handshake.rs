@@ -0,0 +1,397 @@
+use std::time::Duration;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::codecs::codec::CodecKind;
+use crate::error::ExchangeError;
+
+/// Starts every handshake message. As a length prefix it would announce a frame far above
+/// any datagram, so handshake datagrams can't be mistaken for length prefixed frames.
+pub const MAGIC: [u8; 4] = *b"HXCH";
+
+pub const PROTOCOL_VERSION: u16 = 1;
+
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 2;
+
+const HELLO: u8 = 1;
+const ACCEPT: u8 = 2;
+const REJECT: u8 = 3;
+const NONE: u8 = 0;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CompressionKind {
+    Zstd = 1,
+    Deflate = 2,
+}
+
+impl TryFrom<u8> for CompressionKind {
+    type Error = ExchangeError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(CompressionKind::Zstd),
+            2 => Ok(CompressionKind::Deflate),
+            _ => Err(ExchangeError::HandshakeFailed(format!(
+                "unknown compression {id}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AuthMethod {
+    Anonymous = 1,
+    Credentials = 2,
+    /// Proven by the client certificate of a mutual TLS connection.
+    ClientCertificate = 3,
+}
+
+impl TryFrom<u8> for AuthMethod {
+    type Error = ExchangeError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(AuthMethod::Anonymous),
+            2 => Ok(AuthMethod::Credentials),
+            3 => Ok(AuthMethod::ClientCertificate),
+            _ => Err(ExchangeError::HandshakeFailed(format!(
+                "unknown auth method {id}"
+            ))),
+        }
+    }
+}
+
+/// What a peer offers besides its codec, in order of preference.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Capabilities {
+    pub compressions: Vec<CompressionKind>,
+    pub auth_methods: Vec<AuthMethod>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            compressions: vec![CompressionKind::Zstd, CompressionKind::Deflate],
+            auth_methods: vec![AuthMethod::Credentials],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Hello {
+    pub min_version: u16,
+    pub version: u16,
+    pub max_frame_size: usize,
+    pub codecs: Vec<CodecKind>,
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    pub fn new(codec: CodecKind, max_frame_size: usize, capabilities: Capabilities) -> Hello {
+        Hello {
+            min_version: MIN_PROTOCOL_VERSION,
+            version: PROTOCOL_VERSION,
+            max_frame_size,
+            codecs: vec![codec],
+            capabilities,
+        }
+    }
+
+    /// Agrees on the settings for a connection, the client preferences go first.
+    pub fn negotiate(&self, client: &Hello) -> Result<HandshakeInfo, String> {
+        let version = self.version.min(client.version);
+        if version < self.min_version.max(client.min_version) {
+            return Err(format!(
+                "protocol versions {}..={} and {}..={} are incompatible",
+                client.min_version, client.version, self.min_version, self.version
+            ));
+        }
+
+        let codec = first_common(&client.codecs, &self.codecs)
+            .ok_or_else(|| format!("no common codec in {:?}", client.codecs))?;
+        let auth_method = first_common(
+            &client.capabilities.auth_methods,
+            &self.capabilities.auth_methods,
+        )
+        .ok_or_else(|| {
+            format!(
+                "no common auth method in {:?}",
+                client.capabilities.auth_methods
+            )
+        })?;
+
+        Ok(HandshakeInfo {
+            version,
+            codec,
+            max_frame_size: self.max_frame_size.min(client.max_frame_size),
+            compression: first_common(
+                &client.capabilities.compressions,
+                &self.capabilities.compressions,
+            ),
+            auth_method,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = BytesMut::new();
+        body.put_u16(self.min_version);
+        body.put_u16(self.version);
+        body.put_u32(self.max_frame_size.min(u32::MAX as usize) as u32);
+        put_ids(&mut body, self.codecs.iter().map(|c| *c as u8));
+        put_ids(
+            &mut body,
+            self.capabilities.compressions.iter().map(|c| *c as u8),
+        );
+        put_ids(
+            &mut body,
+            self.capabilities.auth_methods.iter().map(|a| *a as u8),
+        );
+        encode_message(HELLO, &body)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Hello, ExchangeError> {
+        let (kind, mut body) = decode_message(bytes)?;
+        if kind != HELLO {
+            return Err(malformed("hello expected"));
+        }
+        if body.remaining() < 8 {
+            return Err(malformed("hello is truncated"));
+        }
+        let min_version = body.get_u16();
+        let version = body.get_u16();
+        let max_frame_size = body.get_u32() as usize;
+        // unknown ids come from newer peers and are skipped
+        let codecs = get_ids(&mut body)?
+            .filter_map(|id| CodecKind::try_from(id).ok())
+            .collect();
+        let compressions = get_ids(&mut body)?
+            .filter_map(|id| CompressionKind::try_from(id).ok())
+            .collect();
+        let auth_methods = get_ids(&mut body)?
+            .filter_map(|id| AuthMethod::try_from(id).ok())
+            .collect();
+
+        Ok(Hello {
+            min_version,
+            version,
+            max_frame_size,
+            codecs,
+            capabilities: Capabilities {
+                compressions,
+                auth_methods,
+            },
+        })
+    }
+}
+
+/// The settings both peers agreed on.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HandshakeInfo {
+    pub version: u16,
+    pub codec: CodecKind,
+    pub max_frame_size: usize,
+    pub compression: Option<CompressionKind>,
+    pub auth_method: AuthMethod,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HandshakeReply {
+    Accept(HandshakeInfo),
+    Reject(String),
+}
+
+impl HandshakeReply {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = BytesMut::new();
+        match self {
+            HandshakeReply::Accept(info) => {
+                body.put_u16(info.version);
+                body.put_u32(info.max_frame_size.min(u32::MAX as usize) as u32);
+                body.put_u8(info.codec as u8);
+                body.put_u8(info.compression.map_or(NONE, |c| c as u8));
+                body.put_u8(info.auth_method as u8);
+                encode_message(ACCEPT, &body)
+            }
+            HandshakeReply::Reject(reason) => {
+                body.put_slice(reason.as_bytes());
+                encode_message(REJECT, &body)
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<HandshakeReply, ExchangeError> {
+        let (kind, mut body) = decode_message(bytes)?;
+        match kind {
+            ACCEPT if body.remaining() >= 9 => {
+                let version = body.get_u16();
+                let max_frame_size = body.get_u32() as usize;
+                let codec = CodecKind::try_from(body.get_u8())?;
+                let compression = match body.get_u8() {
+                    NONE => None,
+                    id => Some(CompressionKind::try_from(id)?),
+                };
+                let auth_method = AuthMethod::try_from(body.get_u8())?;
+                Ok(HandshakeReply::Accept(HandshakeInfo {
+                    version,
+                    codec,
+                    max_frame_size,
+                    compression,
+                    auth_method,
+                }))
+            }
+            REJECT => Ok(HandshakeReply::Reject(
+                String::from_utf8_lossy(body).into_owned(),
+            )),
+            _ => Err(malformed("handshake reply expected")),
+        }
+    }
+
+    pub fn into_result(self) -> Result<HandshakeInfo, ExchangeError> {
+        match self {
+            HandshakeReply::Accept(info) => Ok(info),
+            HandshakeReply::Reject(reason) => Err(ExchangeError::HandshakeRejected(reason)),
+        }
+    }
+}
+
+pub fn is_handshake(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Sends `hello` and waits for the server to accept it.
+pub async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    hello: &Hello,
+    timeout: Duration,
+) -> Result<HandshakeInfo, ExchangeError> {
+    with_timeout(timeout, async {
+        stream.write_all(&hello.encode()).await?;
+        let reply = read_message(stream).await?;
+        HandshakeReply::decode(&reply)?.into_result()
+    })
+    .await
+}
+
+/// Answers the client hello, telling incompatible clients why they are rejected.
+pub async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    hello: &Hello,
+    timeout: Duration,
+) -> Result<HandshakeInfo, ExchangeError> {
+    with_timeout(timeout, async {
+        let client = Hello::decode(&read_message(stream).await?)?;
+        let reply = match hello.negotiate(&client) {
+            Ok(info) => HandshakeReply::Accept(info),
+            Err(reason) => HandshakeReply::Reject(reason),
+        };
+        stream.write_all(&reply.encode()).await?;
+        reply.into_result()
+    })
+    .await
+}
+
+/// Answers the client's hello with a rejection, e.g. when the server is full.
+pub async fn reject_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    reason: String,
+    timeout: Duration,
+) -> Result<(), ExchangeError> {
+    with_timeout(timeout, async {
+        Hello::decode(&read_message(stream).await?)?;
+        stream
+            .write_all(&HandshakeReply::Reject(reason).encode())
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+async fn with_timeout<T>(
+    timeout: Duration,
+    handshake: impl std::future::Future<Output = Result<T, ExchangeError>>,
+) -> Result<T, ExchangeError> {
+    tokio::time::timeout(timeout, handshake)
+        .await
+        .map_err(|_| ExchangeError::HandshakeFailed(format!("no answer within {timeout:?}")))?
+}
+
+/// Reads exactly one handshake message, so no frame bytes following it are consumed.
+async fn read_message<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, ExchangeError> {
+    let mut message = vec![0u8; HEADER_SIZE];
+    stream
+        .read_exact(&mut message)
+        .await
+        .map_err(|error| ExchangeError::HandshakeFailed(error.to_string()))?;
+    if !is_handshake(&message) {
+        return Err(ExchangeError::HandshakeFailed(
+            "peer doesn't speak the exchange protocol".to_string(),
+        ));
+    }
+
+    let body_size = u16::from_be_bytes([message[HEADER_SIZE - 2], message[HEADER_SIZE - 1]]);
+    message.resize(HEADER_SIZE + body_size as usize, 0);
+    stream
+        .read_exact(&mut message[HEADER_SIZE..])
+        .await
+        .map_err(|error| ExchangeError::HandshakeFailed(error.to_string()))?;
+    Ok(message)
+}
+
+fn encode_message(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_SIZE + body.len());
+    message.extend_from_slice(&MAGIC);
+    message.put_u8(kind);
+    message.put_u16(body.len() as u16);
+    message.extend_from_slice(body);
+    message
+}
+
+fn decode_message(bytes: &[u8]) -> Result<(u8, &[u8]), ExchangeError> {
+    if !is_handshake(bytes) || bytes.len() < HEADER_SIZE {
+        return Err(ExchangeError::HandshakeFailed(
+            "peer doesn't speak the exchange protocol".to_string(),
+        ));
+    }
+    let mut header = &bytes[MAGIC.len()..HEADER_SIZE];
+    let kind = header.get_u8();
+    let body_size = header.get_u16() as usize;
+    let body = &bytes[HEADER_SIZE..];
+    if body.len() != body_size {
+        return Err(malformed("handshake message size mismatch"));
+    }
+    Ok((kind, body))
+}
+
+fn put_ids(body: &mut BytesMut, ids: impl ExactSizeIterator<Item = u8>) {
+    body.put_u8(ids.len() as u8);
+    ids.for_each(|id| body.put_u8(id));
+}
+
+fn get_ids<'a>(body: &mut &'a [u8]) -> Result<impl Iterator<Item = u8> + 'a, ExchangeError> {
+    if body.is_empty() {
+        return Err(malformed("hello is truncated"));
+    }
+    let count = body.get_u8() as usize;
+    if body.len() < count {
+        return Err(malformed("hello is truncated"));
+    }
+    let (ids, rest) = body.split_at(count);
+    *body = rest;
+    Ok(ids.iter().copied())
+}
+
+fn first_common<T: Copy + Eq>(preferred: &[T], supported: &[T]) -> Option<T> {
+    preferred
+        .iter()
+        .copied()
+        .find(|item| supported.contains(item))
+}
+
+fn malformed(reason: &str) -> ExchangeError {
+    ExchangeError::HandshakeFailed(reason.to_string())
+}
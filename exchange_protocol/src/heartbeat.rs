@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use crate::handshake::MAGIC;
+
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// after the handshake message kinds
+const PING: u8 = 4;
+const PONG: u8 = 5;
+const SHUTDOWN: u8 = 6;
+const OVERLOADED: u8 = 7;
+const CLOSED: u8 = 8;
+
+const CONTROL_SIZE: usize = MAGIC.len() + 1 + 2;
+
+/// Clients ping every `interval`, servers answer with a pong. Either side gives up on a
+/// peer it hasn't heard from for `idle_timeout`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat::new(DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration, idle_timeout: Duration) -> Heartbeat {
+        Heartbeat {
+            interval,
+            idle_timeout,
+        }
+    }
+}
+
+/// Frames the transports exchange among themselves, never handed to the application.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Control {
+    Ping,
+    Pong,
+    /// The server's last frame before it closes the connection on purpose.
+    Shutdown,
+    /// Sent before the server disconnects a client that floods it.
+    Overloaded,
+    /// Sent before the server closes this one connection on the application's request.
+    Closed,
+}
+
+impl Control {
+    pub fn encode(&self) -> Vec<u8> {
+        let kind = match self {
+            Control::Ping => PING,
+            Control::Pong => PONG,
+            Control::Shutdown => SHUTDOWN,
+            Control::Overloaded => OVERLOADED,
+            Control::Closed => CLOSED,
+        };
+        let mut frame = Vec::with_capacity(CONTROL_SIZE);
+        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&[kind, 0, 0]);
+        frame
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Control> {
+        if frame.len() != CONTROL_SIZE || !frame.starts_with(&MAGIC) || frame[5..] != [0, 0] {
+            return None;
+        }
+        match frame[4] {
+            PING => Some(Control::Ping),
+            PONG => Some(Control::Pong),
+            SHUTDOWN => Some(Control::Shutdown),
+            OVERLOADED => Some(Control::Overloaded),
+            CLOSED => Some(Control::Closed),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc, Notify};
+
+use crate::domain::{Message, NotifyMessage};
+use crate::error::ExchangeError;
+use crate::limits::{Limits, OverflowPolicy};
+
+/// Messages of all peers of a server waiting for the application.
+///
+/// Each peer gets its own queue of at most `queue_size` payloads and the queues take turns,
+/// so a flooding peer can't crowd out the others. Lifecycle messages are never dropped.
+/// The application's channel closes once every clone of the inbox is gone.
+#[derive(Clone)]
+pub struct Inbox {
+    queues: Arc<Mutex<PeerQueues>>,
+    ready: Arc<Notify>,
+    room: Arc<Notify>,
+    limits: Limits,
+    _alive: mpsc::Sender<()>,
+}
+
+#[derive(Default)]
+struct PeerQueues {
+    queues: HashMap<SocketAddr, PeerQueue>,
+    turns: VecDeque<SocketAddr>,
+    /// The application dropped its receiver.
+    closed: bool,
+}
+
+#[derive(Default)]
+struct PeerQueue {
+    messages: VecDeque<NotifyMessage>,
+    payloads: usize,
+}
+
+impl Inbox {
+    pub fn start(limits: Limits, message_notifier_tx: Sender<NotifyMessage>) -> Inbox {
+        let (alive_tx, alive_rx) = mpsc::channel(1);
+        let inbox = Inbox {
+            queues: Default::default(),
+            ready: Arc::new(Notify::new()),
+            room: Arc::new(Notify::new()),
+            limits,
+            _alive: alive_tx,
+        };
+        tokio::spawn(Self::forward(
+            inbox.queues.clone(),
+            inbox.ready.clone(),
+            inbox.room.clone(),
+            alive_rx,
+            message_notifier_tx,
+        ));
+        inbox
+    }
+
+    /// Fails with `QueueOverflow` when the peer's queue is full and the policy is to
+    /// disconnect it.
+    pub async fn push(&self, notify: NotifyMessage) -> Result<(), ExchangeError> {
+        let payload = matches!(notify.message, Message::Bytes(_));
+        let mut notify = Some(notify);
+        loop {
+            let room = self.room.notified();
+            if self.try_push(&mut notify, payload)? {
+                return Ok(());
+            }
+            room.await;
+        }
+    }
+
+    /// Leaves the message in place when it has to wait for room.
+    fn try_push(
+        &self,
+        notify: &mut Option<NotifyMessage>,
+        payload: bool,
+    ) -> Result<bool, ExchangeError> {
+        let address = notify.as_ref().expect("pushed only once").address;
+        let mut queues = self.queues.lock().expect("inbox lock");
+        if queues.closed {
+            return Err(ExchangeError::SendNotifyError(
+                address,
+                "the application stopped receiving".to_string(),
+            ));
+        }
+        let queue = queues.queues.entry(address).or_default();
+        if payload && queue.payloads >= self.limits.queue_size {
+            match self.limits.overflow {
+                OverflowPolicy::Block => return Ok(false),
+                OverflowPolicy::DropOldest => {
+                    let oldest = queue
+                        .messages
+                        .iter()
+                        .position(|queued| matches!(queued.message, Message::Bytes(_)));
+                    if let Some(oldest) = oldest {
+                        queue.messages.remove(oldest);
+                        queue.payloads -= 1;
+                    }
+                }
+                OverflowPolicy::Disconnect => {
+                    return Err(ExchangeError::QueueOverflow(
+                        address,
+                        self.limits.queue_size,
+                    ))
+                }
+            }
+        }
+
+        let was_empty = queue.messages.is_empty();
+        queue
+            .messages
+            .push_back(notify.take().expect("pushed only once"));
+        queue.payloads += usize::from(payload);
+        if was_empty {
+            queues.turns.push_back(address);
+        }
+        self.ready.notify_one();
+        Ok(true)
+    }
+
+    async fn forward(
+        queues: Arc<Mutex<PeerQueues>>,
+        ready: Arc<Notify>,
+        room: Arc<Notify>,
+        mut alive: mpsc::Receiver<()>,
+        message_notifier_tx: Sender<NotifyMessage>,
+    ) {
+        let mut pushers_gone = false;
+        loop {
+            let next = queues.lock().expect("inbox lock").pop();
+            match next {
+                Some(notify) => {
+                    room.notify_waiters();
+                    if message_notifier_tx.send(notify).await.is_err() {
+                        let mut queues = queues.lock().expect("inbox lock");
+                        *queues = PeerQueues {
+                            closed: true,
+                            ..Default::default()
+                        };
+                        room.notify_waiters();
+                        return;
+                    }
+                }
+                None if pushers_gone => return,
+                None => tokio::select! {
+                    _ = ready.notified() => {}
+                    _ = alive.recv() => pushers_gone = true,
+                },
+            }
+        }
+    }
+}
+
+impl PeerQueues {
+    fn pop(&mut self) -> Option<NotifyMessage> {
+        while let Some(address) = self.turns.pop_front() {
+            let queue = match self.queues.get_mut(&address) {
+                Some(queue) => queue,
+                None => continue,
+            };
+            let notify = match queue.messages.pop_front() {
+                Some(notify) => notify,
+                None => continue,
+            };
+            if matches!(notify.message, Message::Bytes(_)) {
+                queue.payloads -= 1;
+            }
+            if queue.messages.is_empty() {
+                self.queues.remove(&address);
+            } else {
+                self.turns.push_back(address);
+            }
+            return Some(notify);
+        }
+        None
+    }
+}
@@ -1,3 +1,12 @@
 pub mod codecs;
+pub mod compression;
 pub mod domain;
 pub mod error;
+pub mod handshake;
+pub mod heartbeat;
+pub mod inbox;
+pub mod limits;
+pub mod metrics;
+pub mod options;
+pub mod reconnect;
+pub mod shutdown;
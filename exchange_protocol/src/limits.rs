@@ -0,0 +1,51 @@
+pub const DEFAULT_QUEUE_SIZE: usize = 1000;
+
+/// What a server does with a peer whose messages pile up faster than the application
+/// takes them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Stops reading until the application catches up. A udp server can't single out
+    /// the peer, so it stops reading from everyone.
+    Block,
+    /// Makes room by dropping the peer's oldest unread message.
+    DropOldest,
+    /// Tells the peer it is overloading the server and disconnects it.
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Limits {
+    /// Further clients are rejected during the handshake, unlimited when not set.
+    pub max_connections: Option<usize>,
+    /// Bounds the messages of a peer waiting for the application as well as the replies
+    /// waiting to be sent.
+    pub queue_size: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_connections: None,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+impl Limits {
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
@@ -0,0 +1,215 @@
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tracing::{info, warn};
+
+use crate::error::ExchangeError;
+use crate::shutdown::ShutdownHandle;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Where the servers of all crates register what they count, the exporter serves it.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+/// Registers a metric once per process, a family registered under the same name already
+/// is a programming error.
+pub fn register<M: prometheus::core::Collector + Clone + 'static>(metric: M) -> M {
+    registry()
+        .register(Box::new(metric.clone()))
+        .expect("metric registered twice");
+    metric
+}
+
+struct Families {
+    connections: IntCounterVec,
+    active: IntGaugeVec,
+    frames: IntCounterVec,
+    bytes: IntCounterVec,
+    decode_failures: IntCounterVec,
+}
+
+fn families() -> &'static Families {
+    static FAMILIES: OnceLock<Families> = OnceLock::new();
+    FAMILIES.get_or_init(|| {
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            register(IntCounterVec::new(Opts::new(name, help), labels).expect("valid metric"))
+        };
+        Families {
+            connections: counter(
+                "exchange_connections_total",
+                "Peers that connected, handshake done",
+                &["transport"],
+            ),
+            active: register(
+                IntGaugeVec::new(
+                    Opts::new("exchange_connections_active", "Peers connected right now"),
+                    &["transport"],
+                )
+                .expect("valid metric"),
+            ),
+            frames: counter(
+                "exchange_frames_total",
+                "Frames or datagrams received and sent",
+                &["transport", "direction"],
+            ),
+            bytes: counter(
+                "exchange_bytes_total",
+                "Message bytes received and sent, before compression and framing",
+                &["transport", "direction"],
+            ),
+            decode_failures: counter(
+                "exchange_decode_failures_total",
+                "Frames or datagrams that could not be decoded",
+                &["transport"],
+            ),
+        }
+    })
+}
+
+/// What one transport of a server counts, labeled by its name like "tcp" or "udp".
+#[derive(Clone)]
+pub struct TransportMetrics {
+    connections: IntCounter,
+    active: IntGauge,
+    frames_in: IntCounter,
+    frames_out: IntCounter,
+    bytes_in: IntCounter,
+    bytes_out: IntCounter,
+    decode_failures: IntCounter,
+}
+
+impl TransportMetrics {
+    pub fn new(transport: &str) -> TransportMetrics {
+        let families = families();
+        TransportMetrics {
+            connections: families.connections.with_label_values(&[transport]),
+            active: families.active.with_label_values(&[transport]),
+            frames_in: families.frames.with_label_values(&[transport, "in"]),
+            frames_out: families.frames.with_label_values(&[transport, "out"]),
+            bytes_in: families.bytes.with_label_values(&[transport, "in"]),
+            bytes_out: families.bytes.with_label_values(&[transport, "out"]),
+            decode_failures: families.decode_failures.with_label_values(&[transport]),
+        }
+    }
+
+    pub fn connected(&self) {
+        self.connections.inc();
+        self.active.inc();
+    }
+
+    pub fn disconnected(&self) {
+        self.active.dec();
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.frames_in.inc();
+        self.bytes_in.inc_by(bytes as u64);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.frames_out.inc();
+        self.bytes_out.inc_by(bytes as u64);
+    }
+
+    pub fn decode_failed(&self) {
+        self.decode_failures.inc();
+    }
+}
+
+/// Everything registered, in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    prometheus::TextEncoder::new()
+        .encode(&registry().gather(), &mut buffer)
+        .expect("text encoding can't fail");
+    String::from_utf8(buffer).expect("text encoding is utf-8")
+}
+
+/// Serves the metrics to Prometheus on `GET /metrics`, one scrape per connection.
+pub struct MetricsExporter {
+    pub address: SocketAddr,
+    shutdown: ShutdownHandle,
+}
+
+impl MetricsExporter {
+    pub async fn start<Addrs: ToSocketAddrs>(
+        address: Addrs,
+    ) -> Result<MetricsExporter, ExchangeError> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        let (shutdown, mut signal) = ShutdownHandle::new();
+
+        tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(error) => {
+                            warn!(?error, "accepting metrics scrape failed");
+                            continue;
+                        }
+                    },
+                    _ = signal.triggered() => break,
+                };
+                let signal = signal.clone();
+                tokio::spawn(async move {
+                    let _signal = signal;
+                    Self::answer(stream)
+                        .await
+                        .unwrap_or_else(|error| warn!(?error, "answering metrics scrape failed"));
+                });
+            }
+        });
+
+        info!(%address, "serving metrics at /metrics");
+
+        Ok(MetricsExporter { address, shutdown })
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    async fn answer(mut stream: TcpStream) -> Result<(), ExchangeError> {
+        let head = tokio::time::timeout(REQUEST_TIMEOUT, Self::read_head(&mut stream))
+            .await
+            .map_err(|_| ExchangeError::ReadTimeout(REQUEST_TIMEOUT))??;
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let (status, body) = match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+            (Some("GET"), _) => ("404 Not Found", String::new()),
+            _ => ("405 Method Not Allowed", String::new()),
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Reads up to the blank line ending the request head, a scrape has no body.
+    async fn read_head(stream: &mut TcpStream) -> Result<String, ExchangeError> {
+        let mut head = Vec::new();
+        let mut buf = [0; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 || head.len() + read > MAX_REQUEST_SIZE {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        Ok(String::from_utf8_lossy(&head).into_owned())
+    }
+}
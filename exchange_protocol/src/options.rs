@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use crate::codecs::codec::Codec;
+use crate::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use crate::compression::{Compressor, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::handshake::{AuthMethod, Capabilities, HandshakeInfo, Hello, DEFAULT_HANDSHAKE_TIMEOUT};
+use crate::heartbeat::Heartbeat;
+use crate::limits::Limits;
+use crate::reconnect::Reconnect;
+
+/// Settings shared by the transports of both sides of a connection.
+#[derive(Debug, Clone)]
+pub struct ExchangeOptions<C: Codec = LengthPrefixedCodec> {
+    pub codec: C,
+    pub capabilities: Capabilities,
+    /// Peers that don't speak the protocol (e.g. text tools on a json lines port) skip it.
+    pub handshake: bool,
+    pub handshake_timeout: Duration,
+    /// Datagrams carry a CRC32C and session header, both sides have to agree on it.
+    pub checksums: bool,
+    /// Smaller payloads are not worth compressing.
+    pub compression_threshold: usize,
+    pub heartbeat: Option<Heartbeat>,
+    pub limits: Limits,
+    /// Only clients reconnect, servers ignore it.
+    pub reconnect: Option<Reconnect>,
+}
+
+impl Default for ExchangeOptions {
+    fn default() -> Self {
+        ExchangeOptions::new(LengthPrefixedCodec::default())
+    }
+}
+
+impl<C: Codec> ExchangeOptions<C> {
+    pub fn new(codec: C) -> ExchangeOptions<C> {
+        ExchangeOptions {
+            codec,
+            capabilities: Capabilities::default(),
+            handshake: true,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            checksums: false,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            heartbeat: Some(Heartbeat::default()),
+            limits: Limits::default(),
+            reconnect: None,
+        }
+    }
+
+    pub fn without_handshake(mut self) -> Self {
+        self.handshake = false;
+        self
+    }
+
+    pub fn with_checksums(mut self) -> Self {
+        self.checksums = true;
+        self
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    pub fn without_heartbeat(mut self) -> Self {
+        self.heartbeat = None;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    /// Only peers speaking the protocol over a binary codec take part in heartbeats.
+    pub fn active_heartbeat(&self) -> Option<Heartbeat> {
+        self.heartbeat
+            .filter(|_| self.handshake && !self.codec.kind().is_text())
+    }
+
+    pub fn hello(&self) -> Hello {
+        let mut capabilities = self.capabilities.clone();
+        if self.codec.kind().is_text() {
+            capabilities.compressions.clear();
+        }
+        Hello::new(self.codec.kind(), self.codec.max_frame_size(), capabilities)
+    }
+
+    /// What a connection without handshake works with, the local preferences.
+    pub fn assumed_handshake(&self) -> HandshakeInfo {
+        let hello = self.hello();
+        HandshakeInfo {
+            version: hello.version,
+            codec: self.codec.kind(),
+            max_frame_size: hello.max_frame_size,
+            compression: hello.capabilities.compressions.first().copied(),
+            auth_method: hello
+                .capabilities
+                .auth_methods
+                .first()
+                .copied()
+                .unwrap_or(AuthMethod::Anonymous),
+        }
+    }
+
+    pub fn compressor(&self, info: &HandshakeInfo) -> Compressor {
+        Compressor::new(
+            info.compression,
+            self.compression_threshold,
+            info.max_frame_size,
+        )
+    }
+
+    /// The codec limited to what was agreed on for a connection.
+    pub fn negotiated_codec(&self, info: &HandshakeInfo) -> C {
+        self.codec.clone().negotiate(info.max_frame_size)
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::domain::DisconnectReason;
+
+pub const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_MAX_PENDING: usize = 100;
+
+/// How a client gets its server back after losing the connection. The delay doubles
+/// with every failed attempt up to `max_delay`, `jitter` takes up to that share off it
+/// at random, so clients dropped together don't come back together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reconnect {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+    /// Gives up after that many failed attempts, never when not set.
+    pub max_attempts: Option<u32>,
+    /// Bounds the sends waiting for the connection to come back.
+    pub max_pending: usize,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect {
+            initial_delay: DEFAULT_INITIAL_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: 0.5,
+            max_attempts: None,
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+}
+
+impl Reconnect {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Reconnect {
+        Reconnect {
+            initial_delay,
+            max_delay: max_delay.max(initial_delay),
+            ..Reconnect::default()
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// The wait before the attempt, counted from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        if self.jitter > 0.0 {
+            backoff.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..self.jitter))
+        } else {
+            backoff
+        }
+    }
+
+    pub fn gives_up(&self, attempt: u32) -> bool {
+        self.max_attempts.is_some_and(|max| attempt > max)
+    }
+
+    /// A client the server closed on purpose stays away.
+    pub fn applies_to(&self, reason: &DisconnectReason) -> bool {
+        *reason != DisconnectReason::ClosedByServer
+    }
+}
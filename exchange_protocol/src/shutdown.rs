@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::time::Instant;
+
+/// How long in-flight requests get to finish once a shutdown starts.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stops a server and lets its owner wait until all of its tasks are gone.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    deadline: Arc<watch::Sender<Option<Instant>>>,
+    stopped: Arc<Mutex<mpsc::Receiver<()>>>,
+}
+
+/// Held by every task of a server, the server has stopped once all copies are dropped.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    deadline: watch::Receiver<Option<Instant>>,
+    _alive: mpsc::Sender<()>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> (ShutdownHandle, ShutdownSignal) {
+        let (deadline_tx, deadline_rx) = watch::channel(None);
+        // nothing is ever sent, the receiver only learns when the last signal is dropped
+        let (alive_tx, alive_rx) = mpsc::channel(1);
+        let handle = ShutdownHandle {
+            deadline: Arc::new(deadline_tx),
+            stopped: Arc::new(Mutex::new(alive_rx)),
+        };
+        let signal = ShutdownSignal {
+            deadline: deadline_rx,
+            _alive: alive_tx,
+        };
+        (handle, signal)
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown_within(DEFAULT_DRAIN_TIMEOUT)
+    }
+
+    /// Only the first call sets the deadline, later ones can't extend or shorten it.
+    pub fn shutdown_within(&self, drain_timeout: Duration) {
+        self.deadline.send_if_modified(|deadline| match deadline {
+            Some(_) => false,
+            None => {
+                *deadline = Some(Instant::now() + drain_timeout);
+                true
+            }
+        });
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.deadline.borrow().is_some()
+    }
+
+    /// Resolves once every task of the server has terminated.
+    pub async fn stopped(&self) {
+        self.stopped.lock().await.recv().await;
+    }
+}
+
+impl ShutdownSignal {
+    /// Resolves with the drain deadline once a shutdown starts, never if the handles are gone.
+    pub async fn triggered(&mut self) -> Instant {
+        loop {
+            if let Some(deadline) = *self.deadline.borrow() {
+                return deadline;
+            }
+            if self.deadline.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        *self.deadline.borrow()
+    }
+}
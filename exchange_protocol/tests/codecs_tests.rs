@@ -0,0 +1,90 @@
+use bytes::BytesMut;
+
+use exchange_protocol::codecs::cobs_codec::CobsCodec;
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::json_lines_codec::JsonLinesCodec;
+use exchange_protocol::codecs::length_prefixed_codec::{LengthPrefixedCodec, LENGTH_SIZE};
+use exchange_protocol::codecs::slip_codec::SlipCodec;
+use exchange_protocol::error::ExchangeError;
+
+fn assert_round_trip<C: Codec>(codec: C, payloads: &[Vec<u8>]) {
+    let mut stream = BytesMut::new();
+    for payload in payloads {
+        codec.encode(payload, &mut stream).unwrap();
+    }
+
+    // decoding has to cope with the stream arriving in arbitrary pieces
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for chunk in stream.chunks(1_000) {
+        src.extend_from_slice(chunk);
+        while let Some(frame) = codec.decode(&mut src).unwrap() {
+            decoded.push(frame);
+        }
+    }
+    assert_eq!(decoded, payloads);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn test_codecs_round_trip() {
+    let binary = vec![
+        vec![1, 0, 2],
+        vec![0xC0, 0xDB, 0, 0xDC],
+        (0..=255).cycle().take(1_000).collect(),
+        vec![7; 200_000],
+    ];
+    assert_round_trip(LengthPrefixedCodec::default(), &binary);
+    assert_round_trip(CobsCodec::default(), &binary);
+    assert_round_trip(SlipCodec::default(), &binary);
+
+    let json = vec![
+        br#"{"room":"kitchen","enabled":true}"#.to_vec(),
+        b"[1,2,3]".to_vec(),
+    ];
+    assert_round_trip(JsonLinesCodec::default(), &json);
+
+    let mut src = BytesMut::from(&b"\r\n{\"a\":1}\r\n"[..]);
+    assert_eq!(
+        JsonLinesCodec::default().decode(&mut src).unwrap(),
+        Some(b"{\"a\":1}".to_vec())
+    );
+}
+
+#[test]
+fn test_codecs_reject_bad_frames() {
+    let codec = LengthPrefixedCodec::new(16);
+    assert!(matches!(
+        codec.encode_frame(&[0; 17]),
+        Err(ExchangeError::FrameTooLarge(17, 16))
+    ));
+
+    let frame = LengthPrefixedCodec::default()
+        .encode_frame(&[0; 32])
+        .unwrap();
+    assert_eq!(frame.len(), 32 + LENGTH_SIZE);
+    assert!(matches!(
+        codec.decode(&mut BytesMut::from(&frame[..10])),
+        Err(ExchangeError::FrameTooLarge(32, 16))
+    ));
+    assert!(matches!(
+        LengthPrefixedCodec::default().decode_datagram(&frame[..10]),
+        Err(ExchangeError::MalformedFrame(_))
+    ));
+
+    assert!(matches!(
+        JsonLinesCodec::default().encode_frame(b"not json"),
+        Err(ExchangeError::MalformedFrame(_))
+    ));
+    assert!(matches!(
+        JsonLinesCodec::new(4).decode(&mut BytesMut::from(&b"[1,2,3"[..])),
+        Err(ExchangeError::FrameTooLarge(6, 4))
+    ));
+    assert!(matches!(
+        SlipCodec::default().decode(&mut BytesMut::from(&[0xC0, 0xDB, 0x01, 0xC0][..])),
+        Err(ExchangeError::MalformedFrame(_))
+    ));
+
+    assert_eq!(codec.negotiate(8), LengthPrefixedCodec::new(8));
+    assert_eq!(codec.negotiate(1024), codec);
+}
@@ -0,0 +1,85 @@
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::compression::Compressor;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::CompressionKind;
+
+fn report() -> Vec<u8> {
+    "{\"device\":\"socket\",\"power\":220.5}"
+        .repeat(100)
+        .into_bytes()
+}
+
+#[test]
+fn test_compressor_round_trips_large_frames() {
+    for kind in [CompressionKind::Zstd, CompressionKind::Deflate] {
+        let compressor = Compressor::new(Some(kind), 512, 1_000_000);
+        let packed = compressor.pack(&report()).unwrap();
+        assert!(packed.len() < report().len() / 4, "{kind:?}");
+        assert_eq!(compressor.unpack(packed).unwrap(), report());
+    }
+}
+
+#[test]
+fn test_compressor_leaves_small_frames_raw() {
+    let compressor = Compressor::new(Some(CompressionKind::Zstd), 512, 1_000);
+    assert_eq!(compressor.pack(b"21.5").unwrap(), b"\x0021.5");
+    assert_eq!(compressor.unpack(b"\x0021.5".to_vec()).unwrap(), b"21.5");
+
+    let disabled = Compressor::new(None, 512, 1_000);
+    assert_eq!(disabled.pack(&report()).unwrap(), report());
+
+    assert!(matches!(
+        compressor.unpack(b"\x0421.5".to_vec()),
+        Err(ExchangeError::MalformedFrame(_))
+    ));
+}
+
+#[test]
+fn test_compressor_refuses_to_inflate_above_max_size() {
+    let bomb = Compressor::new(Some(CompressionKind::Deflate), 0, usize::MAX)
+        .pack(&vec![0; 1_000_000])
+        .unwrap();
+    assert!(bomb.len() < 10_000);
+
+    let compressor = Compressor::new(Some(CompressionKind::Deflate), 0, 64 * 1024);
+    assert!(matches!(
+        compressor.unpack(bomb),
+        Err(ExchangeError::FrameTooLarge(_, 65536))
+    ));
+}
+
+#[test]
+fn test_compressed_frames_fit_the_max_frame_size() {
+    const MAX: usize = 1_000;
+    let codec = LengthPrefixedCodec::new(MAX);
+    for (kind, threshold) in [
+        (CompressionKind::Zstd, 0),
+        (CompressionKind::Deflate, 0),
+        (CompressionKind::Zstd, 2 * MAX),
+    ] {
+        let compressor = Compressor::new(Some(kind), threshold, MAX);
+
+        // the flags byte takes the last one
+        let payload = report()[..MAX - 1].to_vec();
+        let frame = codec
+            .encode_frame(&compressor.pack(&payload).unwrap())
+            .unwrap();
+        let decoded = codec.decode_datagram(&frame).unwrap();
+        assert_eq!(compressor.unpack(decoded).unwrap(), payload);
+
+        assert!(matches!(
+            compressor.pack(&report()[..MAX]),
+            Err(ExchangeError::FrameTooLarge(1_001, MAX))
+        ));
+    }
+
+    // inflated to the full frame size there is no room for the flags byte
+    let inflated = Compressor::new(Some(CompressionKind::Deflate), 0, usize::MAX)
+        .pack(&report()[..MAX])
+        .unwrap();
+    assert!(matches!(
+        Compressor::new(Some(CompressionKind::Deflate), 0, MAX).unpack(inflated),
+        Err(ExchangeError::FrameTooLarge(_, MAX))
+    ));
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::frame_reader::FrameReader;
+use exchange_protocol::codecs::json_lines_codec::JsonLinesCodec;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::error::ExchangeError;
+
+#[tokio::test]
+async fn test_frame_reader_over_duplex() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let codec = LengthPrefixedCodec::default();
+    let mut frames = FrameReader::new(reader, codec);
+
+    tokio::spawn(async move {
+        for size in [0, 10, 1_000, 100_000] {
+            let frame = codec.encode_frame(&vec![1; size]).unwrap();
+            writer.write_all(&frame).await.unwrap();
+        }
+    });
+
+    for size in [0, 10, 1_000, 100_000] {
+        let frame = frames.read_frame().await.unwrap();
+        assert_eq!(frame.map(|frame| frame.len()), Some(size));
+    }
+    assert!(frames.read_frame().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_frame_reader_reports_partial_frames() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let mut frames = FrameReader::new(reader, JsonLinesCodec::default());
+
+    writer.write_all(b"{\"a\":1}\n{\"b\":").await.unwrap();
+    drop(writer);
+
+    assert_eq!(
+        frames.read_frame().await.unwrap(),
+        Some(b"{\"a\":1}".to_vec())
+    );
+    assert!(matches!(
+        frames.read_frame().await,
+        Err(ExchangeError::PartialFrame(5))
+    ));
+}
+
+#[tokio::test]
+async fn test_frame_reader_timeout_and_cancellation() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let mut frames = FrameReader::new(reader, JsonLinesCodec::default())
+        .with_read_timeout(Duration::from_millis(50));
+
+    assert!(matches!(
+        frames.read_frame().await,
+        Err(ExchangeError::ReadTimeout(_))
+    ));
+
+    // a cancelled read keeps the bytes it has already buffered
+    writer.write_all(b"{\"temperature\":").await.unwrap();
+    tokio::select! {
+        _ = frames.read_frame() => panic!("frame is not complete yet"),
+        _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+    }
+    writer.write_all(b"21.5}\n").await.unwrap();
+    assert_eq!(
+        frames.read_frame().await.unwrap(),
+        Some(b"{\"temperature\":21.5}".to_vec())
+    );
+}
+
+#[tokio::test]
+async fn test_frame_reader_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let sender = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let codec = LengthPrefixedCodec::default();
+        stream
+            .write_all(&codec.encode_frame(&[1; 70_000]).unwrap())
+            .await
+            .unwrap();
+        stream
+            .write_all(&codec.encode_frame(&[2; 100_001]).unwrap())
+            .await
+            .unwrap();
+    });
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut frames = FrameReader::new(stream, LengthPrefixedCodec::new(100_000));
+
+    let frame = frames.read_frame().await.unwrap();
+    assert_eq!(frame.map(|frame| frame.len()), Some(70_000));
+    assert!(matches!(
+        frames.read_frame().await,
+        Err(ExchangeError::FrameTooLarge(100_001, 100_000))
+    ));
+    sender.await.unwrap();
+}
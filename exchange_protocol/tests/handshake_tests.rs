@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use exchange_protocol::codecs::codec::CodecKind;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::*;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn capabilities(compressions: Vec<CompressionKind>) -> Capabilities {
+    Capabilities {
+        compressions,
+        auth_methods: vec![AuthMethod::Credentials, AuthMethod::Anonymous],
+    }
+}
+
+#[tokio::test]
+async fn test_handshake_negotiates_common_settings() {
+    let (mut client_stream, mut server_stream) = tokio::io::duplex(1024);
+    let client = Hello::new(
+        CodecKind::LengthPrefixed,
+        1_000,
+        capabilities(vec![CompressionKind::Deflate, CompressionKind::Zstd]),
+    );
+    let server = Hello {
+        capabilities: Capabilities {
+            compressions: vec![CompressionKind::Zstd],
+            auth_methods: vec![AuthMethod::Anonymous],
+        },
+        ..Hello::new(CodecKind::LengthPrefixed, 2_000, Capabilities::default())
+    };
+
+    let (client_info, server_info) = tokio::join!(
+        client_handshake(&mut client_stream, &client, TIMEOUT),
+        server_handshake(&mut server_stream, &server, TIMEOUT)
+    );
+
+    let expected = HandshakeInfo {
+        version: PROTOCOL_VERSION,
+        codec: CodecKind::LengthPrefixed,
+        max_frame_size: 1_000,
+        compression: Some(CompressionKind::Zstd),
+        auth_method: AuthMethod::Anonymous,
+    };
+    assert_eq!(client_info.unwrap(), expected);
+    assert_eq!(server_info.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_handshake_rejects_incompatible_peers() {
+    let (mut client_stream, mut server_stream) = tokio::io::duplex(1024);
+    let client = Hello {
+        min_version: PROTOCOL_VERSION + 1,
+        version: PROTOCOL_VERSION + 1,
+        ..Hello::new(CodecKind::JsonLines, 1_000, Capabilities::default())
+    };
+    let server = Hello::new(CodecKind::LengthPrefixed, 1_000, Capabilities::default());
+
+    let (client_info, server_info) = tokio::join!(
+        client_handshake(&mut client_stream, &client, TIMEOUT),
+        server_handshake(&mut server_stream, &server, TIMEOUT)
+    );
+    assert!(
+        matches!(client_info, Err(ExchangeError::HandshakeRejected(reason)) if reason.contains("protocol versions"))
+    );
+    assert!(matches!(
+        server_info,
+        Err(ExchangeError::HandshakeRejected(_))
+    ));
+
+    let codec_mismatch = Hello::new(CodecKind::JsonLines, 1_000, Capabilities::default());
+    assert!(server
+        .negotiate(&codec_mismatch)
+        .unwrap_err()
+        .contains("no common codec"));
+}
+
+#[tokio::test]
+async fn test_handshake_detects_foreign_peers() {
+    let (mut client_stream, mut server_stream) = tokio::io::duplex(1024);
+    let server = Hello::new(CodecKind::LengthPrefixed, 1_000, Capabilities::default());
+
+    tokio::io::AsyncWriteExt::write_all(&mut client_stream, b"GET / HTTP/1.1\r\n")
+        .await
+        .unwrap();
+    assert!(matches!(
+        server_handshake(&mut server_stream, &server, TIMEOUT).await,
+        Err(ExchangeError::HandshakeFailed(_))
+    ));
+
+    let silent = tokio::io::duplex(1024);
+    let mut silent_server = silent.1;
+    assert!(matches!(
+        server_handshake(&mut silent_server, &server, Duration::from_millis(20)).await,
+        Err(ExchangeError::HandshakeFailed(_))
+    ));
+
+    let hello = Hello::new(CodecKind::Cobs, 1_000, capabilities(vec![]));
+    assert_eq!(Hello::decode(&hello.encode()).unwrap(), hello);
+}
+
+#[test]
+fn test_control_frames_are_told_apart_from_payloads() {
+    use exchange_protocol::heartbeat::Control;
+
+    for control in [
+        Control::Ping,
+        Control::Pong,
+        Control::Shutdown,
+        Control::Overloaded,
+        Control::Closed,
+    ] {
+        assert_eq!(Control::decode(&control.encode()), Some(control));
+    }
+    let mut payload = Control::Ping.encode();
+    payload.push(0);
+    assert_eq!(Control::decode(&payload), None);
+    assert_eq!(Control::decode(b"ping"), None);
+}
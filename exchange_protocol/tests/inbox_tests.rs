@@ -0,0 +1,86 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use exchange_protocol::domain::{Message, NotifyMessage};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::inbox::Inbox;
+use exchange_protocol::limits::{Limits, OverflowPolicy};
+
+fn bytes(address: SocketAddr, payload: &[u8]) -> NotifyMessage {
+    let (reply_tx, _) = mpsc::channel(1);
+    NotifyMessage::new(Message::Bytes(payload.to_vec()), address, reply_tx)
+}
+
+fn payload(notify: NotifyMessage) -> Vec<u8> {
+    match notify.message {
+        Message::Bytes(bytes) => bytes,
+        message => panic!("unexpected {message:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_inbox_drops_the_oldest_payloads_of_a_flooding_peer() {
+    let flooder: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+    let peer: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+    // the application's channel holds one message, the forwarder another one
+    let (notifier_tx, mut notifier_rx) = mpsc::channel(1);
+    let limits = Limits::default()
+        .with_queue_size(2)
+        .with_overflow(OverflowPolicy::DropOldest);
+    let inbox = Inbox::start(limits, notifier_tx);
+
+    for payload in [b"1", b"2", b"3", b"4", b"5", b"6"] {
+        inbox.push(bytes(flooder, payload)).await.unwrap();
+        tokio::task::yield_now().await;
+    }
+    inbox.push(bytes(peer, b"a")).await.unwrap();
+    drop(inbox);
+
+    let mut received = vec![];
+    while let Some(notify) = notifier_rx.recv().await {
+        received.push((notify.address, payload(notify)));
+    }
+    assert_eq!(
+        received,
+        [
+            (flooder, b"1".to_vec()),
+            (flooder, b"2".to_vec()),
+            (flooder, b"5".to_vec()),
+            (peer, b"a".to_vec()),
+            (flooder, b"6".to_vec()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_inbox_blocks_or_refuses_a_full_peer() {
+    let address: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+    let (notifier_tx, mut notifier_rx) = mpsc::channel(1);
+    let inbox = Inbox::start(Limits::default().with_queue_size(1), notifier_tx);
+    inbox.push(bytes(address, b"1")).await.unwrap();
+    inbox.push(bytes(address, b"2")).await.unwrap();
+    inbox.push(bytes(address, b"3")).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), inbox.push(bytes(address, b"4")))
+            .await
+            .is_err()
+    );
+    assert_eq!(payload(notifier_rx.recv().await.unwrap()), b"1");
+    inbox.push(bytes(address, b"4")).await.unwrap();
+
+    let (notifier_tx, _notifier_rx) = mpsc::channel(1);
+    let limits = Limits::default()
+        .with_queue_size(1)
+        .with_overflow(OverflowPolicy::Disconnect);
+    let inbox = Inbox::start(limits, notifier_tx);
+    for payload in [b"1", b"2", b"3"] {
+        inbox.push(bytes(address, payload)).await.unwrap();
+        tokio::task::yield_now().await;
+    }
+    assert!(matches!(
+        inbox.push(bytes(address, b"4")).await,
+        Err(ExchangeError::QueueOverflow(peer, 1)) if peer == address
+    ));
+}
@@ -0,0 +1,37 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use exchange_protocol::metrics::{MetricsExporter, TransportMetrics};
+
+async fn get(exporter: &MetricsExporter, path: &str) -> String {
+    let mut stream = TcpStream::connect(exporter.address).await.unwrap();
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_scrape_shows_what_transports_counted() {
+    let metrics = TransportMetrics::new("test");
+    metrics.connected();
+    metrics.received(10);
+    metrics.sent(4);
+    metrics.decode_failed();
+    let exporter = MetricsExporter::start("127.0.0.1:0").await.unwrap();
+
+    let response = get(&exporter, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("exchange_connections_active{transport=\"test\"} 1"));
+    assert!(response.contains("exchange_bytes_total{direction=\"in\",transport=\"test\"} 10"));
+    assert!(response.contains("exchange_frames_total{direction=\"out\",transport=\"test\"} 1"));
+    assert!(response.contains("exchange_decode_failures_total{transport=\"test\"} 1"));
+
+    assert!(get(&exporter, "/")
+        .await
+        .starts_with("HTTP/1.1 404 Not Found"));
+
+    exporter.shutdown_handle().shutdown();
+    exporter.shutdown_handle().stopped().await;
+}
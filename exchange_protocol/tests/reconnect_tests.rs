@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use exchange_protocol::domain::DisconnectReason;
+use exchange_protocol::reconnect::Reconnect;
+
+#[test]
+fn test_delay_doubles_up_to_the_maximum() {
+    let reconnect =
+        Reconnect::new(Duration::from_millis(100), Duration::from_millis(500)).with_jitter(0.0);
+
+    let delays: Vec<_> = (1..=5).map(|attempt| reconnect.delay(attempt)).collect();
+    assert_eq!(
+        delays,
+        [100, 200, 400, 500, 500]
+            .map(Duration::from_millis)
+            .to_vec()
+    );
+}
+
+#[test]
+fn test_jitter_only_shortens_the_delay() {
+    let reconnect =
+        Reconnect::new(Duration::from_millis(100), Duration::from_secs(1)).with_jitter(0.5);
+
+    for _ in 0..100 {
+        let delay = reconnect.delay(2);
+        assert!(delay > Duration::from_millis(100) && delay <= Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn test_reconnect_gives_up_after_max_attempts() {
+    let reconnect = Reconnect::default().with_max_attempts(2);
+
+    assert!(!reconnect.gives_up(2));
+    assert!(reconnect.gives_up(3));
+    assert!(!Reconnect::default().gives_up(u32::MAX));
+    assert!(!reconnect.applies_to(&DisconnectReason::ClosedByServer));
+    assert!(reconnect.applies_to(&DisconnectReason::ServerShutdown));
+}
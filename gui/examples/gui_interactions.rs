@@ -3,6 +3,7 @@ use iced::alignment::Horizontal;
 use iced::{Alignment, Column, Element, Length, Sandbox, Settings, Text, Toggler};
 
 use homework::error::GuiError;
+use house::access::domain::{Credentials, UserName};
 use house::inventory::memory_device_inventory::MemoryDeviceInventory;
 use house_server::domain::DeviceData::PowerSocketState;
 use house_server::domain::RequestBody::{ChangeDeviceData, ShowDeviceInfo};
@@ -17,8 +18,9 @@ const UDP_SERVER_ADDRESS: &str = "127.0.0.1:45959";
 async fn main() -> Result<(), GuiError> {
     let inventory: MemoryDeviceInventory =
         house::mk_three_rooms_inventory(house::ThreeRoomNames::default());
+    let users = house::mk_three_rooms_users(house::ThreeRoomNames::default());
 
-    HouseServer::start(inventory, TCP_SERVER_ADDRESS, UDP_SERVER_ADDRESS).await?;
+    HouseServer::start(inventory, users, TCP_SERVER_ADDRESS, UDP_SERVER_ADDRESS).await?;
 
     ClientGUI::run(Settings::default())?;
 
@@ -48,6 +50,11 @@ impl Sandbox for ClientGUI {
             "127.0.0.1:41858",
         ))
         .unwrap();
+        block_on(client.authenticate(Credentials {
+            user_name: UserName("resident".to_string()),
+            password: "resident".to_string(),
+        }))
+        .unwrap();
 
         ClientGUI {
             client,
@@ -63,15 +70,15 @@ impl Sandbox for ClientGUI {
     fn update(&mut self, message: Message) {
         match message {
             Message::On => {
-                let response = block_on(self.client.send_and_receive(RequestMessage {
-                    body: ChangeDeviceData {
+                let response = block_on(self.client.send_and_receive(RequestMessage::new(
+                    ChangeDeviceData {
                         location: DeviceLocation {
                             room_name: "kitchen".to_string(),
                             device_name: "socket4".to_string(),
                         },
                         data: PowerSocketState { enabled: true },
                     },
-                }))
+                )))
                 .unwrap();
                 match response.body {
                     ResponseBody::DeviceDataChanged => self.refresh_info(true),
@@ -79,15 +86,15 @@ impl Sandbox for ClientGUI {
                 };
             }
             Message::Off => {
-                let response = block_on(self.client.send_and_receive(RequestMessage {
-                    body: ChangeDeviceData {
+                let response = block_on(self.client.send_and_receive(RequestMessage::new(
+                    ChangeDeviceData {
                         location: DeviceLocation {
                             room_name: "kitchen".to_string(),
                             device_name: "socket4".to_string(),
                         },
                         data: PowerSocketState { enabled: false },
                     },
-                }))
+                )))
                 .unwrap();
                 match response.body {
                     ResponseBody::DeviceDataChanged => self.refresh_info(false),
@@ -97,7 +104,7 @@ impl Sandbox for ClientGUI {
         }
     }
 
-    fn view(&mut self) -> Element<Message> {
+    fn view(&mut self) -> Element<'_, Message> {
         self.refresh_info(self.checked);
 
         Column::new()
@@ -122,14 +129,14 @@ impl Sandbox for ClientGUI {
 
 impl ClientGUI {
     fn refresh_info(&mut self, checked: bool) {
-        let response = block_on(self.client.send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
+        let response = block_on(self.client.send_and_receive(RequestMessage::new(
+            ShowDeviceInfo {
                 location: DeviceLocation {
                     room_name: "kitchen".to_string(),
                     device_name: "socket4".to_string(),
                 },
             },
-        }))
+        )))
         .unwrap();
         match response.body {
             ResponseBody::DeviceDescription(info) => {
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use derive_more::Display;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::intelligent_house_error::AccessError;
+use crate::errors::intelligent_house_error::AccessError::{AccessDenied, AccessRoomDenied};
+use crate::house::domain::RoomName;
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Display, Serialize, Deserialize)]
+pub struct UserName(pub String);
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Display, Serialize, Deserialize)]
+pub struct SessionToken(pub String);
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Display, Serialize, Deserialize)]
+pub enum Role {
+    Owner,
+    Resident,
+    Guest,
+    Service,
+}
+
+impl Role {
+    /// Actions allowed in every room, before any per-room grants.
+    pub fn actions(&self) -> &'static [Action] {
+        match self {
+            Role::Owner => &[Action::Read, Action::Control, Action::Configure],
+            Role::Resident => &[Action::Read, Action::Control],
+            Role::Guest => &[],
+            Role::Service => &[Action::Read, Action::Configure],
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Display, Serialize, Deserialize)]
+pub enum Action {
+    #[display(fmt = "read")]
+    Read,
+    #[display(fmt = "control")]
+    Control,
+    #[display(fmt = "configure")]
+    Configure,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: UserName,
+    pub role: Role,
+    pub rooms: HashMap<RoomName, Vec<Action>>,
+    pub password_hash: String,
+}
+
+impl User {
+    pub fn new(name: UserName, role: Role, password: &str) -> User {
+        User {
+            name,
+            role,
+            rooms: HashMap::new(),
+            password_hash: hash_password(password),
+        }
+    }
+
+    pub fn is_allowed(&self, room_name: Option<&RoomName>, action: Action) -> bool {
+        self.role.actions().contains(&action)
+            || room_name
+                .and_then(|room_name| self.rooms.get(room_name))
+                .is_some_and(|actions| actions.contains(&action))
+    }
+
+    /// Checks `action` on a room, or on the whole house when `room_name` is `None`.
+    pub fn check(&self, room_name: Option<&RoomName>, action: Action) -> Result<(), AccessError> {
+        match (self.is_allowed(room_name, action), room_name) {
+            (true, _) => Ok(()),
+            (false, Some(room_name)) => Err(AccessRoomDenied(
+                self.name.clone(),
+                action,
+                room_name.clone(),
+            )),
+            (false, None) => Err(AccessDenied(self.name.clone(), action)),
+        }
+    }
+
+    pub fn check_owner(&self) -> Result<(), AccessError> {
+        match self.role {
+            Role::Owner => Ok(()),
+            _ => Err(AccessDenied(self.name.clone(), Action::Configure)),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub user_name: UserName,
+    pub password: String,
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user_name", &self.user_name)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+/// Argon2id with a random salt, as a PHC string (`$argon2id$v=19$...`) that carries its
+/// parameters along.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>())
+        .expect("16 bytes are a valid salt");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("the default argon2 parameters hash any password")
+        .to_string()
+}
+
+/// Compares in constant time, a hash that can't be parsed matches no password.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub fn new_session_token() -> SessionToken {
+    SessionToken(to_hex(&rand::thread_rng().gen::<[u8; 32]>()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
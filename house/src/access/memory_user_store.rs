@@ -0,0 +1,80 @@
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use crate::access::domain::{Action, User, UserName};
+use crate::access::user_store::UserStore;
+use crate::errors::intelligent_house_error::AccessError;
+use crate::errors::intelligent_house_error::AccessError::*;
+use crate::house::domain::RoomName;
+
+#[derive(Default, Clone)]
+pub struct MemoryUserStore {
+    users: Arc<RwLock<HashMap<UserName, User>>>,
+}
+
+impl MemoryUserStore {
+    pub fn new(users: Vec<User>) -> MemoryUserStore {
+        let users = users
+            .into_iter()
+            .map(|user| (user.name.clone(), user))
+            .collect();
+        MemoryUserStore {
+            users: Arc::new(RwLock::new(users)),
+        }
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn get_users(&self) -> Result<Vec<User>, AccessError> {
+        Ok(self.users.read().values().cloned().collect())
+    }
+
+    async fn get_user(&self, user_name: &UserName) -> Result<User, AccessError> {
+        self.users
+            .read()
+            .get(user_name)
+            .cloned()
+            .ok_or_else(|| AccessUserNotFound(user_name.clone()))
+    }
+
+    async fn add_user(&self, user: User) -> Result<(), AccessError> {
+        match self.users.write().entry(user.name.clone()) {
+            Occupied(_) => Err(AccessUserAlreadyAdded(user.name)),
+            Vacant(entry) => {
+                entry.insert(user);
+                Ok(())
+            }
+        }
+    }
+
+    async fn remove_user(&self, user_name: &UserName) -> Result<(), AccessError> {
+        self.users
+            .write()
+            .remove(user_name)
+            .map(|_| ())
+            .ok_or_else(|| AccessUserNotFound(user_name.clone()))
+    }
+
+    async fn grant(
+        &self,
+        user_name: &UserName,
+        room_name: &RoomName,
+        actions: Vec<Action>,
+    ) -> Result<(), AccessError> {
+        let mut users = self.users.write();
+        let user = users
+            .get_mut(user_name)
+            .ok_or_else(|| AccessUserNotFound(user_name.clone()))?;
+        if actions.is_empty() {
+            user.rooms.remove(room_name);
+        } else {
+            user.rooms.insert(room_name.clone(), actions);
+        }
+        Ok(())
+    }
+}
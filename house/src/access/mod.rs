@@ -0,0 +1,5 @@
+pub mod domain;
+pub mod memory_user_store;
+pub mod rate_limiter;
+pub mod sessions;
+pub mod user_store;
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::access::domain::UserName;
+use crate::errors::intelligent_house_error::AccessError;
+use crate::errors::intelligent_house_error::AccessError::AccessThrottled;
+
+/// The buckets of the clients least recently seen are dropped beyond this many, a client
+/// coming back starts with a full one.
+const MAX_BUCKETS: usize = 4096;
+
+/// Up to `requests` at once, refilled evenly over `per`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    requests: u32,
+    per: Duration,
+}
+
+impl RateLimit {
+    /// Panics if `requests` or `per` is zero, such a limit lets nothing through or
+    /// never refills.
+    pub fn new(requests: u32, per: Duration) -> RateLimit {
+        assert!(
+            requests > 0,
+            "a rate limit lets at least one request through"
+        );
+        assert!(
+            !per.is_zero(),
+            "a rate limit is over a period longer than zero"
+        );
+        RateLimit { requests, per }
+    }
+
+    pub fn requests(&self) -> u32 {
+        self.requests
+    }
+
+    pub fn per(&self) -> Duration {
+        self.per
+    }
+
+    pub fn per_second(requests: u32) -> RateLimit {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(requests: u32) -> RateLimit {
+        Self::new(requests, Duration::from_secs(60))
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.requests as f64 / self.per.as_secs_f64()
+    }
+}
+
+/// Who the requests are counted for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// The host, whatever port the connection came from.
+    Address(IpAddr),
+    /// The user a client authenticated as.
+    User(UserName),
+    /// The user a local process runs as, local peers have no host of their own.
+    Process(u32),
+}
+
+/// Which requests are limited how. The most specific limit applies: one for the client
+/// and the request, then one for all requests of the client, one for the request and
+/// last the default. Without any, requests are not limited.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    default: Option<RateLimit>,
+    requests: HashMap<String, RateLimit>,
+    clients: HashMap<(Client, Option<String>), RateLimit>,
+}
+
+impl RateLimits {
+    /// Every client gets `limit` for all of its requests together.
+    pub fn new(limit: RateLimit) -> RateLimits {
+        RateLimits {
+            default: Some(limit),
+            ..RateLimits::default()
+        }
+    }
+
+    /// Every client gets `limit` for the requests of that type.
+    pub fn with_request(mut self, request: impl Into<String>, limit: RateLimit) -> RateLimits {
+        self.requests.insert(request.into(), limit);
+        self
+    }
+
+    /// The client gets `limit` for all of its requests together.
+    pub fn with_client(mut self, client: Client, limit: RateLimit) -> RateLimits {
+        self.clients.insert((client, None), limit);
+        self
+    }
+
+    pub fn with_client_request(
+        mut self,
+        client: Client,
+        request: impl Into<String>,
+        limit: RateLimit,
+    ) -> RateLimits {
+        self.clients.insert((client, Some(request.into())), limit);
+        self
+    }
+
+    fn find(&self, clients: &[Client], request: &str) -> Option<(BucketKey, RateLimit)> {
+        for client in clients {
+            for key in [
+                (client.clone(), Some(request.to_string())),
+                (client.clone(), None),
+            ] {
+                if let Some(limit) = self.clients.get(&key) {
+                    return Some((key, *limit));
+                }
+            }
+        }
+        let client = clients.first()?.clone();
+        match self.requests.get(request) {
+            Some(limit) => Some(((client, Some(request.to_string())), *limit)),
+            None => self.default.map(|limit| ((client, None), limit)),
+        }
+    }
+}
+
+type BucketKey = (Client, Option<String>);
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket was taken from last, in `Buckets::uses`.
+    last_use: u64,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Bucket {
+        Bucket {
+            limit,
+            tokens: limit.requests as f64,
+            updated: now,
+            last_use: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.refill_rate()).min(self.limit.requests as f64);
+        self.updated = now;
+    }
+
+    /// Returns how long until a request is let through otherwise.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.limit.refill_rate(),
+        ))
+    }
+}
+
+/// The buckets by client, least recently used dropped first once there are too many.
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<BucketKey, Bucket>,
+    by_use: BTreeMap<u64, BucketKey>,
+    uses: u64,
+}
+
+impl Buckets {
+    fn take(&mut self, key: BucketKey, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(&key) {
+            if let Some((_, least_used)) = self.by_use.pop_first() {
+                self.buckets.remove(&least_used);
+            }
+        }
+        self.uses += 1;
+        let bucket = self
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| Bucket::new(limit, now));
+        self.by_use.remove(&bucket.last_use);
+        bucket.last_use = self.uses;
+        self.by_use.insert(self.uses, key);
+        bucket.take(now)
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+/// Token buckets of the clients, shared by every connection of a server.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: Arc<RateLimits>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> RateLimiter {
+        RateLimiter {
+            limits: Arc::new(limits),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Counts a request of a client known as `clients`, the most specific first. A
+    /// throttled request tells when to try again.
+    pub fn check(&self, clients: &[Client], request: &str) -> Result<(), AccessError> {
+        let (key, limit) = match self.limits.find(clients, request) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        self.buckets
+            .lock()
+            .take(key, limit, Instant::now())
+            .map_err(AccessThrottled)
+    }
+
+    /// How many buckets are kept, one per client and limited request, a few thousand at most.
+    pub fn clients(&self) -> usize {
+        self.buckets.lock().len()
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+
+use crate::access::domain::{new_session_token, SessionToken, UserName};
+use crate::errors::intelligent_house_error::AccessError;
+use crate::errors::intelligent_house_error::AccessError::AccessUnauthenticated;
+
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+struct Session {
+    user_name: UserName,
+    last_used: Instant,
+}
+
+/// Bearer tokens of logged in users, kept in memory only. A token not used for the idle
+/// timeout expires, expired ones are dropped as new sessions open.
+#[derive(Clone)]
+pub struct Sessions {
+    tokens: Arc<RwLock<HashMap<SessionToken, Session>>>,
+    idle_timeout: Duration,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions::new(DEFAULT_SESSION_IDLE_TIMEOUT)
+    }
+}
+
+impl Sessions {
+    pub fn new(idle_timeout: Duration) -> Sessions {
+        Sessions {
+            tokens: Arc::default(),
+            idle_timeout,
+        }
+    }
+
+    pub fn open(&self, user_name: UserName) -> SessionToken {
+        let token = new_session_token();
+        let now = Instant::now();
+        let mut tokens = self.tokens.write();
+        tokens.retain(|_, session| now.duration_since(session.last_used) < self.idle_timeout);
+        tokens.insert(
+            token.clone(),
+            Session {
+                user_name,
+                last_used: now,
+            },
+        );
+        token
+    }
+
+    pub fn close(&self, token: &SessionToken) {
+        self.tokens.write().remove(token);
+    }
+
+    /// Drops every session of the user, e.g. after the account was removed.
+    pub fn close_user(&self, user_name: &UserName) {
+        self.tokens
+            .write()
+            .retain(|_, session| &session.user_name != user_name);
+    }
+
+    /// The user of a live session, using it keeps it alive.
+    pub fn get(&self, token: &SessionToken) -> Result<UserName, AccessError> {
+        let now = Instant::now();
+        let mut tokens = self.tokens.write();
+        match tokens.get_mut(token) {
+            Some(session) if now.duration_since(session.last_used) < self.idle_timeout => {
+                session.last_used = now;
+                Ok(session.user_name.clone())
+            }
+            Some(_) => {
+                tokens.remove(token);
+                Err(AccessUnauthenticated)
+            }
+            None => Err(AccessUnauthenticated),
+        }
+    }
+}
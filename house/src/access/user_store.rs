@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+
+use crate::access::domain::{verify_password, Action, Credentials, User, UserName};
+use crate::errors::intelligent_house_error::AccessError;
+use crate::errors::intelligent_house_error::AccessError::AccessInvalidCredentials;
+use crate::house::domain::RoomName;
+
+#[async_trait]
+pub trait UserStore {
+    async fn get_users(&self) -> Result<Vec<User>, AccessError>;
+
+    async fn get_user(&self, user_name: &UserName) -> Result<User, AccessError>;
+
+    async fn add_user(&self, user: User) -> Result<(), AccessError>;
+
+    async fn remove_user(&self, user_name: &UserName) -> Result<(), AccessError>;
+
+    /// Replaces the actions granted to the user in the room, an empty list revokes the grant.
+    async fn grant(
+        &self,
+        user_name: &UserName,
+        room_name: &RoomName,
+        actions: Vec<Action>,
+    ) -> Result<(), AccessError>;
+
+    async fn authenticate(&self, credentials: &Credentials) -> Result<User, AccessError> {
+        match self.get_user(&credentials.user_name).await {
+            Ok(user) if verify_password(&credentials.password, &user.password_hash) => Ok(user),
+            _ => Err(AccessInvalidCredentials(credentials.user_name.clone())),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use async_trait::async_trait;
+
+use crate::audit::domain::{AuditEntry, AuditFilter};
+use crate::errors::intelligent_house_error::AuditError;
+
+#[async_trait]
+pub trait AuditLog {
+    async fn record(&self, entry: AuditEntry) -> Result<(), AuditError>;
+
+    /// Matching entries, oldest first.
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError>;
+}
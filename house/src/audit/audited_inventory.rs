@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tracing::{debug, error};
+
+use crate::audit::audit_log::AuditLog;
+use crate::audit::domain::{
+    device_target, device_value, room_target, AuditContext, AuditEntry, AuditOperation,
+};
+use crate::errors::intelligent_house_error::InventoryError;
+use crate::errors::intelligent_house_error::InventoryError::InventoryAuditFailed;
+use crate::history::domain::Timestamp;
+use crate::house::domain::{DeviceName, RoomName};
+use crate::inventory::device_inventory::DeviceInventory;
+use crate::inventory::domain::{DeviceHealth, DeviceItem, RoomDevices};
+
+/// Inventory recording every successful mutation into the audit log on behalf of `context`.
+///
+/// Health reports are telemetry and are not audited. A mutation whose entry can't be
+/// recorded fails with `InventoryAuditFailed`, the change itself is made already.
+#[derive(Clone)]
+pub struct AuditedInventory<T: DeviceInventory, A: AuditLog> {
+    inventory: T,
+    audit: A,
+    context: AuditContext,
+}
+
+impl<T: DeviceInventory + Sync, A: AuditLog + Sync> AuditedInventory<T, A> {
+    pub fn new(inventory: T, audit: A, context: AuditContext) -> AuditedInventory<T, A> {
+        AuditedInventory {
+            inventory,
+            audit,
+            context,
+        }
+    }
+
+    async fn record(&self, entry: AuditEntry) -> Result<(), InventoryError> {
+        debug!(operation = ?entry.operation, target = %entry.target, "audited");
+        self.audit.record(entry).await.map_err(|error| {
+            error!(actor = %self.context.actor, %error, "change made but not audited");
+            InventoryAuditFailed(error.to_string())
+        })
+    }
+}
+
+#[async_trait]
+impl<T, A> DeviceInventory for AuditedInventory<T, A>
+where
+    T: DeviceInventory + Send + Sync,
+    A: AuditLog + Send + Sync,
+{
+    async fn get_info(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<String, InventoryError> {
+        self.inventory.get_info(room_name, device_name).await
+    }
+
+    async fn get_rooms(&self) -> Result<Vec<RoomName>, InventoryError> {
+        self.inventory.get_rooms().await
+    }
+
+    async fn add_room(&self, room_name: &RoomName) -> Result<(), InventoryError> {
+        self.inventory.add_room(room_name).await?;
+        self.record(
+            self.context
+                .entry(AuditOperation::AddRoom, room_target(room_name), None, None),
+        )
+        .await
+    }
+
+    async fn remove_room(&self, room_name: &RoomName) -> Result<(), InventoryError> {
+        self.inventory.remove_room(room_name).await?;
+        self.record(self.context.entry(
+            AuditOperation::RemoveRoom,
+            room_target(room_name),
+            None,
+            None,
+        ))
+        .await
+    }
+
+    async fn get_all_room_devices(&self) -> Result<Vec<RoomDevices>, InventoryError> {
+        self.inventory.get_all_room_devices().await
+    }
+
+    async fn add_device(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        device: DeviceItem,
+    ) -> Result<(), InventoryError> {
+        let after = device_value(device);
+        self.inventory
+            .add_device(room_name, device_name, device)
+            .await?;
+        self.record(self.context.entry(
+            AuditOperation::AddDevice,
+            device_target(room_name, device_name),
+            None,
+            Some(after),
+        ))
+        .await
+    }
+
+    async fn remove_device(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError> {
+        let before = self.inventory.get_device(room_name, device_name).await.ok();
+        self.inventory.remove_device(room_name, device_name).await?;
+        self.record(self.context.entry(
+            AuditOperation::RemoveDevice,
+            device_target(room_name, device_name),
+            before.map(device_value),
+            None,
+        ))
+        .await
+    }
+
+    async fn change_device(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        modify: impl Fn(DeviceItem) -> Result<DeviceItem, InventoryError> + Send,
+    ) -> Result<(), InventoryError> {
+        // the device as the inventory changes it, not as a concurrent change may leave it
+        let changes = Mutex::new(None);
+        let changed = &changes;
+        self.inventory
+            .change_device(room_name, device_name, move |before| {
+                let after = modify(before)?;
+                *changed.lock() = Some((before, after));
+                Ok(after)
+            })
+            .await?;
+        let changed = changes.into_inner();
+
+        if let Some((before, after)) = changed.filter(|(before, after)| before != after) {
+            self.record(self.context.entry(
+                AuditOperation::ChangeDevice,
+                device_target(room_name, device_name),
+                Some(device_value(before)),
+                Some(device_value(after)),
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_device(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<DeviceItem, InventoryError> {
+        self.inventory.get_device(room_name, device_name).await
+    }
+
+    async fn get_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<DeviceHealth, InventoryError> {
+        self.inventory.get_health(room_name, device_name).await
+    }
+
+    async fn report_seen(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+    ) -> Result<(), InventoryError> {
+        self.inventory
+            .report_seen(room_name, device_name, timestamp)
+            .await
+    }
+
+    async fn report_error(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError> {
+        self.inventory.report_error(room_name, device_name).await
+    }
+
+    async fn expire_health(
+        &self,
+        now: Timestamp,
+        timeout: Duration,
+    ) -> Result<Vec<(RoomName, DeviceName)>, InventoryError> {
+        self.inventory.expire_health(now, timeout).await
+    }
+}
@@ -0,0 +1,201 @@
+use std::net::SocketAddr;
+
+use frunk::hlist;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::access::domain::UserName;
+use crate::devices::power_socket::PowerSocket;
+use crate::devices::temperature_sensor::TemperatureSensor;
+use crate::errors::intelligent_house_error::AuditError;
+use crate::history::domain::{now_millis, Timestamp};
+use crate::house::domain::{DeviceName, RoomName};
+use crate::inventory::domain::DeviceItem;
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    Tcp,
+    Udp,
+    Unix,
+    WebSocket,
+    Web,
+    Ffi,
+    Automation,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    AddRoom,
+    RemoveRoom,
+    AddDevice,
+    RemoveDevice,
+    ChangeDevice,
+    AttachDevice,
+    DetachDevice,
+    AddUser,
+    RemoveUser,
+    GrantRoom,
+}
+
+/// Who performs the audited operations and where they come from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditContext {
+    pub actor: String,
+    pub source: AuditSource,
+    pub address: Option<SocketAddr>,
+    /// The request the operations are done for, as it shows in the traces.
+    pub trace_id: Option<String>,
+}
+
+impl AuditContext {
+    pub fn new(actor: impl Into<String>, source: AuditSource, address: Option<SocketAddr>) -> Self {
+        AuditContext {
+            actor: actor.into(),
+            source,
+            address,
+            trace_id: None,
+        }
+    }
+
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    pub fn entry(
+        &self,
+        operation: AuditOperation,
+        target: impl Into<String>,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> AuditEntry {
+        AuditEntry {
+            timestamp: now_millis(),
+            actor: self.actor.clone(),
+            source: self.source,
+            address: self.address,
+            trace_id: self.trace_id.clone(),
+            operation,
+            target: target.into(),
+            before,
+            after,
+        }
+    }
+}
+
+/// A random id in hex, like the span ids of W3C trace context.
+pub fn new_trace_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+pub const MAX_TRACE_ID_LENGTH: usize = 64;
+
+/// Ids from callers end up in logs and the audit trail, only plain ones are taken.
+pub fn is_valid_trace_id(trace_id: &str) -> bool {
+    !trace_id.is_empty()
+        && trace_id.len() <= MAX_TRACE_ID_LENGTH
+        && trace_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: Timestamp,
+    pub actor: String,
+    pub source: AuditSource,
+    pub address: Option<SocketAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    pub operation: AuditOperation,
+    /// `room`, `room/device` or `user:name`.
+    pub target: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub source: Option<AuditSource>,
+    pub operation: Option<AuditOperation>,
+    /// Matches the target itself and everything below it, e.g. `kitchen` matches `kitchen/socket1`.
+    pub target: Option<String>,
+    pub trace_id: Option<String>,
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor
+            .as_ref()
+            .is_none_or(|actor| &entry.actor == actor)
+            && self.source.is_none_or(|source| entry.source == source)
+            && self
+                .operation
+                .is_none_or(|operation| entry.operation == operation)
+            && self.target.as_ref().is_none_or(|target| {
+                entry.target == *target || entry.target.starts_with(&format!("{target}/"))
+            })
+            && self
+                .trace_id
+                .as_ref()
+                .is_none_or(|trace_id| entry.trace_id.as_ref() == Some(trace_id))
+            && self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp <= to)
+    }
+
+    /// Keeps the matching entries, the latest ones when a limit is set.
+    pub fn apply(&self, entries: impl IntoIterator<Item = AuditEntry>) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> =
+            entries.into_iter().filter(|e| self.matches(e)).collect();
+        if let Some(limit) = self.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        entries
+    }
+}
+
+pub fn room_target(room_name: &RoomName) -> String {
+    room_name.0.clone()
+}
+
+pub fn device_target(room_name: &RoomName, device_name: &DeviceName) -> String {
+    format!("{room_name}/{device_name}")
+}
+
+pub fn user_target(user_name: &UserName) -> String {
+    format!("user:{user_name}")
+}
+
+pub fn device_value(device: DeviceItem) -> Value {
+    device
+        .fold(hlist![
+            |ps: PowerSocket| serde_json::to_value(ps),
+            |ts: TemperatureSensor| serde_json::to_value(ts)
+        ])
+        .unwrap_or(Value::Null)
+}
+
+pub fn to_json_lines(entries: &[AuditEntry]) -> Result<String, AuditError> {
+    entries.iter().try_fold(String::new(), |mut lines, entry| {
+        lines.push_str(&serde_json::to_string(entry).map_err(AuditError::fmt)?);
+        lines.push('\n');
+        Ok(lines)
+    })
+}
+
+pub fn from_json_lines(lines: &str) -> Result<Vec<AuditEntry>, AuditError> {
+    lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|_| AuditError::AuditMalformedEntry(line.to_string()))
+        })
+        .collect()
+}
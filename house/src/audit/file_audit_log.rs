@@ -0,0 +1,93 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+
+use crate::audit::audit_log::AuditLog;
+use crate::audit::domain::{from_json_lines, to_json_lines, AuditEntry, AuditFilter};
+use crate::errors::intelligent_house_error::AuditError;
+
+/// Appends entries as JSON lines, each one synced to disk before `record` returns.
+///
+/// The file io blocks, within a tokio runtime it runs on the blocking threads. Outside
+/// of one (e.g. behind the FFI) it runs on the caller's thread.
+#[derive(Clone)]
+pub struct FileAuditLog {
+    path: Arc<PathBuf>,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl FileAuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> FileAuditLog {
+        FileAuditLog {
+            path: Arc::new(path.into()),
+            write_lock: Default::default(),
+        }
+    }
+
+    /// Creates the file and its directory if they are missing, a log that can't be
+    /// written fails here rather than with the first entry.
+    pub fn open(path: impl Into<PathBuf>) -> Result<FileAuditLog, AuditError> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(AuditError::fmt)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(AuditError::fmt)?;
+        Ok(FileAuditLog::new(path))
+    }
+
+    async fn blocking<R, F>(&self, io: F) -> Result<R, AuditError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Path, &Mutex<()>) -> Result<R, AuditError> + Send + 'static,
+    {
+        let path = self.path.clone();
+        let write_lock = self.write_lock.clone();
+        match Handle::try_current() {
+            Ok(runtime) => runtime
+                .spawn_blocking(move || io(&path, &write_lock))
+                .await
+                .map_err(AuditError::fmt)?,
+            Err(_) => io(&path, &write_lock),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLog for FileAuditLog {
+    async fn record(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        let line = to_json_lines(&[entry])?;
+
+        self.blocking(move |path, write_lock| {
+            let _guard = write_lock.lock();
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(AuditError::fmt)?;
+            file.write_all(line.as_bytes()).map_err(AuditError::fmt)?;
+            file.sync_data().map_err(AuditError::fmt)
+        })
+        .await
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        let lines = self
+            .blocking(|path, _| match std::fs::read_to_string(path) {
+                Ok(lines) => Ok(lines),
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(String::new()),
+                Err(error) => Err(AuditError::fmt(error)),
+            })
+            .await?;
+
+        Ok(filter.apply(from_json_lines(&lines)?))
+    }
+}
@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use crate::audit::audit_log::AuditLog;
+use crate::audit::domain::{AuditEntry, AuditFilter};
+use crate::errors::intelligent_house_error::AuditError;
+
+#[derive(Default, Clone)]
+pub struct MemoryAuditLog {
+    entries: Arc<RwLock<Vec<AuditEntry>>>,
+}
+
+#[async_trait]
+impl AuditLog for MemoryAuditLog {
+    async fn record(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        self.entries.write().push(entry);
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        Ok(filter.apply(self.entries.read().iter().cloned()))
+    }
+}
@@ -0,0 +1,5 @@
+pub mod audit_log;
+pub mod audited_inventory;
+pub mod domain;
+pub mod file_audit_log;
+pub mod memory_audit_log;
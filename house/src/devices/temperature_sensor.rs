@@ -12,9 +12,11 @@ pub struct TemperatureSensor {
 }
 
 impl TemperatureSensor {
+    /// Reads the temperature with a measurement error of up to `accuracy` degrees.
     pub fn current_temperature(&self) -> i32 {
         let mut rng: ThreadRng = rand::thread_rng();
-        rng.gen_range(self.range.min..self.range.max)
+        let error = rng.gen_range(-self.accuracy.abs()..=self.accuracy.abs());
+        (self.temperature + error).clamp(self.range.min, self.range.max)
     }
 }
 
@@ -1,10 +1,16 @@
 use derive_more::From;
 use serde::Serialize;
 use std::fmt::Debug;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::access::domain::{Action, UserName};
+use crate::errors::intelligent_house_error::AccessError::AccessInternalError;
+use crate::errors::intelligent_house_error::AuditError::AuditInternalError;
+use crate::errors::intelligent_house_error::HistoryError::HistoryInternalError;
 use crate::errors::intelligent_house_error::HouseError::HouseInternalError;
 use crate::errors::intelligent_house_error::InventoryError::InventoryInternalError;
+use crate::history::domain::Timestamp;
 use crate::{DeviceName, RoomName};
 
 #[derive(Error, Debug, Serialize, From)]
@@ -14,6 +20,15 @@ pub enum IntelligentHouseError {
 
     #[error("house error `{0}` raised")]
     HouseErr(HouseError),
+
+    #[error("history error `{0}` raised")]
+    HistoryErr(HistoryError),
+
+    #[error("access error `{0}` raised")]
+    AccessErr(AccessError),
+
+    #[error("audit error `{0}` raised")]
+    AuditErr(AuditError),
 }
 
 #[derive(Error, Debug, Serialize)]
@@ -21,6 +36,9 @@ pub enum InventoryError {
     #[error("inventory device `{0}` not found into room {1}")]
     InventoryDeviceNotFound(DeviceName, RoomName),
 
+    #[error("inventory device `{0}` into room {1} is offline, last seen at {2}")]
+    InventoryDeviceOffline(DeviceName, RoomName, Timestamp),
+
     #[error("inventory inappropriate device `{0}` for change")]
     InventoryDeviceInvalid(DeviceName, RoomName),
 
@@ -42,6 +60,9 @@ pub enum InventoryError {
     #[error("inventory device name `{0}` duplicated into room {1}")]
     InventoryDeviceNameDuplicated(DeviceName, RoomName),
 
+    #[error("inventory change was made but not audited: `{0}`")]
+    InventoryAuditFailed(String),
+
     #[error("inventory action failed with `{0}`")]
     InventoryInternalError(String),
 }
@@ -83,3 +104,84 @@ impl HouseError {
         HouseInternalError(format!("{0:?}", err))
     }
 }
+
+#[derive(Error, Debug, Serialize)]
+pub enum HistoryError {
+    #[error("history range {0}..{1} is invalid")]
+    HistoryInvalidRange(Timestamp, Timestamp),
+
+    #[error("history for device `{0}` into room {1} not found")]
+    HistorySeriesNotFound(DeviceName, RoomName),
+
+    #[error("history action failed with `{0}`")]
+    HistoryInternalError(String),
+}
+
+impl HistoryError {
+    pub fn str<E: AsRef<str>>(err: E) -> HistoryError {
+        HistoryInternalError(err.as_ref().to_string())
+    }
+
+    pub fn fmt<E: Debug>(err: E) -> HistoryError {
+        HistoryInternalError(format!("{0:?}", err))
+    }
+}
+
+#[derive(Error, Debug, Serialize)]
+pub enum AccessError {
+    #[error("user `{0}` not found")]
+    AccessUserNotFound(UserName),
+
+    #[error("user `{0}` already added")]
+    AccessUserAlreadyAdded(UserName),
+
+    #[error("invalid credentials for user `{0}`")]
+    AccessInvalidCredentials(UserName),
+
+    #[error("authentication required")]
+    AccessUnauthenticated,
+
+    #[error("user `{0}` is not allowed to {1} the house")]
+    AccessDenied(UserName, Action),
+
+    #[error("user `{0}` is not allowed to {1} room {2}")]
+    AccessRoomDenied(UserName, Action, RoomName),
+
+    #[error("too many requests, retry in {0:?}")]
+    AccessThrottled(Duration),
+
+    #[error("`{0}` is not allowed over an unencrypted channel")]
+    AccessInsecureChannel(String),
+
+    #[error("access action failed with `{0}`")]
+    AccessInternalError(String),
+}
+
+impl AccessError {
+    pub fn str<E: AsRef<str>>(err: E) -> AccessError {
+        AccessInternalError(err.as_ref().to_string())
+    }
+
+    pub fn fmt<E: Debug>(err: E) -> AccessError {
+        AccessInternalError(format!("{0:?}", err))
+    }
+}
+
+#[derive(Error, Debug, Serialize)]
+pub enum AuditError {
+    #[error("audit entry `{0}` is malformed")]
+    AuditMalformedEntry(String),
+
+    #[error("audit action failed with `{0}`")]
+    AuditInternalError(String),
+}
+
+impl AuditError {
+    pub fn str<E: AsRef<str>>(err: E) -> AuditError {
+        AuditInternalError(err.as_ref().to_string())
+    }
+
+    pub fn fmt<E: Debug>(err: E) -> AuditError {
+        AuditInternalError(format!("{0:?}", err))
+    }
+}
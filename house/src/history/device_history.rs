@@ -0,0 +1,25 @@
+use async_trait::async_trait;
+
+use crate::errors::intelligent_house_error::HistoryError;
+use crate::history::domain::{DeviceSeries, Timestamp};
+use crate::house::domain::*;
+use crate::inventory::domain::DeviceItem;
+
+#[async_trait]
+pub trait DeviceHistory {
+    async fn record(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+        device: DeviceItem,
+    ) -> Result<(), HistoryError>;
+
+    async fn get_series(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<DeviceSeries>, HistoryError>;
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use frunk_core::hlist;
+use serde::{Deserialize, Serialize};
+
+use crate::devices::power_socket::PowerSocket;
+use crate::devices::temperature_sensor::TemperatureSensor;
+use crate::house::domain::{DeviceName, RoomName};
+use crate::inventory::domain::DeviceItem;
+
+/// Milliseconds since the unix epoch.
+pub type Timestamp = u64;
+
+pub fn now_millis() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as Timestamp)
+        .unwrap_or_default()
+}
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Metric {
+    Temperature,
+    Power,
+    Enabled,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Resolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    pub fn bucket_millis(&self) -> u64 {
+        match self {
+            Resolution::Raw => 1,
+            Resolution::Minute => 60 * 1000,
+            Resolution::Hour => 60 * 60 * 1000,
+        }
+    }
+
+    pub fn bucket_start(&self, timestamp: Timestamp) -> Timestamp {
+        timestamp - timestamp % self.bucket_millis()
+    }
+}
+
+/// A single reading (`count == 1`) or an aggregate of readings inside one bucket.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub timestamp: Timestamp,
+    pub resolution: Resolution,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u32,
+}
+
+impl SeriesPoint {
+    pub fn raw(timestamp: Timestamp, value: f64) -> SeriesPoint {
+        SeriesPoint {
+            timestamp,
+            resolution: Resolution::Raw,
+            min: value,
+            max: value,
+            avg: value,
+            count: 1,
+        }
+    }
+
+    /// Whether any of the bucket's time lies in `from..=to`, an aggregate is stamped at
+    /// the start of its bucket.
+    pub fn overlaps(&self, from: Timestamp, to: Timestamp) -> bool {
+        let bucket_end = self
+            .timestamp
+            .saturating_add(self.resolution.bucket_millis());
+        self.timestamp <= to && bucket_end > from
+    }
+
+    pub fn merge(&mut self, other: &SeriesPoint) {
+        let count = self.count + other.count;
+        self.avg = (self.avg * self.count as f64 + other.avg * other.count as f64) / count as f64;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSeries {
+    pub room_name: RoomName,
+    pub device_name: DeviceName,
+    pub metric: Metric,
+    pub points: Vec<SeriesPoint>,
+}
+
+/// How long every resolution is kept before it is rolled up into the next one.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub raw: Duration,
+    pub minute: Duration,
+    pub hour: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            raw: Duration::from_secs(60 * 60),
+            minute: Duration::from_secs(24 * 60 * 60),
+            hour: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+pub fn device_readings(device: DeviceItem) -> Vec<(Metric, f64)> {
+    device.fold(hlist![
+        |ps: PowerSocket| vec![
+            (Metric::Power, ps.power() as f64),
+            (Metric::Enabled, if ps.enabled { 1.0 } else { 0.0 }),
+        ],
+        |ts: TemperatureSensor| vec![(Metric::Temperature, ts.current_temperature() as f64)]
+    ])
+}
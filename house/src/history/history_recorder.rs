@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::errors::intelligent_house_error::IntelligentHouseError;
+use crate::history::device_history::DeviceHistory;
+use crate::history::domain::Timestamp;
+use crate::inventory::device_inventory::DeviceInventory;
+use crate::inventory::domain::DeviceItem;
+use crate::simulation::clock::Clock;
+
+/// Periodically samples every inventory device into the history store.
+pub struct HistoryRecorder<T: DeviceInventory, H: DeviceHistory, C: Clock> {
+    inventory: T,
+    history: H,
+    clock: C,
+}
+
+impl<T, H, C> HistoryRecorder<T, H, C>
+where
+    T: DeviceInventory + Send + Sync + 'static,
+    H: DeviceHistory + Send + Sync + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    pub fn new(inventory: T, history: H, clock: C) -> HistoryRecorder<T, H, C> {
+        HistoryRecorder {
+            inventory,
+            history,
+            clock,
+        }
+    }
+
+    pub async fn record_once(&self, timestamp: Timestamp) -> Result<(), IntelligentHouseError> {
+        for room in self.inventory.get_all_room_devices().await? {
+            for (device_name, socket) in room.sockets {
+                self.history
+                    .record(
+                        &room.name,
+                        &device_name,
+                        timestamp,
+                        DeviceItem::inject(socket),
+                    )
+                    .await?;
+            }
+            for (device_name, sensor) in room.sensors {
+                self.history
+                    .record(
+                        &room.name,
+                        &device_name,
+                        timestamp,
+                        DeviceItem::inject(sensor),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.record_once(self.clock.now())
+                    .await
+                    .unwrap_or_else(|error| warn!(%error, "sampling device history failed"));
+                self.clock.sleep(period).await;
+            }
+        })
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use crate::errors::intelligent_house_error::HistoryError;
+use crate::errors::intelligent_house_error::HistoryError::*;
+use crate::history::device_history::DeviceHistory;
+use crate::history::domain::*;
+use crate::house::domain::*;
+use crate::inventory::domain::DeviceItem;
+
+type SeriesKey = (RoomName, DeviceName, Metric);
+
+#[derive(Default)]
+struct SeriesData {
+    latest: Timestamp,
+    raw: Vec<SeriesPoint>,
+    minute: Vec<SeriesPoint>,
+    hour: Vec<SeriesPoint>,
+}
+
+impl SeriesData {
+    fn push(&mut self, point: SeriesPoint, retention: &RetentionPolicy) {
+        let position = self.raw.partition_point(|p| p.timestamp <= point.timestamp);
+        self.raw.insert(position, point);
+        self.latest = self.latest.max(point.timestamp);
+        self.compact(retention);
+    }
+
+    fn compact(&mut self, retention: &RetentionPolicy) {
+        let raw_cutoff = self.latest.saturating_sub(retention.raw.as_millis() as u64);
+        roll_up(
+            &mut self.raw,
+            &mut self.minute,
+            Resolution::Minute,
+            raw_cutoff,
+        );
+
+        let minute_cutoff = self
+            .latest
+            .saturating_sub(retention.minute.as_millis() as u64);
+        roll_up(
+            &mut self.minute,
+            &mut self.hour,
+            Resolution::Hour,
+            minute_cutoff,
+        );
+
+        let hour_cutoff = self
+            .latest
+            .saturating_sub(retention.hour.as_millis() as u64);
+        let expired = self.hour.partition_point(|p| p.timestamp < hour_cutoff);
+        self.hour.drain(..expired);
+    }
+
+    fn points(&self, from: Timestamp, to: Timestamp) -> Vec<SeriesPoint> {
+        self.hour
+            .iter()
+            .chain(self.minute.iter())
+            .chain(self.raw.iter())
+            .filter(|p| p.overlaps(from, to))
+            .copied()
+            .collect()
+    }
+}
+
+/// Moves every point older than the bucket containing `cutoff` into `resolution` aggregates.
+/// Only whole buckets are rolled up, so a bucket never exists in two resolutions at once.
+fn roll_up(
+    source: &mut Vec<SeriesPoint>,
+    target: &mut Vec<SeriesPoint>,
+    resolution: Resolution,
+    cutoff: Timestamp,
+) {
+    let cutoff = resolution.bucket_start(cutoff);
+    let expired = source.partition_point(|p| p.timestamp < cutoff);
+
+    for point in source.drain(..expired) {
+        let bucket = resolution.bucket_start(point.timestamp);
+        match target.binary_search_by_key(&bucket, |p| p.timestamp) {
+            Ok(index) => target[index].merge(&point),
+            Err(index) => target.insert(
+                index,
+                SeriesPoint {
+                    timestamp: bucket,
+                    resolution,
+                    ..point
+                },
+            ),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct MemoryDeviceHistory {
+    retention: RetentionPolicy,
+    series: Arc<RwLock<HashMap<SeriesKey, SeriesData>>>,
+}
+
+impl MemoryDeviceHistory {
+    pub fn new(retention: RetentionPolicy) -> MemoryDeviceHistory {
+        MemoryDeviceHistory {
+            retention,
+            series: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl DeviceHistory for MemoryDeviceHistory {
+    async fn record(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+        device: DeviceItem,
+    ) -> Result<(), HistoryError> {
+        let mut series = self.series.write();
+        for (metric, value) in device_readings(device) {
+            series
+                .entry((room_name.clone(), device_name.clone(), metric))
+                .or_default()
+                .push(SeriesPoint::raw(timestamp, value), &self.retention);
+        }
+        Ok(())
+    }
+
+    async fn get_series(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<DeviceSeries>, HistoryError> {
+        if from > to {
+            return Err(HistoryInvalidRange(from, to));
+        }
+
+        let series = self.series.read();
+        let mut device_series: Vec<DeviceSeries> = series
+            .iter()
+            .filter(|((rn, dn, _), _)| rn == room_name && dn == device_name)
+            .map(|((_, _, metric), data)| DeviceSeries {
+                room_name: room_name.clone(),
+                device_name: device_name.clone(),
+                metric: *metric,
+                points: data.points(from, to),
+            })
+            .collect();
+
+        if device_series.is_empty() {
+            return Err(HistorySeriesNotFound(
+                device_name.clone(),
+                room_name.clone(),
+            ));
+        }
+
+        device_series.sort_by_key(|s| s.metric);
+        Ok(device_series)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod device_history;
+pub mod domain;
+pub mod history_recorder;
+pub mod memory_device_history;
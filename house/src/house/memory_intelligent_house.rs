@@ -10,6 +10,7 @@ use crate::errors::intelligent_house_error::HouseError::*;
 use crate::house::domain::{DeviceName, HouseName, Room, RoomName};
 use crate::house::intelligent_house::IntelligentHouse;
 use crate::inventory::device_inventory::DeviceInventory;
+use crate::inventory::domain::get_report_info;
 
 #[derive(Debug, Clone)]
 pub struct MemoryIntelligentHouse {
@@ -139,10 +140,8 @@ impl IntelligentHouse for MemoryIntelligentHouse {
                     .fold(
                         ("".to_string(), room_name.clone()),
                         |(acc_dev_info, rn), device_name| async move {
-                            let _dev_info: String = inventory
-                                .get_info(&rn, &device_name)
-                                .await
-                                .unwrap_or_else(|e| format!("{e}"));
+                            let _dev_info: String =
+                                get_report_info(inventory, &rn, &device_name).await;
 
                             (format!("{acc_dev_info}     {_dev_info}\n"), rn)
                         },
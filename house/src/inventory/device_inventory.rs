@@ -1,8 +1,11 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 
 use crate::errors::intelligent_house_error::InventoryError;
+use crate::history::domain::Timestamp;
 use crate::house::domain::*;
-use crate::inventory::domain::{DeviceItem, RoomDevices};
+use crate::inventory::domain::{DeviceHealth, DeviceItem, RoomDevices};
 
 #[async_trait]
 pub trait DeviceInventory {
@@ -45,4 +48,30 @@ pub trait DeviceInventory {
         room_name: &RoomName,
         device_name: &DeviceName,
     ) -> Result<DeviceItem, InventoryError>;
+
+    async fn get_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<DeviceHealth, InventoryError>;
+
+    async fn report_seen(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+    ) -> Result<(), InventoryError>;
+
+    async fn report_error(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError>;
+
+    /// Marks online devices not seen within `timeout` before `now` as offline and returns them.
+    async fn expire_health(
+        &self,
+        now: Timestamp,
+        timeout: Duration,
+    ) -> Result<Vec<(RoomName, DeviceName)>, InventoryError>;
 }
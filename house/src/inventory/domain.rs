@@ -3,10 +3,19 @@ use frunk::Coprod;
 use frunk_core::hlist;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use tracing::warn;
 
 use crate::devices::device_info::DeviceInfo;
 use crate::devices::power_socket::PowerSocket;
 use crate::devices::temperature_sensor::TemperatureSensor;
+use crate::errors::intelligent_house_error::InventoryError;
+use crate::errors::intelligent_house_error::InventoryError::{
+    InventoryAuditFailed, InventoryDeviceInvalid, InventoryDeviceNotFound, InventoryDeviceOffline,
+    InventoryRoomNotFound,
+};
+use crate::history::domain::Timestamp;
+use crate::inventory::device_inventory::DeviceInventory;
 
 pub type DeviceItem = Coprod!(PowerSocket, TemperatureSensor);
 
@@ -23,3 +32,91 @@ pub struct RoomDevices {
     pub sockets: HashMap<DeviceName, PowerSocket>,
     pub sensors: HashMap<DeviceName, TemperatureSensor>,
 }
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum DeviceStatus {
+    #[default]
+    Unknown,
+    Online,
+    Offline,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DeviceHealth {
+    pub status: DeviceStatus,
+    pub last_seen: Option<Timestamp>,
+    pub errors: u32,
+}
+
+impl Display for DeviceHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.status, self.last_seen) {
+            (DeviceStatus::Online, Some(ts)) => write!(f, "online, last seen at {ts}")?,
+            (DeviceStatus::Offline, Some(ts)) => write!(f, "offline, last seen at {ts}")?,
+            (status, _) => write!(f, "{}", format!("{status:?}").to_lowercase())?,
+        }
+        write!(f, ", errors={}", self.errors)
+    }
+}
+
+/// Fails with `InventoryDeviceOffline` when the device stopped reporting.
+pub async fn check_online<T: DeviceInventory + Sync>(
+    inventory: &T,
+    room_name: &RoomName,
+    device_name: &DeviceName,
+) -> Result<DeviceHealth, InventoryError> {
+    let health = inventory.get_health(room_name, device_name).await?;
+    match (health.status, health.last_seen) {
+        (DeviceStatus::Offline, Some(last_seen)) => Err(InventoryDeviceOffline(
+            device_name.clone(),
+            room_name.clone(),
+            last_seen,
+        )),
+        _ => Ok(health),
+    }
+}
+
+/// Counts an operation on the device that failed against its health. A device that is
+/// missing, offline or asked for what it can't do is not at fault.
+pub async fn report_failure<T: DeviceInventory + Sync>(
+    inventory: &T,
+    room_name: &RoomName,
+    device_name: &DeviceName,
+    error: &InventoryError,
+) {
+    if matches!(
+        error,
+        InventoryDeviceNotFound(..)
+            | InventoryRoomNotFound(..)
+            | InventoryDeviceOffline(..)
+            | InventoryDeviceInvalid(..)
+            | InventoryAuditFailed(..)
+    ) {
+        return;
+    }
+    inventory
+        .report_error(room_name, device_name)
+        .await
+        .unwrap_or_else(
+            |error| warn!(%room_name, %device_name, %error, "reporting device error failed"),
+        );
+}
+
+pub fn with_health(info: String, health: &DeviceHealth) -> String {
+    format!("{info}\n                - health={health}")
+}
+
+/// Device description with its health, as shown in house reports.
+pub async fn get_report_info<T: DeviceInventory + Sync>(
+    inventory: &T,
+    room_name: &RoomName,
+    device_name: &DeviceName,
+) -> String {
+    let info = inventory.get_info(room_name, device_name).await;
+    let health = inventory.get_health(room_name, device_name).await;
+    match (info, health) {
+        (Ok(info), Ok(health)) => with_health(info, &health),
+        (Ok(info), Err(_)) => info,
+        (Err(e), _) => format!("{e}"),
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::inventory::device_inventory::DeviceInventory;
+use crate::simulation::clock::Clock;
+
+/// Periodically marks devices that stopped reporting as offline.
+pub struct HealthWatchdog<T: DeviceInventory, C: Clock> {
+    inventory: T,
+    clock: C,
+    timeout: Duration,
+}
+
+impl<T, C> HealthWatchdog<T, C>
+where
+    T: DeviceInventory + Send + Sync + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    pub fn new(inventory: T, clock: C, timeout: Duration) -> HealthWatchdog<T, C> {
+        HealthWatchdog {
+            inventory,
+            clock,
+            timeout,
+        }
+    }
+
+    pub fn start(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self
+                    .inventory
+                    .expire_health(self.clock.now(), self.timeout)
+                    .await
+                {
+                    Ok(expired) => expired.iter().for_each(|(room_name, device_name)| {
+                        info!(room = %room_name, device = %device_name, "device is offline")
+                    }),
+                    Err(error) => warn!(%error, "expiring device health failed"),
+                }
+                self.clock.sleep(period).await;
+            }
+        })
+    }
+}
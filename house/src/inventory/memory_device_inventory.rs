@@ -1,6 +1,7 @@
 use std::collections::hash_map::Entry::Occupied;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -13,13 +14,15 @@ use crate::devices::power_socket::PowerSocket;
 use crate::devices::temperature_sensor::TemperatureSensor;
 use crate::errors::intelligent_house_error::InventoryError;
 use crate::errors::intelligent_house_error::InventoryError::*;
+use crate::history::domain::Timestamp;
 use crate::house::domain::*;
 use crate::inventory::device_inventory::DeviceInventory;
-use crate::inventory::domain::{DeviceItem, RoomDevices};
+use crate::inventory::domain::{DeviceHealth, DeviceItem, DeviceStatus, RoomDevices};
 
 #[derive(Default, Clone)]
 pub struct MemoryDeviceInventory {
     room_devices: Arc<RwLock<HashMap<RoomName, HashMap<DeviceName, DeviceItem>>>>,
+    device_health: Arc<RwLock<HashMap<(RoomName, DeviceName), DeviceHealth>>>,
 }
 
 impl MemoryDeviceInventory {
@@ -28,8 +31,40 @@ impl MemoryDeviceInventory {
     ) -> MemoryDeviceInventory {
         MemoryDeviceInventory {
             room_devices: Arc::new(RwLock::new(room_devices)),
+            device_health: Default::default(),
         }
     }
+
+    fn ensure_device(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError> {
+        match self.room_devices.read().get(room_name) {
+            Some(devices) if devices.contains_key(device_name) => Ok(()),
+            Some(_) => Err(InventoryDeviceNotFound(
+                device_name.clone(),
+                room_name.clone(),
+            )),
+            None => Err(InventoryRoomNotFound(room_name.clone())),
+        }
+    }
+
+    fn modify_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        modify: impl FnOnce(&mut DeviceHealth),
+    ) -> Result<(), InventoryError> {
+        self.ensure_device(room_name, device_name)?;
+        let mut device_health = self.device_health.write();
+        modify(
+            device_health
+                .entry((room_name.clone(), device_name.clone()))
+                .or_default(),
+        );
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -59,7 +94,7 @@ impl DeviceInventory for MemoryDeviceInventory {
 
     async fn get_rooms(&self) -> std::result::Result<Vec<RoomName>, InventoryError> {
         let room_devices = self.room_devices.read();
-        Ok(room_devices.iter().map(|(k, _)| k.clone()).collect())
+        Ok(room_devices.keys().cloned().collect())
     }
 
     async fn add_room(&self, room_name: &RoomName) -> Result<(), InventoryError> {
@@ -74,6 +109,9 @@ impl DeviceInventory for MemoryDeviceInventory {
     }
 
     async fn remove_room(&self, room_name: &RoomName) -> Result<(), InventoryError> {
+        self.device_health
+            .write()
+            .retain(|(rn, _), _| rn != room_name);
         self.room_devices
             .write()
             .remove(room_name)
@@ -82,7 +120,27 @@ impl DeviceInventory for MemoryDeviceInventory {
     }
 
     async fn get_all_room_devices(&self) -> Result<Vec<RoomDevices>, InventoryError> {
-        todo!()
+        let room_devices = self.room_devices.read();
+        Ok(room_devices
+            .iter()
+            .map(|(room_name, devices)| {
+                let mut room = RoomDevices {
+                    name: room_name.clone(),
+                    ..Default::default()
+                };
+                for (device_name, device) in devices {
+                    device.fold(hlist![
+                        |ps: PowerSocket| {
+                            room.sockets.insert(device_name.clone(), ps);
+                        },
+                        |ts: TemperatureSensor| {
+                            room.sensors.insert(device_name.clone(), ts);
+                        }
+                    ]);
+                }
+                room
+            })
+            .collect())
     }
 
     async fn add_device(
@@ -110,6 +168,9 @@ impl DeviceInventory for MemoryDeviceInventory {
         room_name: &RoomName,
         device_name: &DeviceName,
     ) -> Result<(), InventoryError> {
+        self.device_health
+            .write()
+            .remove(&(room_name.clone(), device_name.clone()));
         match self.room_devices.write().get_mut(room_name) {
             Some(devices) if devices.contains_key(device_name) => devices
                 .remove(device_name)
@@ -157,4 +218,54 @@ impl DeviceInventory for MemoryDeviceInventory {
             .copied()
             .ok_or_else(|| InventoryDeviceNotFound(device_name.clone(), room_name.clone()))
     }
+
+    async fn get_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<DeviceHealth, InventoryError> {
+        self.ensure_device(room_name, device_name)?;
+        Ok(self
+            .device_health
+            .read()
+            .get(&(room_name.clone(), device_name.clone()))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn report_seen(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+    ) -> Result<(), InventoryError> {
+        self.modify_health(room_name, device_name, |health| {
+            health.status = DeviceStatus::Online;
+            health.last_seen = health.last_seen.max(Some(timestamp));
+        })
+    }
+
+    async fn report_error(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError> {
+        self.modify_health(room_name, device_name, |health| health.errors += 1)
+    }
+
+    async fn expire_health(
+        &self,
+        now: Timestamp,
+        timeout: Duration,
+    ) -> Result<Vec<(RoomName, DeviceName)>, InventoryError> {
+        let deadline = now.saturating_sub(timeout.as_millis() as Timestamp);
+        let mut expired = Vec::new();
+        for (key, health) in self.device_health.write().iter_mut() {
+            if health.status == DeviceStatus::Online && health.last_seen < Some(deadline) {
+                health.status = DeviceStatus::Offline;
+                expired.push(key.clone());
+            }
+        }
+        Ok(expired)
+    }
 }
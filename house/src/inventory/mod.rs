@@ -1,3 +1,4 @@
 pub mod device_inventory;
 pub mod domain;
+pub mod health_watchdog;
 pub mod memory_device_inventory;
@@ -4,6 +4,8 @@ extern crate futures;
 
 use std::collections::HashMap;
 
+use crate::access::domain::{Action, Role, User, UserName};
+use crate::access::memory_user_store::MemoryUserStore;
 use crate::devices::power_socket::{PowerSocket, SocketType};
 use crate::devices::temperature_sensor::{SensorRange, TemperatureSensor};
 use crate::house::domain::*;
@@ -11,10 +13,14 @@ use crate::house::memory_intelligent_house::*;
 use crate::inventory::domain::DeviceItem;
 use crate::inventory::memory_device_inventory::MemoryDeviceInventory;
 
+pub mod access;
+pub mod audit;
 pub mod devices;
 pub mod errors;
+pub mod history;
 pub mod house;
 pub mod inventory;
+pub mod simulation;
 pub mod synchronizer;
 
 #[derive(Clone)]
@@ -125,3 +131,18 @@ pub fn mk_three_rooms_house(names: ThreeRoomNames) -> MemoryIntelligentHouse {
 
     MemoryIntelligentHouse::create("bachelor's house", vec![bedroom, kitchen, lounge])
 }
+
+/// Users named after their roles with the name as password, the guest may use the lounge only.
+pub fn mk_three_rooms_users(names: ThreeRoomNames) -> MemoryUserStore {
+    let mut guest = User::new(UserName("guest".to_string()), Role::Guest, "guest");
+    guest
+        .rooms
+        .insert(names.lounge, vec![Action::Read, Action::Control]);
+
+    MemoryUserStore::new(vec![
+        User::new(UserName("owner".to_string()), Role::Owner, "owner"),
+        User::new(UserName("resident".to_string()), Role::Resident, "resident"),
+        User::new(UserName("service".to_string()), Role::Service, "service"),
+        guest,
+    ])
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use crate::history::domain::{now_millis, Timestamp};
+
+/// Source of time for everything that samples or expires device data.
+#[async_trait]
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+
+    async fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        now_millis()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Clock that only moves when it is advanced, so sleepers wake up in simulated time.
+#[derive(Debug, Clone)]
+pub struct SimulatedClock {
+    time: Arc<watch::Sender<Timestamp>>,
+}
+
+impl SimulatedClock {
+    pub fn new(start: Timestamp) -> SimulatedClock {
+        let (time, _) = watch::channel(start);
+        SimulatedClock {
+            time: Arc::new(time),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.time
+            .send_modify(|now| *now += duration.as_millis() as Timestamp);
+    }
+}
+
+#[async_trait]
+impl Clock for SimulatedClock {
+    fn now(&self) -> Timestamp {
+        *self.time.borrow()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let wake_at = self.now() + duration.as_millis() as Timestamp;
+        let mut time = self.time.subscribe();
+        loop {
+            let now = *time.borrow();
+            if now >= wake_at || time.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
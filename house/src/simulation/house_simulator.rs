@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use frunk::hlist;
+use tokio::time::sleep;
+
+use crate::devices::temperature_sensor::TemperatureSensor;
+use crate::errors::intelligent_house_error::IntelligentHouseError;
+use crate::errors::intelligent_house_error::InventoryError::InventoryDeviceInvalid;
+use crate::house::domain::RoomName;
+use crate::inventory::device_inventory::DeviceInventory;
+use crate::inventory::domain::DeviceItem;
+use crate::simulation::clock::{Clock, SimulatedClock};
+use crate::simulation::thermal_model::ThermalModel;
+
+/// Drives the temperature sensors of an inventory from a thermal model in simulated time.
+pub struct HouseSimulator<T: DeviceInventory> {
+    inventory: T,
+    clock: SimulatedClock,
+    model: ThermalModel,
+}
+
+impl<T: DeviceInventory + Send + Sync> HouseSimulator<T> {
+    pub fn new(inventory: T, clock: SimulatedClock, model: ThermalModel) -> HouseSimulator<T> {
+        HouseSimulator {
+            inventory,
+            clock,
+            model,
+        }
+    }
+
+    pub fn model(&self) -> &ThermalModel {
+        &self.model
+    }
+
+    pub async fn step(&mut self, dt: Duration) -> Result<(), IntelligentHouseError> {
+        let rooms = self.inventory.get_all_room_devices().await?;
+
+        let heating: HashMap<RoomName, f64> = rooms
+            .iter()
+            .filter_map(|room| {
+                let climate = self.model.rooms.get(&room.name)?;
+                let power = climate
+                    .heaters
+                    .iter()
+                    .filter_map(|heater| room.sockets.get(heater))
+                    .map(|socket| socket.power() as f64)
+                    .sum();
+                Some((room.name.clone(), power))
+            })
+            .collect();
+
+        self.model.step(self.clock.now(), dt, &heating);
+        self.clock.advance(dt);
+
+        let now = self.clock.now();
+        for room in rooms {
+            let temperature = match self.model.temperature(&room.name) {
+                Some(temperature) => temperature.round() as i32,
+                None => continue,
+            };
+            for socket_name in room.sockets.keys() {
+                self.inventory
+                    .report_seen(&room.name, socket_name, now)
+                    .await?;
+            }
+            for sensor_name in room.sensors.keys() {
+                self.inventory
+                    .change_device(&room.name, sensor_name, |device| {
+                        device.fold(hlist![
+                            |_| Err(InventoryDeviceInvalid(
+                                sensor_name.clone(),
+                                room.name.clone()
+                            )),
+                            |mut ts: TemperatureSensor| {
+                                ts.temperature = temperature;
+                                Ok(DeviceItem::inject(ts))
+                            }
+                        ])
+                    })
+                    .await?;
+                self.inventory
+                    .report_seen(&room.name, sensor_name, now)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Simulates `duration` in steps of `step`, running `speedup` times faster than real time.
+    /// An infinite speedup only yields to other tasks between steps.
+    pub async fn run(
+        &mut self,
+        duration: Duration,
+        step: Duration,
+        speedup: f64,
+    ) -> Result<(), IntelligentHouseError> {
+        let pause = step.div_f64(speedup);
+        let mut simulated = Duration::ZERO;
+        while simulated < duration {
+            self.step(step).await?;
+            simulated += step;
+            if pause.is_zero() {
+                tokio::task::yield_now().await;
+            } else {
+                sleep(pause).await;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+pub mod clock;
+pub mod house_simulator;
+pub mod thermal_model;
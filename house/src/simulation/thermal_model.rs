@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use crate::history::domain::Timestamp;
+use crate::house::domain::{DeviceName, RoomName};
+
+const DAY_MILLIS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Outside temperature following a daily cycle: coldest at midnight, warmest at noon (UTC).
+#[derive(Debug, Clone, Copy)]
+pub struct OutsideClimate {
+    pub mean: f64,
+    pub daily_amplitude: f64,
+}
+
+impl OutsideClimate {
+    pub fn temperature_at(&self, timestamp: Timestamp) -> f64 {
+        let day_phase = (timestamp as f64 % DAY_MILLIS) / DAY_MILLIS;
+        self.mean - self.daily_amplitude * (2.0 * PI * day_phase).cos()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoomClimate {
+    pub temperature: f64,
+    /// Heat lost to the outside per kelvin of difference, W/K. Better insulated rooms have lower values.
+    pub heat_loss: f64,
+    /// Energy needed to warm the room by one kelvin, J/K.
+    pub heat_capacity: f64,
+    /// Power sockets of the room with heaters plugged in.
+    pub heaters: Vec<DeviceName>,
+}
+
+impl RoomClimate {
+    pub fn new(temperature: f64, heaters: Vec<DeviceName>) -> RoomClimate {
+        RoomClimate {
+            temperature,
+            heat_loss: 100.0,
+            heat_capacity: 2_000_000.0,
+            heaters,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThermalModel {
+    pub outside: OutsideClimate,
+    pub rooms: HashMap<RoomName, RoomClimate>,
+    /// Pairs of rooms sharing a wall with its conductance, W/K.
+    pub adjacency: Vec<(RoomName, RoomName, f64)>,
+}
+
+impl ThermalModel {
+    pub fn new(outside: OutsideClimate) -> ThermalModel {
+        ThermalModel {
+            outside,
+            rooms: HashMap::new(),
+            adjacency: Vec::new(),
+        }
+    }
+
+    pub fn add_room(&mut self, room_name: RoomName, climate: RoomClimate) {
+        self.rooms.insert(room_name, climate);
+    }
+
+    pub fn add_adjacency(&mut self, first: RoomName, second: RoomName, conductance: f64) {
+        self.adjacency.push((first, second, conductance));
+    }
+
+    pub fn temperature(&self, room_name: &RoomName) -> Option<f64> {
+        self.rooms.get(room_name).map(|room| room.temperature)
+    }
+
+    /// Advances every room by `dt` with explicit Euler integration.
+    /// `heating` holds the power in watts delivered by the heaters of each room.
+    pub fn step(&mut self, timestamp: Timestamp, dt: Duration, heating: &HashMap<RoomName, f64>) {
+        let outside = self.outside.temperature_at(timestamp);
+
+        let mut heat_flows: HashMap<RoomName, f64> = self
+            .rooms
+            .iter()
+            .map(|(room_name, room)| {
+                let heaters = heating.get(room_name).copied().unwrap_or_default();
+                let flow = room.heat_loss * (outside - room.temperature) + heaters;
+                (room_name.clone(), flow)
+            })
+            .collect();
+
+        for (first, second, conductance) in &self.adjacency {
+            if let (Some(a), Some(b)) = (self.rooms.get(first), self.rooms.get(second)) {
+                let flow = conductance * (b.temperature - a.temperature);
+                heat_flows.entry(first.clone()).and_modify(|f| *f += flow);
+                heat_flows.entry(second.clone()).and_modify(|f| *f -= flow);
+            }
+        }
+
+        let seconds = dt.as_secs_f64();
+        for (room_name, room) in self.rooms.iter_mut() {
+            let flow = heat_flows.get(room_name).copied().unwrap_or_default();
+            room.temperature += flow * seconds / room.heat_capacity;
+        }
+    }
+}
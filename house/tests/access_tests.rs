@@ -0,0 +1,229 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use house::access::domain::{hash_password, verify_password, Action, Credentials, Role, UserName};
+use house::access::rate_limiter::{Client, RateLimit, RateLimiter, RateLimits};
+use house::access::sessions::Sessions;
+use house::access::user_store::UserStore;
+use house::errors::intelligent_house_error::AccessError;
+use house::house::domain::RoomName;
+use house::ThreeRoomNames;
+
+fn credentials(name: &str, password: &str) -> Credentials {
+    Credentials {
+        user_name: UserName(name.to_string()),
+        password: password.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_authenticate() {
+    let users = house::mk_three_rooms_users(ThreeRoomNames::default());
+
+    let owner = users
+        .authenticate(&credentials("owner", "owner"))
+        .await
+        .unwrap();
+    assert_eq!(owner.role, Role::Owner);
+    assert_ne!(owner.password_hash, "owner");
+
+    assert!(matches!(
+        users.authenticate(&credentials("owner", "guest")).await,
+        Err(AccessError::AccessInvalidCredentials(_))
+    ));
+    assert!(matches!(
+        users.authenticate(&credentials("nobody", "owner")).await,
+        Err(AccessError::AccessInvalidCredentials(_))
+    ));
+}
+
+#[test]
+fn test_password_hashes() {
+    let hash = hash_password("secret");
+    assert!(hash.starts_with("$argon2id$"));
+    assert_ne!(hash, hash_password("secret"));
+    assert!(verify_password("secret", &hash));
+    assert!(!verify_password("Secret", &hash));
+
+    // neither a plain digest nor garbage matches anything
+    let digest = "00112233445566778899aabbccddeeff$2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+    assert!(!verify_password("secret", digest));
+    assert!(!verify_password("", ""));
+}
+
+#[tokio::test]
+async fn test_role_permissions() {
+    let names = ThreeRoomNames::default();
+    let users = house::mk_three_rooms_users(names.clone());
+    let user = |name: &str| {
+        let users = users.clone();
+        let name = UserName(name.to_string());
+        async move { users.get_user(&name).await.unwrap() }
+    };
+    let kitchen = Some(&names.kitchen);
+
+    let owner = user("owner").await;
+    assert!(owner.check(None, Action::Configure).is_ok());
+
+    let resident = user("resident").await;
+    assert!(resident.check(kitchen, Action::Control).is_ok());
+    assert!(matches!(
+        resident.check(kitchen, Action::Configure),
+        Err(AccessError::AccessRoomDenied(_, Action::Configure, _))
+    ));
+
+    let service = user("service").await;
+    assert!(service.check(kitchen, Action::Configure).is_ok());
+    assert!(service.check(kitchen, Action::Control).is_err());
+    assert!(service.check_owner().is_err());
+
+    let guest = user("guest").await;
+    assert!(guest.check(Some(&names.lounge), Action::Control).is_ok());
+    assert!(guest.check(kitchen, Action::Read).is_err());
+    assert!(matches!(
+        guest.check(None, Action::Read),
+        Err(AccessError::AccessDenied(_, Action::Read))
+    ));
+}
+
+#[tokio::test]
+async fn test_grant_and_revoke_room() {
+    let users = house::mk_three_rooms_users(ThreeRoomNames::default());
+    let guest = UserName("guest".to_string());
+    let garage = RoomName("garage".to_string());
+
+    users
+        .grant(&guest, &garage, vec![Action::Read])
+        .await
+        .unwrap();
+    let user = users.get_user(&guest).await.unwrap();
+    assert!(user.check(Some(&garage), Action::Read).is_ok());
+    assert!(user.check(Some(&garage), Action::Control).is_err());
+
+    users.grant(&guest, &garage, vec![]).await.unwrap();
+    let user = users.get_user(&guest).await.unwrap();
+    assert!(user.check(Some(&garage), Action::Read).is_err());
+
+    assert!(matches!(
+        users
+            .grant(&UserName("nobody".to_string()), &garage, vec![])
+            .await,
+        Err(AccessError::AccessUserNotFound(_))
+    ));
+}
+
+#[test]
+fn test_sessions() {
+    let sessions = Sessions::default();
+    let owner = UserName("owner".to_string());
+
+    let first = sessions.open(owner.clone());
+    let second = sessions.open(owner.clone());
+    assert_ne!(first, second);
+    assert_eq!(sessions.get(&first).unwrap(), owner);
+
+    sessions.close(&first);
+    assert!(matches!(
+        sessions.get(&first),
+        Err(AccessError::AccessUnauthenticated)
+    ));
+    assert!(sessions.get(&second).is_ok());
+
+    sessions.close_user(&owner);
+    assert!(sessions.get(&second).is_err());
+}
+
+#[test]
+fn test_idle_sessions_expire() {
+    let sessions = Sessions::new(Duration::from_millis(100));
+    let owner = UserName("owner".to_string());
+    let busy = sessions.open(owner.clone());
+    let idle = sessions.open(owner.clone());
+
+    for _ in 0..3 {
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(sessions.get(&busy).is_ok());
+    }
+    assert!(matches!(
+        sessions.get(&idle),
+        Err(AccessError::AccessUnauthenticated)
+    ));
+    assert_eq!(sessions.get(&busy).unwrap(), owner);
+}
+
+#[test]
+fn test_rate_limits_most_specific_first() {
+    let host = [Client::Address(IpAddr::from([127, 0, 0, 1]))];
+    let owner = Client::User(UserName("owner".to_string()));
+    let limiter = RateLimiter::new(
+        RateLimits::new(RateLimit::per_minute(3))
+            .with_request("ChangeDeviceData", RateLimit::per_minute(1))
+            .with_client(owner.clone(), RateLimit::per_minute(5)),
+    );
+
+    assert!(limiter.check(&host, "ChangeDeviceData").is_ok());
+    assert!(matches!(
+        limiter.check(&host, "ChangeDeviceData"),
+        Err(AccessError::AccessThrottled(retry_after)) if retry_after > Duration::from_secs(50)
+    ));
+    // the other requests of the host have a bucket of their own
+    for _ in 0..3 {
+        assert!(limiter.check(&host, "ShowDeviceInfo").is_ok());
+    }
+    assert!(limiter.check(&host, "ShowDeviceInfo").is_err());
+
+    // the owner is counted by name, not by the host it shares
+    let clients = [owner, host[0].clone()];
+    for _ in 0..5 {
+        assert!(limiter.check(&clients, "ChangeDeviceData").is_ok());
+    }
+    assert!(limiter.check(&clients, "ShowDeviceInfo").is_err());
+}
+
+#[test]
+fn test_rate_limit_refills() {
+    let host = [Client::Address(IpAddr::from([127, 0, 0, 1]))];
+    let limiter = RateLimiter::new(RateLimits::new(RateLimit::new(
+        2,
+        Duration::from_millis(100),
+    )));
+    assert!(limiter.check(&host, "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host, "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host, "ShowDeviceInfo").is_err());
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(limiter.check(&host, "ShowDeviceInfo").is_ok());
+    assert!(RateLimiter::default()
+        .check(&host, "ShowDeviceInfo")
+        .is_ok());
+}
+
+#[test]
+fn test_least_recently_seen_clients_are_dropped() {
+    let limiter = RateLimiter::new(RateLimits::new(RateLimit::per_minute(1)));
+    let host = |n: u32| [Client::Address(IpAddr::from(n.to_be_bytes()))];
+    assert!(limiter.check(&host(0), "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host(1), "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host(0), "ShowDeviceInfo").is_err());
+
+    for n in 2..5000 {
+        assert!(limiter.check(&host(n), "ShowDeviceInfo").is_ok());
+    }
+    assert_eq!(limiter.clients(), 4096);
+    // both dropped, the throttled one too as it was seen before the others
+    assert!(limiter.check(&host(1), "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host(0), "ShowDeviceInfo").is_ok());
+    assert!(limiter.check(&host(4999), "ShowDeviceInfo").is_err());
+}
+
+#[test]
+#[should_panic]
+fn test_rate_limit_without_requests_is_refused() {
+    RateLimit::per_minute(0);
+}
+
+#[test]
+#[should_panic]
+fn test_rate_limit_without_period_is_refused() {
+    RateLimit::new(1, Duration::ZERO);
+}
@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use frunk::hlist;
+use serde_json::json;
+
+use house::audit::audit_log::AuditLog;
+use house::audit::audited_inventory::AuditedInventory;
+use house::audit::domain::*;
+use house::audit::file_audit_log::FileAuditLog;
+use house::audit::memory_audit_log::MemoryAuditLog;
+use house::devices::power_socket::PowerSocket;
+use house::errors::intelligent_house_error::{AuditError, InventoryError};
+use house::house::domain::*;
+use house::inventory::device_inventory::DeviceInventory;
+use house::inventory::domain::DeviceItem;
+use house::ThreeRoomNames;
+
+fn switch(enabled: bool) -> impl Fn(DeviceItem) -> Result<DeviceItem, InventoryError> + Send {
+    move |device| {
+        device.fold(hlist![
+            |mut ps: PowerSocket| {
+                ps.enabled = enabled;
+                Ok(DeviceItem::inject(ps))
+            },
+            |ts| Ok(DeviceItem::inject(ts))
+        ])
+    }
+}
+
+#[tokio::test]
+async fn test_audited_inventory_records_changes() {
+    let names = ThreeRoomNames::default();
+    let audit = MemoryAuditLog::default();
+    let context = AuditContext::new("owner", AuditSource::Tcp, "127.0.0.1:4000".parse().ok());
+    let inventory = AuditedInventory::new(
+        house::mk_three_rooms_inventory(names.clone()),
+        audit.clone(),
+        context,
+    );
+
+    inventory
+        .change_device(&names.kitchen, &names.socket4, switch(false))
+        .await
+        .unwrap();
+    inventory
+        .change_device(&names.kitchen, &names.socket4, switch(false))
+        .await
+        .unwrap();
+    inventory
+        .remove_device(&names.lounge, &names.socket2)
+        .await
+        .unwrap();
+
+    let entries = audit.query(&AuditFilter::default()).await.unwrap();
+    assert_eq!(entries.len(), 2, "unchanged devices are not audited");
+
+    let change = &entries[0];
+    assert_eq!(change.actor, "owner");
+    assert_eq!(change.source, AuditSource::Tcp);
+    assert_eq!(change.address, "127.0.0.1:4000".parse().ok());
+    assert_eq!(change.operation, AuditOperation::ChangeDevice);
+    assert_eq!(change.target, "kitchen/socket4");
+    assert_eq!(change.before.as_ref().unwrap()["enabled"], json!(true));
+    assert_eq!(change.after.as_ref().unwrap()["enabled"], json!(false));
+
+    let removal = &entries[1];
+    assert_eq!(removal.operation, AuditOperation::RemoveDevice);
+    assert!(removal.before.is_some());
+    assert!(removal.after.is_none());
+}
+
+/// A log on a full disk.
+struct FailingAuditLog;
+
+#[async_trait]
+impl AuditLog for FailingAuditLog {
+    async fn record(&self, _: AuditEntry) -> Result<(), AuditError> {
+        Err(AuditError::str("no space left on device"))
+    }
+
+    async fn query(&self, _: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        Ok(vec![])
+    }
+}
+
+#[tokio::test]
+async fn test_unaudited_changes_fail() {
+    let names = ThreeRoomNames::default();
+    let inventory = AuditedInventory::new(
+        house::mk_three_rooms_inventory(names.clone()),
+        FailingAuditLog,
+        AuditContext::new("owner", AuditSource::Tcp, None),
+    );
+
+    assert!(matches!(
+        inventory
+            .change_device(&names.kitchen, &names.socket4, switch(false))
+            .await,
+        Err(InventoryError::InventoryAuditFailed(_))
+    ));
+    assert!(matches!(
+        inventory.remove_device(&names.lounge, &names.socket2).await,
+        Err(InventoryError::InventoryAuditFailed(_))
+    ));
+    // reading needs no entry
+    assert!(inventory
+        .get_device(&names.kitchen, &names.socket4)
+        .await
+        .is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_changes_are_audited_as_made() {
+    let names = ThreeRoomNames::default();
+    let audit = MemoryAuditLog::default();
+    let inventory = AuditedInventory::new(
+        house::mk_three_rooms_inventory(names.clone()),
+        audit.clone(),
+        AuditContext::new("owner", AuditSource::Tcp, None),
+    );
+    let toggle = |device: DeviceItem| {
+        device.fold(hlist![
+            |mut ps: PowerSocket| {
+                ps.enabled = !ps.enabled;
+                Ok(DeviceItem::inject(ps))
+            },
+            |ts| Ok(DeviceItem::inject(ts))
+        ])
+    };
+
+    let changes = (0..32).map(|_| {
+        let inventory = inventory.clone();
+        let names = names.clone();
+        tokio::spawn(async move {
+            inventory
+                .change_device(&names.kitchen, &names.socket4, toggle)
+                .await
+        })
+    });
+    for change in changes.collect::<Vec<_>>() {
+        change.await.unwrap().unwrap();
+    }
+
+    // every toggle is a change, with the state it was made from
+    let entries = audit.query(&AuditFilter::default()).await.unwrap();
+    assert_eq!(entries.len(), 32);
+    for entry in entries {
+        let enabled = |value: Option<serde_json::Value>| value.unwrap()["enabled"].clone();
+        assert_ne!(enabled(entry.before), enabled(entry.after));
+    }
+}
+
+#[test]
+fn test_audit_filter() {
+    let context = AuditContext::new("guest", AuditSource::Web, None);
+    let kitchen = RoomName("kitchen".to_string());
+    let kitchenette = RoomName("kitchenette".to_string());
+    let socket = DeviceName("socket".to_string());
+    let entries = vec![
+        context.entry(AuditOperation::AddRoom, room_target(&kitchen), None, None),
+        context.entry(
+            AuditOperation::AddDevice,
+            device_target(&kitchen, &socket),
+            None,
+            None,
+        ),
+        context.entry(
+            AuditOperation::AddDevice,
+            device_target(&kitchenette, &socket),
+            None,
+            None,
+        ),
+    ];
+
+    let kitchen_filter = AuditFilter {
+        target: Some("kitchen".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(kitchen_filter.apply(entries.clone()).len(), 2);
+
+    let latest_device = AuditFilter {
+        operation: Some(AuditOperation::AddDevice),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let found = latest_device.apply(entries.clone());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].target, "kitchenette/socket");
+
+    let other_actor = AuditFilter {
+        actor: Some("owner".to_string()),
+        ..Default::default()
+    };
+    assert!(other_actor.apply(entries).is_empty());
+}
+
+#[test]
+fn test_audit_entries_keep_the_trace_id() {
+    let kitchen = RoomName("kitchen".to_string());
+    let traced = AuditContext::new("owner", AuditSource::Tcp, None).with_trace_id("5f0c9a1e");
+    let untraced = AuditContext::new("owner", AuditSource::Tcp, None);
+    let entries = vec![
+        traced.entry(AuditOperation::AddRoom, room_target(&kitchen), None, None),
+        untraced.entry(
+            AuditOperation::RemoveRoom,
+            room_target(&kitchen),
+            None,
+            None,
+        ),
+    ];
+    assert_eq!(entries[0].trace_id.as_deref(), Some("5f0c9a1e"));
+
+    let by_trace = AuditFilter {
+        trace_id: Some("5f0c9a1e".to_string()),
+        ..Default::default()
+    };
+    let found = by_trace.apply(entries.clone());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].operation, AuditOperation::AddRoom);
+
+    // entries of untraced operations stay as they were in the log
+    let lines = to_json_lines(&entries).unwrap();
+    assert!(!lines.lines().nth(1).unwrap().contains("trace_id"));
+    assert_eq!(from_json_lines(&lines).unwrap(), entries);
+}
+
+#[test]
+fn test_trace_ids_from_callers_are_checked() {
+    assert!(is_valid_trace_id(&new_trace_id()));
+    assert!(is_valid_trace_id("4bf92f35-77b3"));
+    assert!(!is_valid_trace_id(""));
+    assert!(!is_valid_trace_id("id\nforged log line"));
+    assert!(!is_valid_trace_id(&"a".repeat(MAX_TRACE_ID_LENGTH + 1)));
+}
+
+#[tokio::test]
+async fn test_file_audit_log() {
+    let path = std::env::temp_dir().join(format!("audit_tests_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let audit = FileAuditLog::new(&path);
+    assert!(audit
+        .query(&AuditFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+
+    let context = AuditContext::new("ffi", AuditSource::Ffi, None);
+    let room_name = RoomName("garage".to_string());
+    audit
+        .record(context.entry(AuditOperation::AddRoom, room_target(&room_name), None, None))
+        .await
+        .unwrap();
+    audit
+        .record(context.entry(
+            AuditOperation::RemoveRoom,
+            room_target(&room_name),
+            None,
+            None,
+        ))
+        .await
+        .unwrap();
+
+    let reopened = FileAuditLog::new(&path);
+    let entries = reopened.query(&AuditFilter::default()).await.unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let lines = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(lines, to_json_lines(&entries).unwrap());
+    assert_eq!(from_json_lines(&lines).unwrap(), entries);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_file_audit_log_opens_or_fails() {
+    let dir = std::env::temp_dir().join(format!("audit_tests_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let path = dir.join("logs").join("audit.jsonl");
+    assert!(FileAuditLog::open(&path).is_ok());
+    assert!(path.is_file());
+
+    // a file where the directory should be
+    assert!(matches!(
+        FileAuditLog::open(path.join("audit.jsonl")),
+        Err(AuditError::AuditInternalError(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
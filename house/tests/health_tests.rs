@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use house::devices::power_socket::{PowerSocket, SocketType};
+use house::errors::intelligent_house_error::InventoryError;
+use house::house::domain::*;
+use house::inventory::device_inventory::DeviceInventory;
+use house::inventory::domain::{
+    check_online, report_failure, DeviceHealth, DeviceItem, DeviceStatus,
+};
+use house::inventory::memory_device_inventory::MemoryDeviceInventory;
+
+fn inventory() -> (MemoryDeviceInventory, RoomName, DeviceName) {
+    let room_name = RoomName("kitchen".to_string());
+    let device_name = DeviceName("socket1".to_string());
+    let inventory = MemoryDeviceInventory::new(HashMap::from([(
+        room_name.clone(),
+        HashMap::from([(
+            device_name.clone(),
+            DeviceItem::inject(PowerSocket {
+                tpe: SocketType::C,
+                voltage: 220,
+                current: 5,
+                enabled: true,
+            }),
+        )]),
+    )]));
+    (inventory, room_name, device_name)
+}
+
+#[tokio::test]
+async fn test_device_goes_offline() {
+    let (inventory, room_name, device_name) = inventory();
+
+    let health = inventory
+        .get_health(&room_name, &device_name)
+        .await
+        .unwrap();
+    assert_eq!(health, DeviceHealth::default());
+
+    inventory
+        .report_seen(&room_name, &device_name, 1_000)
+        .await
+        .unwrap();
+    let expired = inventory
+        .expire_health(20_000, Duration::from_secs(30))
+        .await
+        .unwrap();
+    assert!(expired.is_empty());
+    assert!(check_online(&inventory, &room_name, &device_name)
+        .await
+        .is_ok());
+
+    let expired = inventory
+        .expire_health(40_000, Duration::from_secs(30))
+        .await
+        .unwrap();
+    assert_eq!(expired, vec![(room_name.clone(), device_name.clone())]);
+
+    let health = inventory
+        .get_health(&room_name, &device_name)
+        .await
+        .unwrap();
+    assert_eq!(health.status, DeviceStatus::Offline);
+    assert_eq!(health.last_seen, Some(1_000));
+    assert!(matches!(
+        check_online(&inventory, &room_name, &device_name).await,
+        Err(InventoryError::InventoryDeviceOffline(_, _, 1_000))
+    ));
+
+    inventory
+        .report_seen(&room_name, &device_name, 41_000)
+        .await
+        .unwrap();
+    let health = check_online(&inventory, &room_name, &device_name)
+        .await
+        .unwrap();
+    assert_eq!(health.status, DeviceStatus::Online);
+}
+
+#[tokio::test]
+async fn test_missing_device_is_not_offline() {
+    let (inventory, room_name, _) = inventory();
+    let missing = DeviceName("missing".to_string());
+
+    let result = check_online(&inventory, &room_name, &missing).await;
+    assert!(matches!(
+        result,
+        Err(InventoryError::InventoryDeviceNotFound(_, _))
+    ));
+}
+
+#[tokio::test]
+async fn test_device_errors_are_counted() {
+    let (inventory, room_name, device_name) = inventory();
+
+    inventory
+        .report_error(&room_name, &device_name)
+        .await
+        .unwrap();
+    inventory
+        .report_error(&room_name, &device_name)
+        .await
+        .unwrap();
+
+    let health = inventory
+        .get_health(&room_name, &device_name)
+        .await
+        .unwrap();
+    assert_eq!(health.errors, 2);
+}
+
+#[tokio::test]
+async fn test_only_device_faults_are_counted() {
+    let (inventory, room_name, device_name) = inventory();
+
+    let missing = InventoryError::InventoryDeviceNotFound(device_name.clone(), room_name.clone());
+    report_failure(&inventory, &room_name, &device_name, &missing).await;
+    let failed = InventoryError::str("socket did not answer");
+    report_failure(&inventory, &room_name, &device_name, &failed).await;
+
+    let health = inventory
+        .get_health(&room_name, &device_name)
+        .await
+        .unwrap();
+    assert_eq!(health.errors, 1);
+}
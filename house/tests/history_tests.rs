@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use house::devices::power_socket::{PowerSocket, SocketType};
+use house::errors::intelligent_house_error::HistoryError;
+use house::history::device_history::DeviceHistory;
+use house::history::domain::*;
+use house::history::memory_device_history::MemoryDeviceHistory;
+use house::house::domain::*;
+use house::inventory::domain::DeviceItem;
+
+const MINUTE: u64 = 60 * 1000;
+const HOUR: u64 = 60 * MINUTE;
+
+fn socket(current: u32, enabled: bool) -> DeviceItem {
+    DeviceItem::inject(PowerSocket {
+        tpe: SocketType::C,
+        voltage: 100,
+        current,
+        enabled,
+    })
+}
+
+fn names() -> (RoomName, DeviceName) {
+    (
+        RoomName("kitchen".to_string()),
+        DeviceName("socket1".to_string()),
+    )
+}
+
+async fn power_points(
+    history: &MemoryDeviceHistory,
+    from: Timestamp,
+    to: Timestamp,
+) -> Vec<SeriesPoint> {
+    let (room_name, device_name) = names();
+    history
+        .get_series(&room_name, &device_name, from, to)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|s| s.metric == Metric::Power)
+        .unwrap()
+        .points
+}
+
+#[tokio::test]
+async fn test_history_range_query() {
+    let (room_name, device_name) = names();
+    let history = MemoryDeviceHistory::default();
+
+    for (i, current) in [1, 2, 3, 4].into_iter().enumerate() {
+        history
+            .record(
+                &room_name,
+                &device_name,
+                1000 * i as u64,
+                socket(current, true),
+            )
+            .await
+            .unwrap();
+    }
+
+    let series = history
+        .get_series(&room_name, &device_name, 1000, 2000)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        series.iter().map(|s| s.metric).collect::<Vec<_>>(),
+        vec![Metric::Power, Metric::Enabled]
+    );
+    assert_eq!(
+        series[0].points,
+        vec![SeriesPoint::raw(1000, 200.0), SeriesPoint::raw(2000, 300.0)]
+    );
+}
+
+#[tokio::test]
+async fn test_history_downsampling() {
+    let (room_name, device_name) = names();
+    let history = MemoryDeviceHistory::new(RetentionPolicy {
+        raw: Duration::from_secs(60),
+        minute: Duration::from_secs(60 * 60),
+        hour: Duration::from_secs(24 * 60 * 60),
+    });
+
+    history
+        .record(&room_name, &device_name, 0, socket(1, true))
+        .await
+        .unwrap();
+    history
+        .record(&room_name, &device_name, 30 * 1000, socket(3, true))
+        .await
+        .unwrap();
+    history
+        .record(&room_name, &device_name, 2 * MINUTE + 1, socket(5, true))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        power_points(&history, 0, 3 * MINUTE).await,
+        vec![
+            SeriesPoint {
+                timestamp: 0,
+                resolution: Resolution::Minute,
+                min: 100.0,
+                max: 300.0,
+                avg: 200.0,
+                count: 2,
+            },
+            SeriesPoint::raw(2 * MINUTE + 1, 500.0),
+        ]
+    );
+
+    history
+        .record(&room_name, &device_name, 2 * HOUR, socket(7, false))
+        .await
+        .unwrap();
+
+    let points = power_points(&history, 0, 3 * HOUR).await;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].resolution, Resolution::Hour);
+    assert_eq!(points[0].count, 3);
+    assert_eq!(points[0].max, 500.0);
+    assert_eq!(points[1], SeriesPoint::raw(2 * HOUR, 0.0));
+}
+
+#[tokio::test]
+async fn test_history_range_starts_inside_a_bucket() {
+    let (room_name, device_name) = names();
+    let history = MemoryDeviceHistory::new(RetentionPolicy {
+        raw: Duration::from_secs(60),
+        minute: Duration::from_secs(60 * 60),
+        hour: Duration::from_secs(24 * 60 * 60),
+    });
+
+    for (timestamp, current) in [(10 * 1000, 1), (40 * 1000, 3), (3 * MINUTE, 5)] {
+        history
+            .record(&room_name, &device_name, timestamp, socket(current, true))
+            .await
+            .unwrap();
+    }
+
+    // the minute bucket starts at 0, before the range, but ends inside it
+    let points = power_points(&history, 30 * 1000, 3 * MINUTE).await;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].timestamp, 0);
+    assert_eq!(points[0].resolution, Resolution::Minute);
+    assert_eq!(points[0].count, 2);
+    assert_eq!(points[1], SeriesPoint::raw(3 * MINUTE, 500.0));
+
+    // a range after the bucket ended leaves it out
+    assert_eq!(
+        power_points(&history, MINUTE, 3 * MINUTE).await,
+        vec![SeriesPoint::raw(3 * MINUTE, 500.0)]
+    );
+}
+
+#[tokio::test]
+async fn test_history_retention() {
+    let (room_name, device_name) = names();
+    let history = MemoryDeviceHistory::new(RetentionPolicy {
+        raw: Duration::from_secs(60),
+        minute: Duration::from_secs(60 * 60),
+        hour: Duration::from_secs(2 * 60 * 60),
+    });
+
+    history
+        .record(&room_name, &device_name, 0, socket(1, true))
+        .await
+        .unwrap();
+    history
+        .record(&room_name, &device_name, 5 * HOUR, socket(2, true))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        power_points(&history, 0, 6 * HOUR).await,
+        vec![SeriesPoint::raw(5 * HOUR, 200.0)]
+    );
+}
+
+#[tokio::test]
+async fn test_history_errors() {
+    let (room_name, device_name) = names();
+    let history = MemoryDeviceHistory::default();
+
+    assert!(matches!(
+        history.get_series(&room_name, &device_name, 0, 10).await,
+        Err(HistoryError::HistorySeriesNotFound(..))
+    ));
+    assert!(matches!(
+        history.get_series(&room_name, &device_name, 10, 0).await,
+        Err(HistoryError::HistoryInvalidRange(10, 0))
+    ));
+}
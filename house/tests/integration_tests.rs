@@ -172,9 +172,11 @@ async fn test_house_report() {
                 - current=5
                 - enabled=true
                 - power=1100
+                - health=unknown, errors=0
      TS 'sensor1' specification:
                 - 26C° 
                 - range 10-40C° 
-                - accuracy 1"
+                - accuracy 1
+                - health=unknown, errors=0"
     );
 }
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use house::devices::power_socket::{PowerSocket, SocketType};
+use house::devices::temperature_sensor::{SensorRange, TemperatureSensor};
+use house::house::domain::*;
+use house::inventory::device_inventory::DeviceInventory;
+use house::inventory::domain::DeviceItem;
+use house::inventory::memory_device_inventory::MemoryDeviceInventory;
+use house::simulation::clock::{Clock, SimulatedClock};
+use house::simulation::house_simulator::HouseSimulator;
+use house::simulation::thermal_model::{OutsideClimate, RoomClimate, ThermalModel};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+fn sensor(temperature: i32) -> DeviceItem {
+    DeviceItem::inject(TemperatureSensor {
+        temperature,
+        range: SensorRange { min: -50, max: 100 },
+        accuracy: 0,
+    })
+}
+
+#[tokio::test]
+async fn test_simulated_clock_sleep() {
+    let clock = SimulatedClock::new(0);
+
+    let sleeper = clock.clone();
+    let wake = tokio::spawn(async move {
+        sleeper.sleep(HOUR).await;
+        sleeper.now()
+    });
+    tokio::task::yield_now().await;
+
+    clock.advance(HOUR / 2);
+    tokio::task::yield_now().await;
+    assert!(!wake.is_finished());
+
+    clock.advance(HOUR / 2);
+    assert_eq!(wake.await.unwrap(), 60 * 60 * 1000);
+}
+
+#[test]
+fn test_thermal_model_equilibrium() {
+    let kitchen = RoomName("kitchen".to_string());
+    let mut model = ThermalModel::new(OutsideClimate {
+        mean: 0.0,
+        daily_amplitude: 0.0,
+    });
+    model.add_room(kitchen.clone(), RoomClimate::new(20.0, vec![]));
+
+    let heating = HashMap::from([(kitchen.clone(), 1000.0)]);
+    for _ in 0..24 * 30 {
+        model.step(0, HOUR, &heating);
+    }
+
+    let temperature = model.temperature(&kitchen).unwrap();
+    assert!((temperature - 10.0).abs() < 0.1, "{temperature}");
+}
+
+#[tokio::test]
+async fn test_simulator_drives_sensors() {
+    let kitchen = RoomName("kitchen".to_string());
+    let bedroom = RoomName("bedroom".to_string());
+    let heater = DeviceName("heater".to_string());
+    let sensor_name = DeviceName("sensor".to_string());
+
+    let inventory = MemoryDeviceInventory::new(HashMap::from([
+        (
+            kitchen.clone(),
+            HashMap::from([
+                (
+                    heater.clone(),
+                    DeviceItem::inject(PowerSocket {
+                        tpe: SocketType::C,
+                        voltage: 200,
+                        current: 10,
+                        enabled: true,
+                    }),
+                ),
+                (sensor_name.clone(), sensor(10)),
+            ]),
+        ),
+        (
+            bedroom.clone(),
+            HashMap::from([(sensor_name.clone(), sensor(10))]),
+        ),
+    ]));
+
+    let mut model = ThermalModel::new(OutsideClimate {
+        mean: 10.0,
+        daily_amplitude: 0.0,
+    });
+    model.add_room(kitchen.clone(), RoomClimate::new(10.0, vec![heater]));
+    model.add_room(bedroom.clone(), RoomClimate::new(10.0, vec![]));
+    model.add_adjacency(kitchen.clone(), bedroom.clone(), 50.0);
+
+    let clock = SimulatedClock::new(0);
+    let mut simulator = HouseSimulator::new(inventory.clone(), clock.clone(), model);
+    simulator
+        .run(24 * HOUR, Duration::from_secs(60), f64::INFINITY)
+        .await
+        .unwrap();
+
+    assert_eq!(clock.now(), 24 * 60 * 60 * 1000);
+
+    let read = |room_name: RoomName| {
+        let inventory = inventory.clone();
+        let sensor_name = sensor_name.clone();
+        async move {
+            let device = inventory
+                .get_device(&room_name, &sensor_name)
+                .await
+                .unwrap();
+            device.get::<TemperatureSensor, _>().unwrap().temperature
+        }
+    };
+    let kitchen_temperature = read(kitchen).await;
+    let bedroom_temperature = read(bedroom).await;
+
+    assert!(kitchen_temperature > bedroom_temperature);
+    assert!(bedroom_temperature > 10);
+}
@@ -148,7 +148,7 @@ impl<'a> TryFrom<&'a RoomName> for RawRoomName {
     type Error = anyhow::Error;
 
     fn try_from(name: &'a RoomName) -> Result<Self, Self::Error> {
-        let cs = CString::new(name.0.as_str().as_bytes())?;
+        let cs = CString::new(name.0.as_bytes())?;
         let raw = cs.into_raw();
         Ok(RawRoomName(raw))
         /*let x = rn.0.as_str();
@@ -163,7 +163,7 @@ impl<'a> TryFrom<&'a DeviceName> for RawDeviceName {
     type Error = anyhow::Error;
 
     fn try_from(name: &'a DeviceName) -> Result<Self, Self::Error> {
-        let cs = CString::new(name.0.as_str().as_bytes())?;
+        let cs = CString::new(name.0.as_bytes())?;
         let raw = cs.into_raw();
         Ok(RawDeviceName(raw))
         /* let rn_bytes = rn.0.as_str().as_bytes();
@@ -1,30 +1,40 @@
 use frunk_core::hlist;
 use futures::executor::block_on;
+use house::audit::audited_inventory::AuditedInventory;
+use house::audit::domain::{AuditContext, AuditSource};
+use house::audit::file_audit_log::FileAuditLog;
 use house::devices::power_socket::{PowerSocket, SocketType};
 use house::house::domain::{DeviceName, RoomName};
 use house::inventory::device_inventory::DeviceInventory;
 use house::inventory::domain::DeviceItem;
 use house::inventory::memory_device_inventory::MemoryDeviceInventory;
 use std::collections::HashMap;
+use std::env;
 use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::PathBuf;
+
+/// The audit log in the user's data directory, `HOUSE_AUDIT_LOG` names another file.
+const AUDIT_LOG_FILE: &str = "house_audit.jsonl";
+
+type FfiInventory = AuditedInventory<MemoryDeviceInventory, FileAuditLog>;
 
 #[repr(transparent)]
 #[derive(Debug)]
 struct InventoryHandle(*mut c_void);
 impl InventoryHandle {
-    pub unsafe fn as_inventory(&self) -> &'static mut MemoryDeviceInventory {
-        let ptr = self.0 as *mut MemoryDeviceInventory;
+    pub unsafe fn as_inventory(&self) -> &'static mut FfiInventory {
+        let ptr = self.0 as *mut FfiInventory;
         ptr.as_mut().unwrap()
     }
 
-    pub fn from_inventory(inventory: MemoryDeviceInventory) -> Self {
+    pub fn from_inventory(inventory: FfiInventory) -> Self {
         let reference = Box::leak(Box::new(inventory));
-        let ptr = reference as *mut MemoryDeviceInventory;
+        let ptr = reference as *mut FfiInventory;
         Self(ptr as _)
     }
 
-    pub unsafe fn into_inventory(self) -> Box<MemoryDeviceInventory> {
-        let ptr = self.0 as *mut MemoryDeviceInventory;
+    pub unsafe fn into_inventory(self) -> Box<FfiInventory> {
+        let ptr = self.0 as *mut FfiInventory;
         Box::from_raw(ptr)
     }
 }
@@ -95,7 +105,7 @@ enum InventoryError {
     Encoding,*/
     Parameter,
     /*Unsupported,*/
-    InventoryError,
+    InternalError,
 }
 
 type CreateMemoryInventoryFn = unsafe extern "C" fn(*mut InventoryHandle) -> InventoryError;
@@ -151,12 +161,40 @@ unsafe extern "C" fn create_inventory(handle: *mut InventoryHandle) -> Inventory
     )]);
 
     let inventory: MemoryDeviceInventory = MemoryDeviceInventory::new(power_sockets);
+    let audit = match audit_log_path().map(FileAuditLog::open) {
+        Some(Ok(audit)) => audit,
+        Some(Err(error)) => {
+            eprintln!("house audit log can't be opened: {error}");
+            return InventoryError::InternalError;
+        }
+        None => {
+            eprintln!("house audit log has no place, set HOUSE_AUDIT_LOG");
+            return InventoryError::InternalError;
+        }
+    };
+    let inventory = AuditedInventory::new(
+        inventory,
+        audit,
+        AuditContext::new("ffi", AuditSource::Ffi, None),
+    );
 
     *handle = InventoryHandle::from_inventory(inventory);
 
     InventoryError::NoError
 }
 
+/// Kept across restarts, unlike the temp directory the OS cleans up.
+fn audit_log_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("HOUSE_AUDIT_LOG") {
+        return Some(path.into());
+    }
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(data_dir.join("house").join(AUDIT_LOG_FILE))
+}
+
 unsafe extern "C" fn switch_socket(
     room: RawRoomName,
     device: RawDeviceName,
@@ -192,7 +230,7 @@ unsafe extern "C" fn switch_socket(
         ])
     })) {
         Ok(_) => InventoryError::NoError,
-        Err(_) => InventoryError::InventoryError,
+        Err(_) => InventoryError::InternalError,
     }
 }
 
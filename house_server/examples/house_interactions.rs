@@ -1,7 +1,9 @@
 use std::time::Duration;
 
+use house::access::domain::{Credentials, UserName};
 use house::inventory::memory_device_inventory::MemoryDeviceInventory;
 use tokio::time::sleep;
+use tracing_subscriber::EnvFilter;
 
 use house_server::domain::DeviceData::*;
 use house_server::domain::RequestBody::{
@@ -15,13 +17,19 @@ use house_server::house_server::HouseServer;
 
 #[tokio::main]
 async fn main() -> Result<(), HouseExchangeError> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let tcp_server_address = "127.0.0.1:45932";
     let udp_server_address = "127.0.0.1:45959";
 
     let room_device_names = house::ThreeRoomNames::default();
-    let inventory: MemoryDeviceInventory = house::mk_three_rooms_inventory(room_device_names);
+    let inventory: MemoryDeviceInventory =
+        house::mk_three_rooms_inventory(room_device_names.clone());
+    let users = house::mk_three_rooms_users(room_device_names);
 
-    HouseServer::start(inventory, tcp_server_address, udp_server_address).await?;
+    let server =
+        HouseServer::start(inventory, users, tcp_server_address, udp_server_address).await?;
 
     sleep(Duration::from_secs(2)).await;
 
@@ -33,15 +41,20 @@ async fn main() -> Result<(), HouseExchangeError> {
     )
     .await?;
 
+    client
+        .authenticate(Credentials {
+            user_name: UserName("owner".to_string()),
+            password: "owner".to_string(),
+        })
+        .await?;
+
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
 
     println!(
@@ -50,15 +63,13 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ChangeDeviceData {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
-                data: PowerSocketState { enabled: false },
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+            data: PowerSocketState { enabled: false },
+        }))
         .await?;
     println!(
         "client_first: kitchen->socket4 try to disable: {:?}",
@@ -66,14 +77,12 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
     println!(
         "client_first: kitchen->socket4 after disable: {:?}",
@@ -81,15 +90,13 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ChangeDeviceData {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
-                data: PowerSocketState { enabled: true },
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+            data: PowerSocketState { enabled: true },
+        }))
         .await?;
     println!(
         "client_first: kitchen->socket4 try to enable: {:?}",
@@ -97,14 +104,12 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
     println!(
         "client_first: kitchen->socket4 after enable: {:?}",
@@ -115,38 +120,33 @@ async fn main() -> Result<(), HouseExchangeError> {
         room_name: "kitchen".to_string(),
         device_name: "sensor1".to_string(),
     };
-    client
-        .send(RequestMessage {
-            body: RegisterDeviceMonitor {
-                location: sensor_location.clone(),
-            },
-        })
+    let response = client
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: sensor_location.clone(),
+            reliable: true,
+        }))
         .await?;
+    println!("client_first: received {:?}", response.body);
 
-    let mut i = 0;
-    while let Some(response) = client.response_message_rx.recv().await {
-        match response {
-            ResponseMessage {
-                body: MonitorRemoved { .. },
-            } => {
-                println!("client_first: left monitoring {:?}", sensor_location);
-                break;
-            }
-            ResponseMessage { body } => {
-                println!("client_first: received {:?}", body);
-            }
-        }
-        i += 1;
-        if i == 5 {
-            client
-                .send(RequestMessage {
-                    body: RemoveDeviceMonitor,
-                })
-                .await?;
+    for _ in 0..4 {
+        if let Some(ResponseMessage { body, .. }) = client.monitor_event_rx.recv().await {
+            println!("client_first: received {:?}", body);
         }
     }
 
+    let response = client
+        .send_and_receive(RequestMessage::new(RemoveDeviceMonitor))
+        .await?;
+    if let MonitorRemoved = response.body {
+        println!("client_first: left monitoring {:?}", sensor_location);
+    }
+
     println!("Interactions are completed");
 
+    let shutdown = server.shutdown_handle();
+    shutdown.shutdown();
+    shutdown.stopped().await;
+    println!("House server has stopped");
+
     Ok(())
 }
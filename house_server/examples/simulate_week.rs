@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use house::access::domain::{Credentials, UserName};
+use house::access::rate_limiter::RateLimits;
+use house::audit::audit_log::AuditLog;
+use house::audit::audited_inventory::AuditedInventory;
+use house::audit::domain::{AuditContext, AuditFilter, AuditSource};
+use house::audit::memory_audit_log::MemoryAuditLog;
+use house::errors::intelligent_house_error::IntelligentHouseError;
+use house::history::domain::now_millis;
+use house::history::memory_device_history::MemoryDeviceHistory;
+use house::inventory::memory_device_inventory::MemoryDeviceInventory;
+use house::simulation::clock::{Clock, SimulatedClock};
+use house::simulation::house_simulator::HouseSimulator;
+use house::simulation::thermal_model::{OutsideClimate, RoomClimate, ThermalModel};
+use house::ThreeRoomNames;
+use tracing_subscriber::EnvFilter;
+
+use house_server::domain::RequestBody::ShowDeviceHistory;
+use house_server::domain::ResponseBody::DeviceHistory;
+use house_server::domain::{DeviceLocation, RequestMessage};
+use house_server::error::*;
+use house_server::house_client::HouseClient;
+use house_server::house_server::{HouseServer, Listeners};
+
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[tokio::main]
+async fn main() -> Result<(), HouseExchangeError> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
+    let tcp_server_address = "127.0.0.1:45932";
+    let udp_server_address = "127.0.0.1:45959";
+
+    let names = ThreeRoomNames::default();
+    let inventory: MemoryDeviceInventory = house::mk_three_rooms_inventory(names.clone());
+    let history = MemoryDeviceHistory::default();
+    let audit = MemoryAuditLog::default();
+    let clock = SimulatedClock::new(now_millis());
+    let started_at = clock.now();
+
+    HouseServer::start_with(
+        inventory.clone(),
+        history,
+        house::mk_three_rooms_users(names.clone()),
+        audit.clone(),
+        clock.clone(),
+        Listeners::new(tcp_server_address, udp_server_address),
+        RateLimits::default(),
+    )
+    .await?;
+
+    let mut model = ThermalModel::new(OutsideClimate {
+        mean: 5.0,
+        daily_amplitude: 6.0,
+    });
+    model.add_room(
+        names.kitchen.clone(),
+        RoomClimate {
+            heat_loss: 250.0,
+            ..RoomClimate::new(18.0, vec![names.socket4.clone()])
+        },
+    );
+    model.add_room(
+        names.lounge.clone(),
+        RoomClimate {
+            heat_loss: 250.0,
+            ..RoomClimate::new(18.0, vec![names.socket2.clone()])
+        },
+    );
+    model.add_room(names.bedroom.clone(), RoomClimate::new(18.0, vec![]));
+    model.add_adjacency(names.kitchen.clone(), names.lounge.clone(), 50.0);
+    model.add_adjacency(names.lounge.clone(), names.bedroom.clone(), 50.0);
+
+    let automation = AuditedInventory::new(
+        inventory,
+        audit.clone(),
+        AuditContext::new("simulator", AuditSource::Automation, None),
+    );
+    let mut simulator = HouseSimulator::new(automation, clock.clone(), model);
+    simulator
+        .run(WEEK, Duration::from_secs(5 * 60), 100_000.0)
+        .await?;
+
+    for room_name in [&names.kitchen, &names.lounge, &names.bedroom] {
+        println!(
+            "simulation: {} is {:.1}C° after a week",
+            room_name,
+            simulator.model().temperature(room_name).unwrap_or_default()
+        );
+    }
+
+    let changes = audit
+        .query(&AuditFilter {
+            source: Some(AuditSource::Automation),
+            ..Default::default()
+        })
+        .await
+        .map_err(IntelligentHouseError::AuditErr)?;
+    println!("simulation: {} sensor changes audited", changes.len());
+
+    let client = HouseClient::connect(
+        "simulation".to_string(),
+        tcp_server_address,
+        udp_server_address,
+        "127.0.0.1:41858",
+    )
+    .await?;
+
+    client
+        .authenticate(Credentials {
+            user_name: UserName("owner".to_string()),
+            password: "owner".to_string(),
+        })
+        .await?;
+
+    let response = client
+        .send_and_receive(RequestMessage::new(ShowDeviceHistory {
+            location: DeviceLocation {
+                room_name: names.kitchen.0.clone(),
+                device_name: names.sensor1.0.clone(),
+            },
+            from: started_at,
+            to: clock.now(),
+        }))
+        .await?;
+
+    if let DeviceHistory(series) = response.body {
+        for s in series {
+            println!(
+                "simulation: {:?} of kitchen->sensor1 has {} points",
+                s.metric,
+                s.points.len()
+            );
+        }
+    }
+
+    Ok(())
+}
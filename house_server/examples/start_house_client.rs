@@ -1,3 +1,4 @@
+use house::access::domain::{Credentials, UserName};
 use house_server::domain::DeviceData::*;
 use house_server::domain::RequestBody::{
     ChangeDeviceData, RegisterDeviceMonitor, RemoveDeviceMonitor, ShowDeviceInfo,
@@ -6,9 +7,13 @@ use house_server::domain::ResponseBody::MonitorRemoved;
 use house_server::domain::{DeviceLocation, RequestMessage, ResponseMessage};
 use house_server::error::*;
 use house_server::house_client::HouseClient;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), HouseExchangeError> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let tcp_server_address = "127.0.0.1:45932";
     let udp_server_address = "127.0.0.1:45959";
     let udp_local_address = "127.0.0.1:41868";
@@ -21,15 +26,20 @@ async fn main() -> Result<(), HouseExchangeError> {
     )
     .await?;
 
+    client
+        .authenticate(Credentials {
+            user_name: UserName("owner".to_string()),
+            password: "owner".to_string(),
+        })
+        .await?;
+
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
     println!(
         "client_b: kitchen->socket4 before disable: {:?}'",
@@ -37,15 +47,13 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ChangeDeviceData {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
-                data: PowerSocketState { enabled: false },
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+            data: PowerSocketState { enabled: false },
+        }))
         .await?;
     println!(
         "client_b: kitchen->socket4 try to disable: {:?}",
@@ -53,14 +61,12 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
     println!(
         "client_b: kitchen->socket4 after disable: {:?}",
@@ -68,15 +74,13 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ChangeDeviceData {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
-                data: PowerSocketState { enabled: true },
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+            data: PowerSocketState { enabled: true },
+        }))
         .await?;
     println!(
         "client_b: kitchen->socket4 try to enable: {:?}",
@@ -84,14 +88,12 @@ async fn main() -> Result<(), HouseExchangeError> {
     );
 
     let response = client
-        .send_and_receive(RequestMessage {
-            body: ShowDeviceInfo {
-                location: DeviceLocation {
-                    room_name: "kitchen".to_string(),
-                    device_name: "socket4".to_string(),
-                },
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: DeviceLocation {
+                room_name: "kitchen".to_string(),
+                device_name: "socket4".to_string(),
             },
-        })
+        }))
         .await?;
     println!(
         "client_b: kitchen->socket4 after enable: {:?}",
@@ -102,36 +104,26 @@ async fn main() -> Result<(), HouseExchangeError> {
         room_name: "kitchen".to_string(),
         device_name: "sensor1".to_string(),
     };
-    client
-        .send(RequestMessage {
-            body: RegisterDeviceMonitor {
-                location: sensor_location.clone(),
-            },
-        })
+    let response = client
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: sensor_location.clone(),
+            reliable: false,
+        }))
         .await?;
+    println!("client_b: received {:?}", response.body);
 
-    let mut i = 0;
-    while let Some(response) = client.response_message_rx.recv().await {
-        match response {
-            ResponseMessage {
-                body: MonitorRemoved { .. },
-            } => {
-                println!("client_b: left monitoring {:?}", sensor_location);
-                break;
-            }
-            ResponseMessage { body } => {
-                println!("client_b: received {:?}", body);
-            }
-        }
-        i += 1;
-        if i == 5 {
-            client
-                .send(RequestMessage {
-                    body: RemoveDeviceMonitor,
-                })
-                .await?;
+    for _ in 0..4 {
+        if let Some(ResponseMessage { body, .. }) = client.monitor_event_rx.recv().await {
+            println!("client_b: received {:?}", body);
         }
     }
 
+    let response = client
+        .send_and_receive(RequestMessage::new(RemoveDeviceMonitor))
+        .await?;
+    if let MonitorRemoved = response.body {
+        println!("client_b: left monitoring {:?}", sensor_location);
+    }
+
     Ok(())
 }
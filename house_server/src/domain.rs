@@ -1,12 +1,49 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
+use exchange_protocol::domain::DisconnectReason;
+use house::access::domain::{Credentials, Role, SessionToken};
+use house::history::domain::{DeviceSeries, Timestamp};
+use house::inventory::domain::DeviceHealth;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestMessage {
+    /// Given by the client, the response carries it back.
+    #[serde(default)]
+    pub id: u64,
+    /// Follows the request through the traces of client, server and inventory. The
+    /// server makes one up for a request that comes without.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Authenticates a request over udp, a connection hands it out with `Authenticated`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionToken>,
     pub body: RequestBody,
 }
 
+impl RequestMessage {
+    pub fn new(body: RequestBody) -> RequestMessage {
+        RequestMessage {
+            id: 0,
+            trace_id: None,
+            session: None,
+            body,
+        }
+    }
+
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> RequestMessage {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RequestBody {
+    /// Only over a connection, never over plain udp.
+    Authenticate {
+        credentials: Credentials,
+    },
     ChangeDeviceData {
         location: DeviceLocation,
         data: DeviceData,
@@ -16,8 +53,30 @@ pub enum RequestBody {
     },
     RegisterDeviceMonitor {
         location: DeviceLocation,
+        /// The data comes over tcp instead of udp, none of it gets lost.
+        #[serde(default)]
+        reliable: bool,
     },
     RemoveDeviceMonitor,
+    ShowDeviceHistory {
+        location: DeviceLocation,
+        from: Timestamp,
+        to: Timestamp,
+    },
+}
+
+impl RequestBody {
+    /// The variant name, requests are measured by it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RequestBody::Authenticate { .. } => "Authenticate",
+            RequestBody::ChangeDeviceData { .. } => "ChangeDeviceData",
+            RequestBody::ShowDeviceInfo { .. } => "ShowDeviceInfo",
+            RequestBody::RegisterDeviceMonitor { .. } => "RegisterDeviceMonitor",
+            RequestBody::RemoveDeviceMonitor => "RemoveDeviceMonitor",
+            RequestBody::ShowDeviceHistory { .. } => "ShowDeviceHistory",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,15 +92,87 @@ pub enum DeviceData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResponseMessage {
+    /// The request answered, none for monitor data the server pushes.
+    #[serde(default)]
+    pub id: Option<u64>,
+    /// The one of the request answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
     pub body: ResponseBody,
 }
 
+impl ResponseMessage {
+    pub fn new(body: ResponseBody) -> ResponseMessage {
+        ResponseMessage {
+            id: None,
+            trace_id: None,
+            body,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ResponseBody {
+    Authenticated {
+        role: Role,
+        /// For the requests over udp, valid while the connection lasts.
+        #[serde(default)]
+        session: Option<SessionToken>,
+    },
+    AccessDenied(String),
     DeviceDataChanged,
     DeviceDescription(String),
     MonitorRegistered,
     MonitorRemoved,
-    PowerSocketInfo { enabled: bool, power: u32 },
-    TemperatureSensorInfo { temperature: i32 },
+    PowerSocketInfo {
+        enabled: bool,
+        power: u32,
+        health: DeviceHealth,
+    },
+    TemperatureSensorInfo {
+        temperature: i32,
+        health: DeviceHealth,
+    },
+    DeviceHistory(Vec<DeviceSeries>),
+    DeviceOffline {
+        last_seen: Timestamp,
+    },
+    /// The client sent more requests of the type than its rate limit lets through.
+    Throttled {
+        retry_after_ms: u64,
+    },
+    /// The request was allowed but could not be carried out.
+    RequestFailed(String),
+}
+
+/// How a client encodes its requests, the replies and its monitor data go back the same
+/// way. Web pages send JSON in WebSocket text frames, binary frames and the other clients
+/// carry flexbuffers.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Encoding {
+    Flexbuffers,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Channel {
+    Tcp,
+    Udp,
+}
+
+/// How the connection of a client to the house server goes, both channels reconnect on
+/// their own.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConnectionEvent {
+    Disconnected(Channel, DisconnectReason),
+    Reconnecting {
+        channel: Channel,
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Authentication and monitors are restored, requests sent meanwhile follow.
+    Reconnected(Channel),
+    /// The server took the client certificate for a user with the role, requests need no
+    /// password. It comes again with every reconnect.
+    Authenticated(Role),
 }
@@ -1,5 +1,6 @@
 use std::io;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use flexbuffers::{DeserializationError, SerializationError};
 use thiserror::Error;
@@ -13,6 +14,8 @@ pub enum HouseExchangeError {
     DecodeError(#[from] DeserializationError),
     #[error("Encode message error: {0}")]
     EncodeError(#[from] SerializationError),
+    #[error("JSON message error: {0}")]
+    JsonError(#[from] serde_json::Error),
     #[error("Tcp exchange error")]
     TcpExchangeError(#[from] ExchangeError),
     #[error("IO error: {0}")]
@@ -23,6 +26,10 @@ pub enum HouseExchangeError {
     SendNotifyError(SocketAddr, String),
     #[error("Sending notify message to bus failed: {0}")]
     SendNotifyEventError(String),
+    #[error("No response within {0:?}")]
+    ResponseTimeout(Duration),
+    #[error("Authentication failed: {0}")]
+    AuthenticationError(String),
     #[error("house interaction error: {0}")]
     IntelligentHouseError(#[from] IntelligentHouseError),
 }
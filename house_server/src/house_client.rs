@@ -1,24 +1,203 @@
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use parking_lot::Mutex as SyncMutex;
 
 use flexbuffers::{DeserializationError, Reader, SerializationError};
 use serde::{Deserialize, Serialize};
 use tokio::net::ToSocketAddrs;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use exchange_protocol::domain::Message;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::limits::Limits;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::reconnect::Reconnect;
+use house::access::domain::{Credentials, Role, SessionToken};
+use house::audit::domain::new_trace_id;
 use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tls::ClientTls;
+use udp_exchange::encryption::Encryption;
 use udp_exchange::udp_client::UdpClient;
 
-use crate::domain::{RequestBody, RequestMessage, ResponseMessage};
+use crate::domain::{
+    Channel, ConnectionEvent, DeviceLocation, RequestBody, RequestMessage, ResponseBody,
+    ResponseMessage,
+};
 use crate::error::HouseExchangeError;
 use crate::error::HouseExchangeError::*;
 
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests restoring a reconnected channel, nobody waits for their responses.
+const REPLAY_ID: u64 = 0;
+
+/// How a house client talks to its server, apart from the addresses.
+#[derive(Clone)]
+pub struct ClientOptions {
+    /// Bound the queues of requests and responses on both channels.
+    pub limits: Limits,
+    /// Without it a lost channel stays lost.
+    pub reconnect: Option<Reconnect>,
+    /// Tcp goes over TLS. A server may take the client certificate for a user, then no
+    /// password is needed.
+    pub tls: Option<ClientTls>,
+    /// Udp datagrams are encrypted, the server has to expect the same key.
+    pub encryption: Option<Encryption>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            limits: Limits::default(),
+            reconnect: Some(Reconnect::default()),
+            tls: None,
+            encryption: None,
+        }
+    }
+}
+
+impl ClientOptions {
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_reconnect(mut self, reconnect: Option<Reconnect>) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: ClientTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    fn exchange(&self) -> ExchangeOptions {
+        let mut options = ExchangeOptions::default().with_limits(self.limits);
+        options.reconnect = self.reconnect;
+        options
+    }
+}
+
+// streams nobody takes from in time drop what comes next, they never hold up the others
 pub struct HouseClient {
     pub client_name: String,
+    /// Responses to requests that were only sent.
     pub response_message_rx: Receiver<ResponseMessage>,
+    /// Data of the watched device as the server pushes it.
+    pub monitor_event_rx: Receiver<ResponseMessage>,
+    pub connection_event_rx: Receiver<ConnectionEvent>,
+    pub response_timeout: Duration,
     request_message_tx: Sender<RequestMessage>,
+    waiting: Waiting,
+    next_id: AtomicU64,
+}
+
+/// Callers waiting for the responses to their requests, by request id.
+type Waiting = Arc<DashMap<u64, Sender<ResponseMessage>>>;
+
+/// The token the requests over udp carry, the latest authentication hands it out.
+type Session = Arc<SyncMutex<Option<SessionToken>>>;
+
+/// Takes the responses to one request, stops waiting for them once dropped.
+struct Waiter {
+    id: u64,
+    responses: Receiver<ResponseMessage>,
+    waiting: Waiting,
+}
+
+impl Waiter {
+    async fn receive(&mut self, timeout: Duration) -> Result<ResponseMessage, HouseExchangeError> {
+        tokio::time::timeout(timeout, self.responses.recv())
+            .await
+            .map_err(|_| ResponseTimeout(timeout))?
+            .ok_or(ReceiveError)
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.waiting.remove(&self.id);
+    }
+}
+
+/// What a reconnected channel has to tell the server again.
+#[derive(Default)]
+struct Replay {
+    credentials: Option<Credentials>,
+    /// The watched device and whether it is watched over tcp.
+    monitor: Option<(DeviceLocation, bool)>,
+}
+
+impl Replay {
+    fn requests(&self, channel: Channel) -> Vec<RequestMessage> {
+        // udp requests carry the session of the tcp connection instead
+        let authenticate = self
+            .credentials
+            .iter()
+            .filter(|_| channel == Channel::Tcp)
+            .map(|credentials| RequestBody::Authenticate {
+                credentials: credentials.clone(),
+            });
+        let monitor = self
+            .monitor
+            .iter()
+            .filter(|(_, reliable)| *reliable == (channel == Channel::Tcp))
+            .map(|(location, reliable)| RequestBody::RegisterDeviceMonitor {
+                location: location.clone(),
+                reliable: *reliable,
+            });
+        authenticate
+            .chain(monitor)
+            .map(|body| RequestMessage {
+                id: REPLAY_ID,
+                ..RequestMessage::new(body)
+            })
+            .collect()
+    }
+
+    /// Returns whether there is something new to restore.
+    fn update(&mut self, body: &RequestBody) -> bool {
+        match body {
+            RequestBody::Authenticate { credentials } => {
+                self.credentials = Some(credentials.clone())
+            }
+            RequestBody::RegisterDeviceMonitor { location, reliable } => {
+                self.monitor = Some((location.clone(), *reliable))
+            }
+            RequestBody::RemoveDeviceMonitor => self.monitor = None,
+            _ => return false,
+        }
+        true
+    }
+
+    fn reliable_monitor(&self) -> bool {
+        self.monitor.as_ref().is_some_and(|(_, reliable)| *reliable)
+    }
+}
+
+/// Where the receiving side of both channels reports to.
+#[derive(Clone)]
+struct Receiving {
+    response_message_tx: Sender<ResponseMessage>,
+    monitor_event_tx: Sender<ResponseMessage>,
+    connection_event_tx: Sender<ConnectionEvent>,
+    waiting: Waiting,
+    replay: Arc<Mutex<Replay>>,
+    session: Session,
 }
 
 impl HouseClient {
@@ -28,153 +207,506 @@ impl HouseClient {
         udp_server_address: Addrs,
         local_address: Addrs,
     ) -> Result<HouseClient, HouseExchangeError> {
-        let client = TcpClient::connect(tcp_server_address).await?;
+        Self::connect_with(
+            client_name,
+            tcp_server_address,
+            udp_server_address,
+            local_address,
+            ClientOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn connect_with<Addrs: ToSocketAddrs>(
+        client_name: String,
+        tcp_server_address: Addrs,
+        udp_server_address: Addrs,
+        local_address: Addrs,
+        options: ClientOptions,
+    ) -> Result<HouseClient, HouseExchangeError> {
+        let client = match options.tls.clone() {
+            Some(tls) => {
+                TcpClient::connect_tls_with(tcp_server_address, options.exchange(), tls).await?
+            }
+            None => TcpClient::connect_with(tcp_server_address, options.exchange()).await?,
+        };
+        Self::start(
+            client_name,
+            client,
+            (udp_server_address, local_address),
+            options,
+        )
+        .await
+    }
+
+    /// Talks to a server on the same machine over its unix socket instead of tcp, the
+    /// socket needs no TLS.
+    pub async fn connect_local_with<P: AsRef<Path>, Addrs: ToSocketAddrs>(
+        client_name: String,
+        unix_path: P,
+        udp_server_address: Addrs,
+        local_address: Addrs,
+        options: ClientOptions,
+    ) -> Result<HouseClient, HouseExchangeError> {
+        let client = TcpClient::connect_unix_with(unix_path, options.exchange()).await?;
+        Self::start(
+            client_name,
+            client,
+            (udp_server_address, local_address),
+            options,
+        )
+        .await
+    }
+
+    async fn start<Addrs: ToSocketAddrs>(
+        client_name: String,
+        client: TcpClient,
+        (udp_server_address, local_address): (Addrs, Addrs),
+        options: ClientOptions,
+    ) -> Result<HouseClient, HouseExchangeError> {
+        let ClientOptions {
+            limits,
+            reconnect,
+            encryption,
+            ..
+        } = options;
         let tcp_server_address = client.server_address;
         let tcp_client_orig = Arc::new(Mutex::new(client));
 
-        let client = UdpClient::connect(udp_server_address, local_address).await?;
-        let udp_server_address = client.server_address;
+        let udp_options = ExchangeOptions::new(LengthPrefixedCodec::datagram()).with_limits(limits);
+        let client = Self::connect_udp(
+            udp_server_address,
+            local_address,
+            udp_options.clone(),
+            encryption.clone(),
+        )
+        .await?;
         let udp_client_orig = Arc::new(Mutex::new(client));
 
-        let (request_message_tx, mut request_message_rx) = mpsc::channel::<RequestMessage>(1000);
+        let replay = Arc::new(Mutex::new(Replay::default()));
+        let session = Session::default();
+
+        let (request_message_tx, mut request_message_rx) =
+            mpsc::channel::<RequestMessage>(limits.queue_size);
 
         let tcp_client = tcp_client_orig.clone();
         let udp_client = udp_client_orig.clone();
-        let name = client_name.clone();
-        tokio::spawn(async move {
-            while let Some(msg) = request_message_rx.recv().await {
-                Self::send_message(msg, tcp_client.clone(), udp_client.clone(), name.clone())
-                    .await
-                    .unwrap_or_else(|error| {
-                        eprintln!("client_{name}: send message to house server failed: {error:?}")
-                    });
+        let send_replay = replay.clone();
+        let send_session = session.clone();
+        let span = info_span!("house_client", name = %client_name);
+        tokio::spawn(
+            async move {
+                while let Some(msg) = request_message_rx.recv().await {
+                    let span = info_span!(
+                        "request",
+                        trace_id = %msg.trace_id.as_deref().unwrap_or_default(),
+                        id = msg.id,
+                        kind = msg.body.kind()
+                    );
+                    let channels = (tcp_client.clone(), udp_client.clone());
+                    Self::send_message(msg, channels, &send_replay, &send_session)
+                        .instrument(span)
+                        .await
+                        .unwrap_or_else(|error| warn!(?error, "sending request failed"));
+                }
             }
-        });
+            .instrument(span.clone()),
+        );
 
-        let (response_message_tx, response_message_rx) = mpsc::channel::<ResponseMessage>(1000);
+        let (response_message_tx, response_message_rx) =
+            mpsc::channel::<ResponseMessage>(limits.queue_size);
+        let (monitor_event_tx, monitor_event_rx) =
+            mpsc::channel::<ResponseMessage>(limits.queue_size);
+        let (connection_event_tx, connection_event_rx) =
+            mpsc::channel::<ConnectionEvent>(limits.queue_size);
+        let waiting = Waiting::default();
+        let receiving = Receiving {
+            response_message_tx,
+            monitor_event_tx,
+            connection_event_tx,
+            waiting: waiting.clone(),
+            replay,
+            session,
+        };
 
-        let response_tx = response_message_tx.clone();
-        let name = client_name.clone();
-        tokio::spawn(async move {
-            let messages = (tcp_client_orig.clone()).lock().await.messages.clone();
-            Self::receive_messages(messages, tcp_server_address, response_tx, name).await;
-        });
+        let tcp_receiving = receiving.clone();
+        tokio::spawn(
+            async move {
+                let messages = tcp_client_orig.lock().await.messages.clone();
+                // the tcp client stops reconnecting once the house client is dropped
+                drop(tcp_client_orig);
+                let mut reconnected = false;
+                // the tcp client reconnects by itself, its messages go on
+                while let Some(reason) = Self::receive_messages(
+                    messages.clone(),
+                    tcp_server_address,
+                    Channel::Tcp,
+                    reconnected,
+                    &tcp_receiving,
+                )
+                .await
+                {
+                    if !reconnect.is_some_and(|reconnect| reconnect.applies_to(&reason)) {
+                        break;
+                    }
+                    reconnected = true;
+                }
+            }
+            .instrument(span.clone()),
+        );
 
-        let name = client_name.clone();
-        tokio::spawn(async move {
-            let messages = (udp_client_orig.clone()).lock().await.messages.clone();
-            Self::receive_messages(messages, udp_server_address, response_message_tx, name).await;
-        });
+        tokio::spawn(
+            Self::keep_udp_connected(
+                udp_client_orig,
+                (udp_options, encryption),
+                reconnect,
+                receiving,
+            )
+            .instrument(span),
+        );
 
         Ok(HouseClient {
             client_name,
             response_message_rx,
+            monitor_event_rx,
+            connection_event_rx,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
             request_message_tx,
+            waiting,
+            next_id: AtomicU64::new(REPLAY_ID + 1),
         })
     }
 
-    async fn send_message(
-        request_message: RequestMessage,
-        tcp_client: Arc<Mutex<TcpClient>>,
-        udp_client: Arc<Mutex<UdpClient>>,
-        _client_name: String,
-    ) -> Result<(), HouseExchangeError> {
+    fn serialize_request(request_message: &RequestMessage) -> Result<Vec<u8>, HouseExchangeError> {
         let mut serializer = flexbuffers::FlexbufferSerializer::new();
         request_message
             .serialize(&mut serializer)
             .map_err(|e| SerializationError::Serde(e.to_string()))?;
 
-        let bytes = serializer.view();
+        Ok(serializer.take_buffer())
+    }
 
-        println!(
-            "client_{_client_name}: send message {:?}",
-            request_message.body
-        );
+    async fn send_message(
+        mut request_message: RequestMessage,
+        (tcp_client, udp_client): (Arc<Mutex<TcpClient>>, Arc<Mutex<UdpClient>>),
+        replay: &Mutex<Replay>,
+        session: &Session,
+    ) -> Result<(), HouseExchangeError> {
+        debug!(body = ?request_message.body, "sending request");
 
-        match request_message.body {
-            RequestBody::ChangeDeviceData { .. } | RequestBody::ShowDeviceInfo { .. } => {
-                let mut client = tcp_client.lock().await;
-                client
-                    .send(bytes)
-                    .await
-                    .map_err(|e| SendNotifyError(client.server_address, e.to_string()))
+        // a monitor is removed over the channel it was registered on
+        let reliable = {
+            let mut replay = replay.lock().await;
+            let reliable = match request_message.body {
+                RequestBody::RegisterDeviceMonitor { reliable, .. } => reliable,
+                _ => replay.reliable_monitor(),
+            };
+            if replay.update(&request_message.body) {
+                let frames = replay
+                    .requests(Channel::Tcp)
+                    .iter()
+                    .map(Self::serialize_request)
+                    .collect::<Result<Vec<_>, _>>()?;
+                tcp_client.lock().await.replay_on_reconnect(frames).await;
             }
-            RequestBody::RegisterDeviceMonitor { .. } | RequestBody::RemoveDeviceMonitor { .. } => {
-                let mut client = udp_client.lock().await;
-                client
-                    .send(bytes)
-                    .await
-                    .map_err(|e| SendNotifyError(client.server_address, e.to_string()))
+            reliable
+        };
+
+        // only monitors not watched reliably go over udp, everything else over tcp
+        let over_udp = match request_message.body {
+            RequestBody::RegisterDeviceMonitor { .. } | RequestBody::RemoveDeviceMonitor => {
+                !reliable
             }
+            _ => false,
+        };
+        if !over_udp {
+            let bytes = Self::serialize_request(&request_message)?;
+            let mut client = tcp_client.lock().await;
+            return client
+                .send(&bytes)
+                .await
+                .map_err(|e| SendNotifyError(client.server_address, e.to_string()));
+        }
+        request_message.session = session.lock().clone();
+        let bytes = Self::serialize_request(&request_message)?;
+        let mut client = udp_client.lock().await;
+        client
+            .send(&bytes)
+            .await
+            .map_err(|e| SendNotifyError(client.server_address, e.to_string()))
+    }
+
+    /// Authenticates over tcp, the requests over udp carry the session it hands out.
+    pub async fn authenticate(&self, credentials: Credentials) -> Result<Role, HouseExchangeError> {
+        let response = self
+            .send_and_receive(RequestMessage::new(RequestBody::Authenticate {
+                credentials,
+            }))
+            .await?;
+        match response.body {
+            ResponseBody::Authenticated { role, .. } => Ok(role),
+            body => Err(AuthenticationError(format!("{body:?}"))),
         }
     }
 
+    /// Waits for the response to this very request, up to the `response_timeout`.
     pub async fn send_and_receive(
-        &mut self,
+        &self,
+        msg: RequestMessage,
+    ) -> Result<ResponseMessage, HouseExchangeError> {
+        self.send_and_receive_within(msg, self.response_timeout)
+            .await
+    }
+
+    pub async fn send_and_receive_within(
+        &self,
         msg: RequestMessage,
+        timeout: Duration,
     ) -> Result<ResponseMessage, HouseExchangeError> {
-        self.request_message_tx.send(msg).await.map_err(|e| {
-            eprintln!("client: failed send to bus: {}", e);
-            SendNotifyEventError(e.to_string())
-        })?;
-        self.response_message_rx.recv().await.ok_or(ReceiveError)
+        self.send_waiting(msg, 1).await?.receive(timeout).await
+    }
+
+    /// Returns the id the request got, its response comes on `response_message_rx`.
+    /// A request without a trace id gets a new one.
+    pub async fn send(&self, mut msg: RequestMessage) -> Result<u64, HouseExchangeError> {
+        msg.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        msg.trace_id.get_or_insert_with(new_trace_id);
+        let id = msg.id;
+        self.request_message_tx
+            .send(msg)
+            .await
+            .map_err(|e| SendNotifyEventError(e.to_string()))?;
+        Ok(id)
     }
 
-    pub async fn send(&mut self, msg: RequestMessage) -> Result<(), HouseExchangeError> {
+    /// Registers for the `responses` expected before the request goes out.
+    async fn send_waiting(
+        &self,
+        mut msg: RequestMessage,
+        responses: usize,
+    ) -> Result<Waiter, HouseExchangeError> {
+        msg.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        msg.trace_id.get_or_insert_with(new_trace_id);
+        let (response_tx, response_rx) = mpsc::channel(responses);
+        self.waiting.insert(msg.id, response_tx);
+        let waiter = Waiter {
+            id: msg.id,
+            responses: response_rx,
+            waiting: self.waiting.clone(),
+        };
         self.request_message_tx
             .send(msg)
             .await
-            .map_err(|e| SendNotifyEventError(e.to_string()))
+            .map_err(|e| SendNotifyEventError(e.to_string()))?;
+        Ok(waiter)
     }
 
+    async fn connect_udp<Addrs: ToSocketAddrs>(
+        server_address: Addrs,
+        local_address: Addrs,
+        options: ExchangeOptions,
+        encryption: Option<Encryption>,
+    ) -> Result<UdpClient, ExchangeError> {
+        match encryption {
+            Some(encryption) => {
+                UdpClient::connect_encrypted_with(
+                    server_address,
+                    local_address,
+                    options,
+                    encryption,
+                )
+                .await
+            }
+            None => UdpClient::connect_with(server_address, local_address, options).await,
+        }
+    }
+
+    /// A udp client can't tell a restarted server from the old one, it is replaced by a
+    /// new one on a fresh port. Requests wait for it meanwhile.
+    async fn keep_udp_connected(
+        udp_client: Arc<Mutex<UdpClient>>,
+        (options, encryption): (ExchangeOptions, Option<Encryption>),
+        reconnect: Option<Reconnect>,
+        receiving: Receiving,
+    ) {
+        let mut reconnected = false;
+        loop {
+            let (messages, server_address) = {
+                let client = udp_client.lock().await;
+                (client.messages.clone(), client.server_address)
+            };
+            let reason = match Self::receive_messages(
+                messages,
+                server_address,
+                Channel::Udp,
+                reconnected,
+                &receiving,
+            )
+            .await
+            {
+                Some(reason) => reason,
+                None => return,
+            };
+            let reconnect = match reconnect.filter(|reconnect| reconnect.applies_to(&reason)) {
+                Some(reconnect) => reconnect,
+                None => return,
+            };
+
+            let mut client = udp_client.lock().await;
+            let local_address = SocketAddr::new(client.address.ip(), 0);
+            let mut attempt = 1;
+            let replaced = loop {
+                // nobody is waiting for responses anymore, the house client is gone
+                if reconnect.gives_up(attempt) || receiving.response_message_tx.is_closed() {
+                    break None;
+                }
+                let delay = reconnect.delay(attempt);
+                let _ = receiving
+                    .connection_event_tx
+                    .try_send(ConnectionEvent::Reconnecting {
+                        channel: Channel::Udp,
+                        attempt,
+                        delay,
+                    });
+                tokio::time::sleep(delay).await;
+                match Self::connect_udp(
+                    server_address,
+                    local_address,
+                    options.clone(),
+                    encryption.clone(),
+                )
+                .await
+                {
+                    Ok(replaced) => break Some(replaced),
+                    Err(error) => {
+                        warn!(server = %server_address, %error, "reconnecting udp failed")
+                    }
+                }
+                attempt += 1;
+            };
+            let mut replaced = match replaced {
+                Some(replaced) => replaced,
+                None => return,
+            };
+
+            let requests = receiving.replay.lock().await.requests(Channel::Udp);
+            for mut request in requests {
+                request.session = receiving.session.lock().clone();
+                let sent = match Self::serialize_request(&request) {
+                    Ok(bytes) => replaced
+                        .send(&bytes)
+                        .await
+                        .map_err(HouseExchangeError::from),
+                    Err(error) => Err(error),
+                };
+                sent.unwrap_or_else(
+                    |error| warn!(body = ?request.body, ?error, "restoring request failed"),
+                );
+            }
+            *client = replaced;
+            reconnected = true;
+        }
+    }
+
+    /// Returns why the channel was lost, nothing once its messages have ended.
     async fn receive_messages(
         messages: Arc<Mutex<Receiver<Message>>>,
         server_address: SocketAddr,
-        response_message_tx: Sender<ResponseMessage>,
-        _client_name: String,
-    ) {
+        channel: Channel,
+        reconnected: bool,
+        receiving: &Receiving,
+    ) -> Option<DisconnectReason> {
         while let Some(msg) = messages.lock().await.recv().await {
             match msg {
-                Message::Connected => {
-                    println!(
-                        "client_{_client_name}: connected to server '{}'",
-                        server_address
-                    )
+                Message::Connected(info) => {
+                    info!(
+                        server = %server_address,
+                        version = info.version(),
+                        "connected"
+                    );
+                    if reconnected {
+                        let _ = receiving
+                            .connection_event_tx
+                            .try_send(ConnectionEvent::Reconnected(channel));
+                    }
                 }
                 Message::Bytes(ref response_bytes) => {
-                    Self::receive_message(
-                        response_bytes,
-                        response_message_tx.clone(),
-                        server_address,
-                    ).await.unwrap_or_else(|error| {
-                        eprintln!(
-                            "client_{_client_name}: receiving message from house server '{server_address}' failed: {error:?}"
-                        )
-                    });
+                    let response = match Self::receive_message(response_bytes) {
+                        Ok(response) => response,
+                        Err(error) => {
+                            warn!(server = %server_address, ?error, "decoding response failed");
+                            continue;
+                        }
+                    };
+                    Self::dispatch(response, receiving);
+                }
+                // only WebSocket has text frames
+                Message::Text(_) => {}
+                Message::Error(error) => {
+                    warn!(server = %server_address, %error, "exchange with server failed")
+                }
+                Message::Reconnecting { attempt, delay } => {
+                    let _ = receiving
+                        .connection_event_tx
+                        .try_send(ConnectionEvent::Reconnecting {
+                            channel,
+                            attempt,
+                            delay,
+                        });
                 }
-                Message::Disconnected => {
-                    println!(
-                        "client_{_client_name}: disconnected from '{}'",
-                        server_address
-                    )
+                Message::Disconnected(reason) => {
+                    info!(server = %server_address, ?reason, "disconnected");
+                    let _ = receiving
+                        .connection_event_tx
+                        .try_send(ConnectionEvent::Disconnected(channel, reason.clone()));
+                    // waiting callers time out once both channels are gone for good
+                    return Some(reason);
                 }
             };
         }
+        None
     }
 
-    async fn receive_message(
-        response_bytes: &Vec<u8>,
-        response_message_tx: Sender<ResponseMessage>,
-        server_address: SocketAddr,
-    ) -> Result<(), HouseExchangeError> {
-        let reader =
-            Reader::get_root(response_bytes.as_slice()).map_err(DeserializationError::Reader)?;
+    fn dispatch(response: ResponseMessage, receiving: &Receiving) {
+        // a replayed authentication hands out a new session too
+        if let ResponseBody::Authenticated {
+            session: Some(token),
+            ..
+        } = &response.body
+        {
+            *receiving.session.lock() = Some(token.clone());
+        }
+        // nobody asked, the server took the client certificate for a user
+        if let (None, ResponseBody::Authenticated { role, .. }) = (response.id, &response.body) {
+            let _ = receiving
+                .connection_event_tx
+                .try_send(ConnectionEvent::Authenticated(*role));
+            return;
+        }
+        let waiter = match response.id {
+            Some(REPLAY_ID) => return,
+            Some(id) => {
+                debug!(
+                    id,
+                    trace_id = response.trace_id.as_deref(),
+                    "response received"
+                );
+                receiving.waiting.get(&id).map(|waiter| waiter.clone())
+            }
+            None => {
+                let _ = receiving.monitor_event_tx.try_send(response);
+                return;
+            }
+        };
+        let _ = match waiter {
+            Some(waiter) => waiter.try_send(response),
+            None => receiving.response_message_tx.try_send(response),
+        };
+    }
 
-        let response = ResponseMessage::deserialize(reader).unwrap();
+    fn receive_message(response_bytes: &[u8]) -> Result<ResponseMessage, HouseExchangeError> {
+        let reader = Reader::get_root(response_bytes).map_err(DeserializationError::Reader)?;
 
-        response_message_tx
-            .send(response)
-            .await
-            .map_err(|e| SendNotifyError(server_address, e.to_string()))
+        Ok(ResponseMessage::deserialize(reader)?)
     }
 }
@@ -1,5 +1,5 @@
-use std::borrow::BorrowMut;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -10,131 +10,610 @@ use serde::{Deserialize, Serialize};
 use tokio::net::ToSocketAddrs;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::Mutex;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use exchange_protocol::domain::{Message, NotifyMessage};
+use exchange_protocol::domain::{Message, NotifyMessage, PeerIdentity};
+use exchange_protocol::metrics::MetricsExporter;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::shutdown::{ShutdownHandle, ShutdownSignal};
+use house::access::domain::{Action, SessionToken, UserName};
+use house::access::rate_limiter::{Client, RateLimiter, RateLimits};
+use house::access::sessions::Sessions;
+use house::access::user_store::UserStore;
+use house::audit::audit_log::AuditLog;
+use house::audit::audited_inventory::AuditedInventory;
+use house::audit::domain::{is_valid_trace_id, new_trace_id, AuditContext, AuditSource};
+use house::audit::memory_audit_log::MemoryAuditLog;
 use house::devices::power_socket::PowerSocket;
 use house::devices::temperature_sensor::TemperatureSensor;
+use house::errors::intelligent_house_error::AccessError;
+use house::errors::intelligent_house_error::AccessError::{
+    AccessInsecureChannel, AccessThrottled, AccessUnauthenticated,
+};
 use house::errors::intelligent_house_error::IntelligentHouseError;
 use house::errors::intelligent_house_error::InventoryError;
+use house::errors::intelligent_house_error::InventoryError::InventoryDeviceOffline;
+use house::history::device_history::DeviceHistory;
+use house::history::history_recorder::HistoryRecorder;
+use house::history::memory_device_history::MemoryDeviceHistory;
 use house::house::domain::*;
 use house::inventory::device_inventory::DeviceInventory;
-use house::inventory::domain::DeviceItem;
+use house::inventory::domain::{check_online, report_failure, with_health, DeviceItem};
+use house::inventory::health_watchdog::HealthWatchdog;
+use house::simulation::clock::{Clock, SystemClock};
 use tcp_exchange::tcp_server::TcpServer;
+use tcp_exchange::tls::ServerTls;
+use tcp_exchange::unix_server::{LocalAccess, UnixServer};
+use tcp_exchange::ws_server::WsServer;
+use udp_exchange::encryption::Encryption;
 use udp_exchange::udp_server::UdpServer;
 
 use crate::domain::RequestBody::*;
 use crate::domain::ResponseBody::*;
-use crate::domain::{DeviceData, DeviceLocation, RequestBody, RequestMessage, ResponseMessage};
+use crate::domain::{
+    DeviceData, DeviceLocation, Encoding, RequestBody, RequestMessage, ResponseBody,
+    ResponseMessage,
+};
 use crate::error::HouseExchangeError;
+use crate::metrics;
+
+const HISTORY_SAMPLING_PERIOD: Duration = Duration::from_secs(1);
+const DEVICE_OFFLINE_TIMEOUT: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_PERIOD: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct HouseServer {
-    pub tcp_address: SocketAddr,
+    pub tcp_address: Option<SocketAddr>,
+    pub unix_path: Option<PathBuf>,
+    pub ws_address: Option<SocketAddr>,
     pub udp_address: SocketAddr,
+    pub metrics_address: Option<SocketAddr>,
+    shutdown: ShutdownHandle,
+}
+
+/// Where a house server takes connections. Tcp, a unix socket and WebSocket serve side
+/// by side or one without the others, udp is always there for the monitors.
+pub struct Listeners<Addrs> {
+    pub tcp: Option<Addrs>,
+    pub tls: Option<ServerTls>,
+    pub unix: Option<(PathBuf, LocalAccess)>,
+    pub ws: Option<Addrs>,
+    pub udp: Addrs,
+    pub encryption: Option<Encryption>,
+    pub metrics: Option<Addrs>,
+}
+
+impl<Addrs> Listeners<Addrs> {
+    pub fn new(tcp: Addrs, udp: Addrs) -> Self {
+        Listeners {
+            tcp: Some(tcp),
+            tls: None,
+            unix: None,
+            ws: None,
+            udp,
+            encryption: None,
+            metrics: None,
+        }
+    }
+
+    /// Only clients on the same machine, running as the server's user, connect.
+    pub fn local<P: AsRef<Path>>(path: P, udp: Addrs) -> Self {
+        Listeners {
+            tcp: None,
+            tls: None,
+            unix: Some((path.as_ref().to_path_buf(), LocalAccess::Owner)),
+            ws: None,
+            udp,
+            encryption: None,
+            metrics: None,
+        }
+    }
+
+    /// Tcp clients connect over TLS. With client authentication a client whose certificate
+    /// has a user's name as its common name is that user, without a password.
+    pub fn with_tls(mut self, tls: ServerTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Monitors and udp requests are encrypted, forged and replayed datagrams are dropped.
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn with_unix_socket<P: AsRef<Path>>(mut self, path: P, access: LocalAccess) -> Self {
+        self.unix = Some((path.as_ref().to_path_buf(), access));
+        self
+    }
+
+    /// Web pages talk JSON or flexbuffers over WebSocket.
+    pub fn with_websocket(mut self, address: Addrs) -> Self {
+        self.ws = Some(address);
+        self
+    }
+
+    /// Prometheus scrapes the transport and request metrics at `/metrics`.
+    pub fn with_metrics(mut self, address: Addrs) -> Self {
+        self.metrics = Some(address);
+        self
+    }
+}
+
+/// The servers monitor data goes out through, by the channel a monitor came on.
+struct Servers {
+    tcp: Option<Arc<TcpServer>>,
+    unix: Option<Arc<UnixServer>>,
+    ws: Option<Arc<WsServer>>,
+    udp: Arc<Mutex<UdpServer>>,
+}
+
+/// Shared state the request processing works on, one per server.
+#[derive(Clone)]
+struct HouseContext<T, H, U, A> {
+    inventory: T,
+    history: H,
+    users: U,
+    audit: A,
+    monitors: Arc<DashMap<Connection, Monitor>>,
+    sessions: Arc<DashMap<Connection, Session>>,
+    tokens: Sessions,
+    limiter: RateLimiter,
+}
+
+/// A client by the channel it came on, tcp and udp ports are apart and so are its
+/// sessions and monitors on each.
+type Connection = (AuditSource, SocketAddr);
+
+/// The user a connection authenticated as and the token its udp requests carry.
+struct Session {
+    user_name: UserName,
+    token: SessionToken,
+}
+
+/// A device a client watches.
+#[derive(Clone)]
+struct Monitor {
+    location: DeviceLocation,
+    encoding: Encoding,
 }
 
 impl HouseServer {
     pub async fn start<Addrs: ToSocketAddrs>(
         device_inventory: impl DeviceInventory + Send + Sync + Clone + 'static,
+        users: impl UserStore + Send + Sync + Clone + 'static,
         tcp_address: Addrs,
         udp_address: Addrs,
     ) -> Result<HouseServer, HouseExchangeError> {
-        let tcp_server = TcpServer::start(tcp_address).await?;
-        let udp_server = UdpServer::start(udp_address).await?;
+        Self::start_with(
+            device_inventory,
+            MemoryDeviceHistory::default(),
+            users,
+            MemoryAuditLog::default(),
+            SystemClock,
+            Listeners::new(tcp_address, udp_address),
+            RateLimits::default(),
+        )
+        .await
+    }
 
+    pub async fn start_with<Addrs: ToSocketAddrs>(
+        device_inventory: impl DeviceInventory + Send + Sync + Clone + 'static,
+        device_history: impl DeviceHistory + Send + Sync + Clone + 'static,
+        users: impl UserStore + Send + Sync + Clone + 'static,
+        audit: impl AuditLog + Send + Sync + Clone + 'static,
+        clock: impl Clock + Send + Sync + Clone + 'static,
+        listeners: Listeners<Addrs>,
+        rate_limits: RateLimits,
+    ) -> Result<HouseServer, HouseExchangeError> {
+        let tcp_server = match (listeners.tcp, listeners.tls) {
+            (Some(tcp_address), Some(tls)) => Some(TcpServer::start_tls(tcp_address, tls).await?),
+            (Some(tcp_address), None) => Some(TcpServer::start(tcp_address).await?),
+            (None, _) => None,
+        };
+        let unix_server = match listeners.unix {
+            Some((path, access)) => {
+                Some(UnixServer::start_with(path, ExchangeOptions::default(), access).await?)
+            }
+            None => None,
+        };
+        let ws_server = match listeners.ws {
+            Some(ws_address) => Some(WsServer::start(ws_address).await?),
+            None => None,
+        };
+        let udp_server = match listeners.encryption {
+            Some(encryption) => UdpServer::start_encrypted(listeners.udp, encryption).await?,
+            None => UdpServer::start(listeners.udp).await?,
+        };
+        let exporter = match listeners.metrics {
+            Some(metrics_address) => Some(MetricsExporter::start(metrics_address).await?),
+            None => None,
+        };
+
+        let (shutdown, signal) = ShutdownHandle::new();
         let house_server = HouseServer {
-            tcp_address: tcp_server.address,
+            tcp_address: tcp_server.as_ref().map(|server| server.address),
+            unix_path: unix_server.as_ref().map(|server| server.path.clone()),
+            ws_address: ws_server.as_ref().map(|server| server.address),
             udp_address: udp_server.address,
+            metrics_address: exporter.as_ref().map(|exporter| exporter.address),
+            shutdown,
         };
+        let mut shutdowns = vec![udp_server.shutdown_handle()];
+        shutdowns.extend(tcp_server.iter().map(TcpServer::shutdown_handle));
+        shutdowns.extend(unix_server.iter().map(UnixServer::shutdown_handle));
+        shutdowns.extend(ws_server.iter().map(WsServer::shutdown_handle));
+        shutdowns.extend(exporter.iter().map(MetricsExporter::shutdown_handle));
 
-        let udp_server = Arc::new(Mutex::new(udp_server));
-        let device_monitors = Arc::new(DashMap::<SocketAddr, DeviceLocation>::new());
+        let servers = Servers {
+            tcp: tcp_server.map(Arc::new),
+            unix: unix_server.map(Arc::new),
+            ws: ws_server.map(Arc::new),
+            udp: Arc::new(Mutex::new(udp_server)),
+        };
 
-        let inventory = device_inventory.clone();
-        let monitors = device_monitors.clone();
-        tokio::spawn(async move {
-            let messages = tcp_server.messages.clone();
-            Self::process_exchange(messages, inventory, monitors).await;
-        });
+        let recorder = HistoryRecorder::new(
+            device_inventory.clone(),
+            device_history.clone(),
+            clock.clone(),
+        )
+        .start(HISTORY_SAMPLING_PERIOD);
+
+        let watchdog = HealthWatchdog::new(device_inventory.clone(), clock, DEVICE_OFFLINE_TIMEOUT)
+            .start(HEALTH_CHECK_PERIOD);
 
-        let inventory = device_inventory.clone();
-        let monitors = device_monitors.clone();
-        let server = udp_server.clone();
+        let mut supervisor_signal = signal.clone();
         tokio::spawn(async move {
-            let messages = server.lock().await.messages.clone();
-            Self::process_exchange(messages, inventory, monitors).await;
+            let deadline = supervisor_signal.triggered().await;
+            let drain_timeout = deadline.saturating_duration_since(Instant::now());
+            for shutdown in &shutdowns {
+                shutdown.shutdown_within(drain_timeout);
+            }
+            recorder.abort();
+            watchdog.abort();
+            for shutdown in &shutdowns {
+                shutdown.stopped().await;
+            }
         });
 
-        Self::broadcast_monitors(device_monitors, device_inventory, udp_server).await;
+        let context = HouseContext {
+            inventory: device_inventory,
+            history: device_history,
+            users,
+            audit,
+            monitors: Arc::new(DashMap::new()),
+            sessions: Arc::new(DashMap::new()),
+            tokens: Sessions::default(),
+            limiter: RateLimiter::new(rate_limits),
+        };
+
+        // the requests still in flight are processed until the servers have stopped
+        let streams = [
+            (
+                servers.tcp.as_ref().map(|server| server.messages.clone()),
+                AuditSource::Tcp,
+            ),
+            (
+                servers.unix.as_ref().map(|server| server.messages.clone()),
+                AuditSource::Unix,
+            ),
+            (
+                servers.ws.as_ref().map(|server| server.messages.clone()),
+                AuditSource::WebSocket,
+            ),
+        ];
+        for (messages, source) in streams {
+            if let Some(messages) = messages {
+                let stream_context = context.clone();
+                let stream_signal = signal.clone();
+                tokio::spawn(
+                    async move {
+                        let _signal = stream_signal;
+                        Self::process_exchange(messages, stream_context, source).await;
+                    }
+                    .instrument(info_span!("house_server", ?source)),
+                );
+            }
+        }
+
+        let udp_context = context.clone();
+        let server = servers.udp.clone();
+        let udp_signal = signal.clone();
+        tokio::spawn(
+            async move {
+                let _signal = udp_signal;
+                let messages = server.lock().await.messages.clone();
+                Self::process_exchange(messages, udp_context, AuditSource::Udp).await;
+            }
+            .instrument(info_span!("house_server", source = ?AuditSource::Udp)),
+        );
+
+        Self::broadcast_monitors(context.monitors, context.inventory, servers, signal).await;
 
         Ok(house_server)
     }
 
-    async fn process_exchange(
+    /// Stops the servers, waits for in-flight requests until the drain deadline and stops
+    /// the background tasks.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    async fn process_exchange<T, H, U, A>(
         messages: Arc<Mutex<Receiver<NotifyMessage>>>,
-        device_inventory: impl DeviceInventory + Clone,
-        device_monitors: Arc<DashMap<SocketAddr, DeviceLocation>>,
-    ) {
+        context: HouseContext<T, H, U, A>,
+        source: AuditSource,
+    ) where
+        T: DeviceInventory + Send + Sync + Clone,
+        H: DeviceHistory + Sync,
+        U: UserStore + Sync,
+        A: AuditLog + Send + Sync + Clone,
+    {
         while let Some(notify) = messages.lock().await.recv().await {
             match notify.message {
-                Message::Connected => {
-                    println!("house server: client {} connected", notify.address)
-                }
-                Message::Bytes(ref request_bytes) => {
-                    let result = Self::process_bytes(
-                        request_bytes,
-                        device_inventory.clone(),
-                        device_monitors.clone(),
-                        notify.address,
-                    )
-                    .await;
-
-                    match result {
-                        Ok(response_bytes) => {
-                            notify.reply(response_bytes).await.unwrap_or_else(|error| {
-                                eprintln!(
-                                    "house server: send message to client '{}' failed: {error:?}",
-                                    notify.address
-                                );
-                            })
-                        }
-                        Err(error) => eprintln!(
-                            "house server: process message from client '{}' failed: {error:?}",
-                            notify.address
-                        ),
+                Message::Connected(ref info) => {
+                    let identity = match info.peer_identity.as_deref() {
+                        Some(PeerIdentity::Certificate {
+                            common_name: Some(name),
+                            ..
+                        }) => format!("'{name}'"),
+                        Some(PeerIdentity::Process { uid, .. }) => format!("uid {uid}"),
+                        _ => String::new(),
+                    };
+                    info!(
+                        client = %notify.address,
+                        identity,
+                        version = info.version(),
+                        "client connected"
+                    );
+                    if let Some(PeerIdentity::Certificate {
+                        common_name: Some(name),
+                        ..
+                    }) = info.peer_identity.as_deref()
+                    {
+                        Self::authenticate_certificate(&context, source, &notify, name).await;
                     }
                 }
-                Message::Disconnected => {
-                    println!("house server: client {} disconnected", notify.address)
+                Message::Error(ref error) => {
+                    warn!(client = %notify.address, %error, "exchange with client failed")
+                }
+                // web pages send JSON in text frames, the rest is flexbuffers
+                Message::Bytes(ref bytes) => {
+                    Self::answer(&context, source, &notify, (bytes, Encoding::Flexbuffers)).await
+                }
+                Message::Text(ref text) => {
+                    Self::answer(&context, source, &notify, (text.as_bytes(), Encoding::Json)).await
+                }
+                // only clients reconnect
+                Message::Reconnecting { .. } => {}
+                Message::Disconnected(ref reason) => {
+                    let connection = (source, notify.address);
+                    if let Some((_, session)) = context.sessions.remove(&connection) {
+                        context.tokens.close(&session.token);
+                    }
+                    context.monitors.remove(&connection);
+                    info!(client = %notify.address, ?reason, "client disconnected")
                 }
             }
         }
     }
 
-    async fn process_bytes(
-        bytes: &Vec<u8>,
-        device_inventory: impl DeviceInventory,
-        device_monitors: Arc<DashMap<SocketAddr, DeviceLocation>>,
-        sender_address: SocketAddr,
-    ) -> Result<Vec<u8>, HouseExchangeError> {
-        let msg_reader =
-            Reader::get_root(bytes.as_slice()).map_err(DeserializationError::Reader)?;
+    async fn answer<T, H, U, A>(
+        context: &HouseContext<T, H, U, A>,
+        source: AuditSource,
+        notify: &NotifyMessage,
+        request: (&[u8], Encoding),
+    ) where
+        T: DeviceInventory + Send + Sync + Clone,
+        H: DeviceHistory + Sync,
+        U: UserStore + Sync,
+        A: AuditLog + Send + Sync + Clone,
+    {
+        let peer = match notify.identity.as_deref() {
+            Some(PeerIdentity::Process { uid, .. }) => Client::Process(*uid),
+            _ => Client::Address(notify.address.ip()),
+        };
+        let result = Self::process_bytes(request, context, source, (notify.address, peer)).await;
 
-        let request = RequestMessage::deserialize(msg_reader)?;
+        match result {
+            Ok(response_bytes) => notify.reply(response_bytes).await.unwrap_or_else(
+                |error| warn!(client = %notify.address, ?error, "sending response failed"),
+            ),
+            Err(error) => warn!(client = %notify.address, ?error, "processing request failed"),
+        }
+    }
 
-        let response = Self::process_request(
+    /// The client certificate was verified already, its common name is taken for a user.
+    /// The client is told its role and session as if it had authenticated.
+    async fn authenticate_certificate<T, H, U, A>(
+        context: &HouseContext<T, H, U, A>,
+        source: AuditSource,
+        notify: &NotifyMessage,
+        common_name: &str,
+    ) where
+        U: UserStore + Sync,
+    {
+        let user = match context
+            .users
+            .get_user(&UserName(common_name.to_string()))
+            .await
+        {
+            Ok(user) => user,
+            Err(error) => {
+                info!(client = %notify.address, %error, "client certificate names no user");
+                return;
+            }
+        };
+        info!(client = %notify.address, user = %user.name, "authenticated by certificate");
+        let token = Self::open_session(context, (source, notify.address), user.name);
+        let authenticated = ResponseMessage::new(Authenticated {
+            role: user.role,
+            session: Some(token),
+        });
+        let sent = match Self::serialize_response(authenticated, Encoding::Flexbuffers) {
+            Ok(bytes) => notify.reply(bytes).await.map_err(HouseExchangeError::from),
+            Err(error) => Err(error),
+        };
+        sent.unwrap_or_else(
+            |error| warn!(client = %notify.address, ?error, "sending session failed"),
+        );
+    }
+
+    /// Replaces the session the connection had, its token stops working.
+    fn open_session<T, H, U, A>(
+        context: &HouseContext<T, H, U, A>,
+        connection: Connection,
+        user_name: UserName,
+    ) -> SessionToken {
+        let token = context.tokens.open(user_name.clone());
+        let session = Session {
+            user_name,
+            token: token.clone(),
+        };
+        if let Some(replaced) = context.sessions.insert(connection, session) {
+            context.tokens.close(&replaced.token);
+        }
+        token
+    }
+
+    async fn process_bytes<T, H, U, A>(
+        (bytes, encoding): (&[u8], Encoding),
+        context: &HouseContext<T, H, U, A>,
+        source: AuditSource,
+        (sender_address, peer): (SocketAddr, Client),
+    ) -> Result<Vec<u8>, HouseExchangeError>
+    where
+        T: DeviceInventory + Send + Sync + Clone,
+        H: DeviceHistory + Sync,
+        U: UserStore + Sync,
+        A: AuditLog + Send + Sync + Clone,
+    {
+        let request = match encoding {
+            Encoding::Json => serde_json::from_slice(bytes)?,
+            Encoding::Flexbuffers => {
+                let msg_reader = Reader::get_root(bytes).map_err(DeserializationError::Reader)?;
+                RequestMessage::deserialize(msg_reader)?
+            }
+        };
+
+        let request_id = request.id;
+        let trace_id = request
+            .trace_id
+            .filter(|trace_id| is_valid_trace_id(trace_id))
+            .unwrap_or_else(new_trace_id);
+        let kind = request.body.kind();
+        let span = info_span!(
+            "request",
+            %trace_id,
+            id = request_id,
+            kind,
+            client = %sender_address
+        );
+        let connection = (source, sender_address);
+        let session = request.session;
+        let user_name = Self::session_user(context, connection, session.as_ref());
+        if let Err(AccessThrottled(retry_after)) =
+            Self::admit(context, kind, peer, user_name.as_ref().ok())
+        {
+            span.in_scope(|| info!(?retry_after, "request throttled"));
+            let throttled = Throttled {
+                retry_after_ms: retry_after.as_millis() as u64,
+            };
+            return Self::serialize_response(
+                ResponseMessage {
+                    id: Some(request_id),
+                    trace_id: Some(trace_id),
+                    ..ResponseMessage::new(throttled)
+                },
+                encoding,
+            );
+        }
+        let duration = metrics::request_duration().with_label_values(&[kind]);
+        let timer = duration.start_timer();
+        let processed = Self::process_request(
             request.body,
-            device_inventory,
-            device_monitors,
-            sender_address,
+            context,
+            (source, encoding),
+            (sender_address, &trace_id),
+            user_name,
         )
-        .await?;
+        .instrument(span.clone())
+        .await;
+        let elapsed = timer.stop_and_record();
+        span.in_scope(|| match &processed {
+            Ok(_) => debug!(elapsed, "request processed"),
+            Err(error) => info!(elapsed, ?error, "request failed"),
+        });
+        let response = match processed {
+            Ok(response) => response,
+            Err(HouseExchangeError::IntelligentHouseError(error)) => {
+                ResponseMessage::new(Self::failure(error))
+            }
+            Err(error) => return Err(error),
+        };
 
-        Self::serialize_response(response)
+        Self::serialize_response(
+            ResponseMessage {
+                id: Some(request_id),
+                trace_id: Some(trace_id),
+                ..response
+            },
+            encoding,
+        )
     }
 
-    fn serialize_response(response: ResponseMessage) -> Result<Vec<u8>, HouseExchangeError> {
+    /// What a client is told about its request that failed.
+    fn failure(error: IntelligentHouseError) -> ResponseBody {
+        match error {
+            IntelligentHouseError::AccessErr(error) => AccessDenied(error.to_string()),
+            IntelligentHouseError::InventoryErr(InventoryDeviceOffline(_, _, last_seen)) => {
+                DeviceOffline { last_seen }
+            }
+            error => RequestFailed(error.to_string()),
+        }
+    }
+
+    /// The user a request is made for. A connection authenticates once, a udp request
+    /// carries the token of a connection's session.
+    fn session_user<T, H, U, A>(
+        context: &HouseContext<T, H, U, A>,
+        connection: Connection,
+        session: Option<&SessionToken>,
+    ) -> Result<UserName, AccessError> {
+        match connection {
+            (AuditSource::Udp, _) => context.tokens.get(session.ok_or(AccessUnauthenticated)?),
+            connection => context
+                .sessions
+                .get(&connection)
+                .map(|session| session.user_name.clone())
+                .ok_or(AccessUnauthenticated),
+        }
+    }
+
+    /// Counts the request against the limits of the user the client authenticated as, or
+    /// else of its peer: the host, or the user a local process runs as.
+    fn admit<T, H, U, A>(
+        context: &HouseContext<T, H, U, A>,
+        kind: &str,
+        peer: Client,
+        user_name: Option<&UserName>,
+    ) -> Result<(), AccessError> {
+        let clients: Vec<Client> = user_name
+            .map(|user_name| Client::User(user_name.clone()))
+            .into_iter()
+            .chain([peer])
+            .collect();
+        context.limiter.check(&clients, kind)
+    }
+
+    fn serialize_response(
+        response: ResponseMessage,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, HouseExchangeError> {
+        if encoding == Encoding::Json {
+            return Ok(serde_json::to_vec(&response)?);
+        }
         let mut serializer = flexbuffers::FlexbufferSerializer::new();
         response
             .serialize(&mut serializer)
@@ -143,118 +622,237 @@ impl HouseServer {
         Ok(serializer.take_buffer())
     }
 
-    async fn process_request(
+    async fn process_request<T, H, U, A>(
         request_body: RequestBody,
-        mut device_inventory: impl DeviceInventory,
-        device_monitors: Arc<DashMap<SocketAddr, DeviceLocation>>,
-        sender_address: SocketAddr,
-    ) -> Result<ResponseMessage, HouseExchangeError> {
+        context: &HouseContext<T, H, U, A>,
+        (source, encoding): (AuditSource, Encoding),
+        (sender_address, trace_id): (SocketAddr, &str),
+        user_name: Result<UserName, AccessError>,
+    ) -> Result<ResponseMessage, HouseExchangeError>
+    where
+        T: DeviceInventory + Send + Sync + Clone,
+        H: DeviceHistory + Sync,
+        U: UserStore + Sync,
+        A: AuditLog + Send + Sync + Clone,
+    {
+        // anybody can send a datagram from any address and read what is in it
+        if let (AuditSource::Udp, Authenticate { .. } | ChangeDeviceData { .. }) =
+            (source, &request_body)
+        {
+            let refused = AccessInsecureChannel(request_body.kind().to_string());
+            return Err(IntelligentHouseError::AccessErr(refused).into());
+        }
+
+        if let Authenticate { credentials } = request_body {
+            let user = context
+                .users
+                .authenticate(&credentials)
+                .await
+                .map_err(IntelligentHouseError::AccessErr)?;
+            let token = Self::open_session(context, (source, sender_address), user.name);
+            return Ok(ResponseMessage::new(Authenticated {
+                role: user.role,
+                session: Some(token),
+            }));
+        }
+
+        let user_name = user_name.map_err(IntelligentHouseError::AccessErr)?;
+        let user = context
+            .users
+            .get_user(&user_name)
+            .await
+            .map_err(IntelligentHouseError::AccessErr)?;
+        let check = |location: &DeviceLocation, action: Action| {
+            user.check(Some(&RoomName(location.room_name.clone())), action)
+                .map_err(IntelligentHouseError::AccessErr)
+        };
+
+        let device_inventory = &context.inventory;
         match request_body {
+            Authenticate { .. } => unreachable!("authentication is handled above"),
             ChangeDeviceData { location, data } => {
+                check(&location, Action::Control)?;
                 let device_name = &DeviceName(location.device_name);
                 let room_name = &RoomName(location.room_name);
-                device_inventory
-                    .borrow_mut()
-                    .change_device(room_name, device_name, |device| match data {
-                        DeviceData::PowerSocketState { enabled } => device.fold(hlist![
-                            |mut ps: PowerSocket| {
-                                ps.enabled = enabled;
-                                Ok(DeviceItem::inject(ps))
-                            },
-                            |_| Err(InventoryError::InventoryDeviceInvalid(
-                                device_name.clone(),
-                                room_name.clone()
-                            ))
-                        ]),
-                    })
+                check_online(device_inventory, room_name, device_name)
                     .await
                     .map_err(IntelligentHouseError::InventoryErr)?;
-
-                Ok(ResponseMessage {
-                    body: DeviceDataChanged,
+                let changed = AuditedInventory::new(
+                    device_inventory.clone(),
+                    context.audit.clone(),
+                    AuditContext::new(user.name.0.clone(), source, Some(sender_address))
+                        .with_trace_id(trace_id),
+                )
+                .change_device(room_name, device_name, |device| match data {
+                    DeviceData::PowerSocketState { enabled } => device.fold(hlist![
+                        |mut ps: PowerSocket| {
+                            ps.enabled = enabled;
+                            Ok(DeviceItem::inject(ps))
+                        },
+                        |_| Err(InventoryError::InventoryDeviceInvalid(
+                            device_name.clone(),
+                            room_name.clone()
+                        ))
+                    ]),
                 })
+                .await;
+                if let Err(error) = changed {
+                    report_failure(device_inventory, room_name, device_name, &error).await;
+                    return Err(IntelligentHouseError::InventoryErr(error).into());
+                }
+
+                Ok(ResponseMessage::new(DeviceDataChanged))
             }
             ShowDeviceInfo { location } => {
-                let info = device_inventory
-                    .get_info(
-                        &RoomName(location.room_name),
-                        &DeviceName(location.device_name),
-                    )
+                check(&location, Action::Read)?;
+                let device_name = &DeviceName(location.device_name);
+                let room_name = &RoomName(location.room_name);
+                let health = check_online(device_inventory, room_name, device_name)
                     .await
                     .map_err(IntelligentHouseError::InventoryErr)?;
+                let info = match device_inventory.get_info(room_name, device_name).await {
+                    Ok(info) => info,
+                    Err(error) => {
+                        report_failure(device_inventory, room_name, device_name, &error).await;
+                        return Err(IntelligentHouseError::InventoryErr(error).into());
+                    }
+                };
 
-                Ok(ResponseMessage {
-                    body: DeviceDescription(info),
-                })
+                Ok(ResponseMessage::new(DeviceDescription(with_health(
+                    info, &health,
+                ))))
             }
-            RegisterDeviceMonitor { location } => {
-                device_monitors.insert(sender_address, location);
-                Ok(ResponseMessage {
-                    body: MonitorRegistered,
-                })
+            RegisterDeviceMonitor { location, .. } => {
+                check(&location, Action::Read)?;
+                // the data goes back the way the registration came
+                context
+                    .monitors
+                    .insert((source, sender_address), Monitor { location, encoding });
+                Ok(ResponseMessage::new(MonitorRegistered))
             }
             RemoveDeviceMonitor => {
-                device_monitors.remove(&sender_address);
-                Ok(ResponseMessage {
-                    body: MonitorRemoved,
-                })
+                context.monitors.remove(&(source, sender_address));
+                Ok(ResponseMessage::new(MonitorRemoved))
+            }
+            ShowDeviceHistory { location, from, to } => {
+                check(&location, Action::Read)?;
+                let series = context
+                    .history
+                    .get_series(
+                        &RoomName(location.room_name),
+                        &DeviceName(location.device_name),
+                        from,
+                        to,
+                    )
+                    .await
+                    .map_err(IntelligentHouseError::HistoryErr)?;
+
+                Ok(ResponseMessage::new(DeviceHistory(series)))
             }
         }
     }
 
     async fn broadcast_monitors(
-        device_monitors: Arc<DashMap<SocketAddr, DeviceLocation>>,
+        device_monitors: Arc<DashMap<Connection, Monitor>>,
         device_inventory: impl DeviceInventory + Clone + Send + Sync + 'static,
-        udp_server: Arc<Mutex<UdpServer>>,
+        servers: Servers,
+        mut signal: ShutdownSignal,
     ) {
         tokio::spawn(async move {
             loop {
-                for dm in device_monitors.iter() {
-                    let (client_address, location) = dm.pair();
-
-                    let data = Self::get_device_data(location, device_inventory.clone())
-                        .await
-                        .unwrap();
-                    udp_server.lock().await
-                      .send(client_address, data.as_slice())
-                      .await
-                      .unwrap_or_else(|error| {
-                          eprintln!(
-                              "house server: sending message to '{client_address}' failed: {error:?}"
-                          )
-                      });
+                // no shard of the map stays locked while the data goes out
+                let monitors: Vec<(Connection, Monitor)> = device_monitors
+                    .iter()
+                    .map(|dm| (*dm.key(), dm.value().clone()))
+                    .collect();
+                let mut fanout = 0;
+                for ((source, client_address), monitor) in &monitors {
+                    let data = match Self::get_device_data(
+                        &monitor.location,
+                        monitor.encoding,
+                        device_inventory.clone(),
+                    )
+                    .await
+                    {
+                        Ok(data) => data,
+                        Err(error) => {
+                            warn!(client = %client_address, ?error, "reading monitored device failed");
+                            continue;
+                        }
+                    };
+                    // a client whose queue is full misses the data, the others don't wait
+                    let streams = (&servers.tcp, &servers.unix, &servers.ws);
+                    let sent = match (source, streams) {
+                        (AuditSource::Tcp, (Some(tcp_server), _, _)) => {
+                            tcp_server.try_send(client_address, &data)
+                        }
+                        (AuditSource::Unix, (_, Some(unix_server), _)) => {
+                            unix_server.try_send(client_address, &data)
+                        }
+                        (AuditSource::WebSocket, (_, _, Some(ws_server))) => {
+                            ws_server.try_send(client_address, &data)
+                        }
+                        _ => servers.udp.lock().await.send(client_address, &data).await,
+                    };
+                    match sent {
+                        Ok(()) => fanout += 1,
+                        Err(error) => {
+                            warn!(client = %client_address, ?error, "sending monitor data failed")
+                        }
+                    }
+                }
+                if !monitors.is_empty() {
+                    metrics::monitor_fanout().observe(fanout as f64);
+                }
+                tokio::select! {
+                    _ = sleep(Duration::from_millis(500)) => {}
+                    _ = signal.triggered() => break,
                 }
-                sleep(Duration::from_millis(500)).await;
             }
-        });
+        }.instrument(info_span!("monitors")));
     }
 
     async fn get_device_data(
         location: &DeviceLocation,
-        device_inventory: impl DeviceInventory,
+        encoding: Encoding,
+        device_inventory: impl DeviceInventory + Sync,
     ) -> Result<Vec<u8>, HouseExchangeError> {
-        let device = device_inventory
-            .get_device(
-                &RoomName(location.room_name.clone()),
-                &DeviceName(location.device_name.clone()),
-            )
-            .await
-            .map_err(IntelligentHouseError::InventoryErr)?;
+        let room_name = &RoomName(location.room_name.clone());
+        let device_name = &DeviceName(location.device_name.clone());
+
+        let health = match check_online(&device_inventory, room_name, device_name).await {
+            Ok(health) => health,
+            Err(InventoryDeviceOffline(_, _, last_seen)) => {
+                let offline = ResponseMessage::new(DeviceOffline { last_seen });
+                return Self::serialize_response(offline, encoding);
+            }
+            Err(error) => return Err(IntelligentHouseError::InventoryErr(error).into()),
+        };
+
+        let device = match device_inventory.get_device(room_name, device_name).await {
+            Ok(device) => device,
+            Err(error) => {
+                report_failure(&device_inventory, room_name, device_name, &error).await;
+                return Err(IntelligentHouseError::InventoryErr(error).into());
+            }
+        };
 
         let body = device.fold(hlist![
             |ps: PowerSocket| {
                 PowerSocketInfo {
                     enabled: ps.enabled,
                     power: ps.power(),
+                    health,
                 }
             },
             |ts: TemperatureSensor| {
                 TemperatureSensorInfo {
                     temperature: ts.current_temperature(),
+                    health,
                 }
             }
         ]);
 
-        Self::serialize_response(ResponseMessage { body })
+        Self::serialize_response(ResponseMessage::new(body), encoding)
     }
 }
@@ -6,3 +6,4 @@ pub mod domain;
 pub mod error;
 pub mod house_client;
 pub mod house_server;
+pub mod metrics;
@@ -0,0 +1,36 @@
+use std::sync::OnceLock;
+
+use prometheus::{Histogram, HistogramOpts, HistogramVec};
+
+use exchange_protocol::metrics::register;
+
+/// How long requests take to process, by the `RequestBody` variant.
+pub fn request_duration() -> &'static HistogramVec {
+    static REQUEST_DURATION: OnceLock<HistogramVec> = OnceLock::new();
+    REQUEST_DURATION.get_or_init(|| {
+        register(
+            HistogramVec::new(
+                HistogramOpts::new(
+                    "house_request_duration_seconds",
+                    "Time from decoding a request to its response",
+                ),
+                &["request"],
+            )
+            .expect("valid metric"),
+        )
+    })
+}
+
+/// How many monitors got data in one round of the broadcast.
+pub fn monitor_fanout() -> &'static Histogram {
+    static MONITOR_FANOUT: OnceLock<Histogram> = OnceLock::new();
+    MONITOR_FANOUT.get_or_init(|| {
+        register(
+            Histogram::with_opts(
+                HistogramOpts::new("house_monitor_fanout", "Monitors sent data per round")
+                    .buckets(vec![1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1000.0]),
+            )
+            .expect("valid metric"),
+        )
+    })
+}
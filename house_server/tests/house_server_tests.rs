@@ -0,0 +1,641 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message as Frame;
+
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::Message;
+use exchange_protocol::options::ExchangeOptions;
+use house::access::domain::{Credentials, Role, UserName};
+use house::access::rate_limiter::{Client, RateLimit, RateLimits};
+use house::audit::memory_audit_log::MemoryAuditLog;
+use house::history::memory_device_history::MemoryDeviceHistory;
+use house::simulation::clock::SystemClock;
+use house::ThreeRoomNames;
+use house_server::domain::RequestBody::*;
+use house_server::domain::ResponseBody::*;
+use house_server::domain::{
+    ConnectionEvent, DeviceData, DeviceLocation, RequestBody, RequestMessage, ResponseBody,
+    ResponseMessage,
+};
+use house_server::error::HouseExchangeError;
+use house_server::house_client::{ClientOptions, HouseClient};
+use house_server::house_server::{HouseServer, Listeners};
+use tokio::net::{UdpSocket, UnixStream};
+use tokio::sync::mpsc;
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa};
+use tcp_exchange::tls::{root_store, Certificate, ClientTls, PrivateKey, ServerTls};
+use tcp_exchange::unix_server::LocalAccess;
+use udp_exchange::encryption::{Encryption, KEY_SIZE};
+use udp_exchange::udp_client::UdpClient;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const KEY: [u8; KEY_SIZE] = [7; KEY_SIZE];
+
+async fn start_server() -> HouseServer {
+    let names = ThreeRoomNames::default();
+    HouseServer::start(
+        house::mk_three_rooms_inventory(names.clone()),
+        house::mk_three_rooms_users(names),
+        "127.0.0.1:0",
+        "127.0.0.1:0",
+    )
+    .await
+    .unwrap()
+}
+
+async fn connect(server: &HouseServer) -> HouseClient {
+    let local: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    HouseClient::connect(
+        "test".to_string(),
+        server.tcp_address.unwrap(),
+        server.udp_address,
+        local,
+    )
+    .await
+    .unwrap()
+}
+
+fn credentials(name: &str, password: &str) -> Credentials {
+    Credentials {
+        user_name: UserName(name.to_string()),
+        password: password.to_string(),
+    }
+}
+
+fn location(room_name: &str, device_name: &str) -> DeviceLocation {
+    DeviceLocation {
+        room_name: room_name.to_string(),
+        device_name: device_name.to_string(),
+    }
+}
+
+/// Issues the certificates of the server and its clients.
+struct Authority(rcgen::Certificate);
+
+impl Authority {
+    fn new() -> Authority {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "house ca");
+        Authority(rcgen::Certificate::from_params(params).unwrap())
+    }
+
+    fn tls_roots(&self) -> tcp_exchange::tls::RootCertStore {
+        root_store(&[Certificate(self.0.serialize_der().unwrap())]).unwrap()
+    }
+
+    fn issue(&self, common_name: &str) -> (Vec<Certificate>, PrivateKey) {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        let issued = rcgen::Certificate::from_params(params).unwrap();
+        (
+            vec![Certificate(
+                issued.serialize_der_with_signer(&self.0).unwrap(),
+            )],
+            PrivateKey(issued.serialize_private_key_der()),
+        )
+    }
+
+    fn client_tls(&self, common_name: &str) -> ClientTls {
+        let (certificates, key) = self.issue(common_name);
+        ClientTls::with_identity(self.tls_roots(), "localhost", certificates, key).unwrap()
+    }
+}
+
+/// Passes datagrams between a udp client and the server, keeping what the client sent.
+struct Relay {
+    address: SocketAddr,
+    socket: Arc<UdpSocket>,
+    server: SocketAddr,
+    sent: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl Relay {
+    async fn start(server: SocketAddr) -> Relay {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let (sent_tx, sent) = mpsc::unbounded_channel();
+        let relay = socket.clone();
+        tokio::spawn(async move {
+            let mut client = None;
+            let mut buffer = vec![0; u16::MAX as usize];
+            while let Ok((size, from)) = relay.recv_from(&mut buffer).await {
+                let datagram = &buffer[..size];
+                if from == server {
+                    if let Some(client) = client {
+                        let _ = relay.send_to(datagram, client).await;
+                    }
+                } else {
+                    client = Some(from);
+                    let _ = sent_tx.send(datagram.to_vec());
+                    let _ = relay.send_to(datagram, server).await;
+                }
+            }
+        });
+        Relay {
+            address: socket.local_addr().unwrap(),
+            socket,
+            server,
+            sent,
+        }
+    }
+
+    /// Sends a datagram to the server as if the client sent it.
+    async fn inject(&self, datagram: &[u8]) {
+        self.socket.send_to(datagram, self.server).await.unwrap();
+    }
+}
+
+/// Sends a request in a datagram of its own, the way a forged one comes.
+async fn udp_request(client: &mut UdpClient, body: RequestBody) -> ResponseBody {
+    let bytes = flexbuffers::to_vec(RequestMessage::new(body)).unwrap();
+    client.send(&bytes).await.unwrap();
+    let mut messages = client.messages.lock().await;
+    loop {
+        match tokio::time::timeout(TIMEOUT, messages.recv())
+            .await
+            .unwrap()
+        {
+            Some(Message::Bytes(bytes)) => {
+                return flexbuffers::from_slice::<ResponseMessage>(&bytes)
+                    .unwrap()
+                    .body
+            }
+            Some(_) => continue,
+            None => panic!("udp client stopped"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_denied_requests_are_answered() {
+    let server = start_server().await;
+    let client = connect(&server).await;
+
+    let response = client
+        .send_and_receive(
+            RequestMessage::new(ShowDeviceInfo {
+                location: location("kitchen", "socket4"),
+            })
+            .with_trace_id("unauthenticated"),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(response.body, AccessDenied(_)));
+    assert!(response.id.is_some());
+    assert_eq!(response.trace_id.as_deref(), Some("unauthenticated"));
+
+    assert!(matches!(
+        client.authenticate(credentials("guest", "wrong")).await,
+        Err(HouseExchangeError::AuthenticationError(_))
+    ));
+
+    client
+        .authenticate(credentials("guest", "guest"))
+        .await
+        .unwrap();
+    let response = client
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: location("kitchen", "socket4"),
+            data: DeviceData::PowerSocketState { enabled: false },
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, AccessDenied(_)));
+
+    let response = client
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: location("lounge", "nothing"),
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, RequestFailed(_)));
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_udp_requests_need_the_session_of_a_connection() {
+    let server = start_server().await;
+    let client = connect(&server).await;
+    client
+        .authenticate(credentials("owner", "owner"))
+        .await
+        .unwrap();
+
+    // from a port of the same host, with neither credentials nor session
+    let mut forged = UdpClient::connect_with(
+        server.udp_address,
+        "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+        ExchangeOptions::new(LengthPrefixedCodec::datagram()),
+    )
+    .await
+    .unwrap();
+    let refused = [
+        Authenticate {
+            credentials: credentials("owner", "owner"),
+        },
+        ChangeDeviceData {
+            location: location("kitchen", "socket4"),
+            data: DeviceData::PowerSocketState { enabled: false },
+        },
+        RegisterDeviceMonitor {
+            location: location("kitchen", "socket4"),
+            reliable: false,
+        },
+    ];
+    for body in refused {
+        assert!(matches!(
+            udp_request(&mut forged, body).await,
+            AccessDenied(_)
+        ));
+    }
+
+    // the house client's udp requests carry the session of its tcp connection
+    let response = client
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: location("kitchen", "socket4"),
+            reliable: false,
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, MonitorRegistered));
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_throttled_requests_are_answered() {
+    let names = ThreeRoomNames::default();
+    let server = HouseServer::start_with(
+        house::mk_three_rooms_inventory(names.clone()),
+        MemoryDeviceHistory::default(),
+        house::mk_three_rooms_users(names),
+        MemoryAuditLog::default(),
+        SystemClock,
+        Listeners::new("127.0.0.1:0", "127.0.0.1:0"),
+        RateLimits::default().with_request("ShowDeviceInfo", RateLimit::per_minute(1)),
+    )
+    .await
+    .unwrap();
+    let mut client = connect(&server).await;
+    client
+        .authenticate(credentials("owner", "owner"))
+        .await
+        .unwrap();
+
+    let show = || {
+        RequestMessage::new(ShowDeviceInfo {
+            location: location("kitchen", "socket4"),
+        })
+    };
+    let response = client.send_and_receive(show()).await.unwrap();
+    assert!(matches!(response.body, DeviceDescription(_)));
+
+    let request_id = client
+        .send(show().with_trace_id("throttled"))
+        .await
+        .unwrap();
+    let response = tokio::time::timeout(TIMEOUT, client.response_message_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        response.body,
+        Throttled { retry_after_ms } if retry_after_ms > 50_000
+    ));
+    assert_eq!(response.id, Some(request_id));
+    assert_eq!(response.trace_id.as_deref(), Some("throttled"));
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_local_clients_are_limited_by_their_user() {
+    let path =
+        std::env::temp_dir().join(format!("house_server_limits_{}.sock", std::process::id()));
+    let uid = UnixStream::pair().unwrap().0.peer_cred().unwrap().uid();
+    let names = ThreeRoomNames::default();
+    let server = HouseServer::start_with(
+        house::mk_three_rooms_inventory(names.clone()),
+        MemoryDeviceHistory::default(),
+        house::mk_three_rooms_users(names),
+        MemoryAuditLog::default(),
+        SystemClock,
+        Listeners::new("127.0.0.1:0", "127.0.0.1:0").with_unix_socket(&path, LocalAccess::Owner),
+        RateLimits::default().with_client(Client::Process(uid), RateLimit::per_minute(1)),
+    )
+    .await
+    .unwrap();
+    let connect_local = || {
+        HouseClient::connect_local_with(
+            "local".to_string(),
+            &path,
+            server.udp_address,
+            "127.0.0.1:0".parse().unwrap(),
+            ClientOptions::default(),
+        )
+    };
+    let first = connect_local().await.unwrap();
+    let second = connect_local().await.unwrap();
+    let remote = connect(&server).await;
+
+    let show = || {
+        RequestMessage::new(ShowDeviceInfo {
+            location: location("kitchen", "socket4"),
+        })
+    };
+    let response = first.send_and_receive(show()).await.unwrap();
+    assert!(matches!(response.body, AccessDenied(_)));
+    // another connection of the same user shares the limit, a tcp client has none
+    let response = second.send_and_receive(show()).await.unwrap();
+    assert!(matches!(response.body, Throttled { .. }));
+    for _ in 0..3 {
+        let response = remote.send_and_receive(show()).await.unwrap();
+        assert!(matches!(response.body, AccessDenied(_)));
+    }
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_websocket_frames_tell_the_encoding() {
+    let names = ThreeRoomNames::default();
+    let server = HouseServer::start_with(
+        house::mk_three_rooms_inventory(names.clone()),
+        MemoryDeviceHistory::default(),
+        house::mk_three_rooms_users(names),
+        MemoryAuditLog::default(),
+        SystemClock,
+        Listeners::new("127.0.0.1:0", "127.0.0.1:0").with_websocket("127.0.0.1:0"),
+        RateLimits::default(),
+    )
+    .await
+    .unwrap();
+    let url = format!("ws://{}", server.ws_address.unwrap());
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let authenticate = || {
+        RequestMessage::new(Authenticate {
+            credentials: credentials("owner", "owner"),
+        })
+    };
+
+    let json = serde_json::to_string(&authenticate()).unwrap();
+    socket.send(Frame::Text(json)).await.unwrap();
+    let response = match tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap() {
+        Some(Ok(Frame::Text(text))) => serde_json::from_str::<ResponseMessage>(&text).unwrap(),
+        frame => panic!("expected a text frame, got {frame:?}"),
+    };
+    assert!(matches!(response.body, Authenticated { .. }));
+
+    // what is in a binary frame is flexbuffers, even if it looks like JSON
+    let json = serde_json::to_vec(&authenticate()).unwrap();
+    socket.send(Frame::Binary(json)).await.unwrap();
+    socket
+        .send(Frame::Binary(flexbuffers::to_vec(authenticate()).unwrap()))
+        .await
+        .unwrap();
+    let response = match tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap() {
+        Some(Ok(Frame::Binary(bytes))) => {
+            flexbuffers::from_slice::<ResponseMessage>(&bytes).unwrap()
+        }
+        frame => panic!("expected a binary frame, got {frame:?}"),
+    };
+    assert!(matches!(response.body, Authenticated { .. }));
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_get_their_own_responses_beside_monitor_data() {
+    let server = start_server().await;
+    let mut client = connect(&server).await;
+    client
+        .authenticate(credentials("owner", "owner"))
+        .await
+        .unwrap();
+    let response = client
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: location("kitchen", "socket4"),
+            reliable: true,
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, MonitorRegistered));
+
+    let mut ids = HashSet::new();
+    let mut pushes = 0;
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    // the server pushes every half second, the rounds run across a few of them
+    for round in 0.. {
+        let ask = |n: u32| {
+            let trace_id = format!("round-{}-{}", round, n);
+            let request = RequestMessage::new(ShowDeviceInfo {
+                location: location("kitchen", "socket4"),
+            })
+            .with_trace_id(&trace_id);
+            let client = &client;
+            async move { (trace_id, client.send_and_receive(request).await.unwrap()) }
+        };
+        let responses = tokio::join!(ask(0), ask(1), ask(2), ask(3));
+        for (trace_id, response) in [responses.0, responses.1, responses.2, responses.3] {
+            assert_eq!(response.trace_id, Some(trace_id));
+            assert!(matches!(response.body, DeviceDescription(_)));
+            assert!(ids.insert(response.id.unwrap()));
+        }
+
+        while let Ok(push) = client.monitor_event_rx.try_recv() {
+            assert_eq!(push.id, None);
+            pushes += 1;
+        }
+        if pushes >= 2 {
+            break;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "no monitor data came"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(client.response_message_rx.try_recv().is_err());
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_clients_are_authenticated_by_certificate_over_tls() {
+    let authority = Authority::new();
+    let (certificates, key) = authority.issue("house server");
+    let tls = ServerTls::with_client_auth(certificates, key, authority.tls_roots()).unwrap();
+    let names = ThreeRoomNames::default();
+    let server = HouseServer::start_with(
+        house::mk_three_rooms_inventory(names.clone()),
+        MemoryDeviceHistory::default(),
+        house::mk_three_rooms_users(names),
+        MemoryAuditLog::default(),
+        SystemClock,
+        Listeners::new("127.0.0.1:0", "127.0.0.1:0").with_tls(tls),
+        RateLimits::default(),
+    )
+    .await
+    .unwrap();
+    let connect = |options: ClientOptions| {
+        HouseClient::connect_with(
+            "test".to_string(),
+            server.tcp_address.unwrap(),
+            server.udp_address,
+            "127.0.0.1:0".parse().unwrap(),
+            options.with_reconnect(None),
+        )
+    };
+
+    // neither in plain text nor without a certificate
+    assert!(connect(ClientOptions::default()).await.is_err());
+    let anonymous = ClientTls::new(authority.tls_roots(), "localhost").unwrap();
+    assert!(connect(ClientOptions::default().with_tls(anonymous))
+        .await
+        .is_err());
+
+    // the owner's certificate is as good as the owner's password
+    let mut owner = connect(ClientOptions::default().with_tls(authority.client_tls("owner")))
+        .await
+        .unwrap();
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, owner.connection_event_rx.recv())
+            .await
+            .unwrap(),
+        Some(ConnectionEvent::Authenticated(Role::Owner))
+    );
+    let response = owner
+        .send_and_receive(RequestMessage::new(ChangeDeviceData {
+            location: location("kitchen", "socket4"),
+            data: DeviceData::PowerSocketState { enabled: false },
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, DeviceDataChanged));
+    // and the session it got serves the udp requests
+    let response = owner
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: location("kitchen", "socket4"),
+            reliable: false,
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, MonitorRegistered));
+
+    // a certificate naming nobody still needs a password
+    let stranger = connect(ClientOptions::default().with_tls(authority.client_tls("stranger")))
+        .await
+        .unwrap();
+    let response = stranger
+        .send_and_receive(RequestMessage::new(ShowDeviceInfo {
+            location: location("kitchen", "socket4"),
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, AccessDenied(_)));
+    assert_eq!(
+        stranger
+            .authenticate(credentials("guest", "guest"))
+            .await
+            .unwrap(),
+        Role::Guest
+    );
+
+    server.shutdown_handle().shutdown();
+}
+
+#[tokio::test]
+async fn test_encrypted_udp_drops_tampered_and_replayed_datagrams() {
+    let names = ThreeRoomNames::default();
+    let server = HouseServer::start_with(
+        house::mk_three_rooms_inventory(names.clone()),
+        MemoryDeviceHistory::default(),
+        house::mk_three_rooms_users(names),
+        MemoryAuditLog::default(),
+        SystemClock,
+        Listeners::new("127.0.0.1:0", "127.0.0.1:0").with_encryption(Encryption::pre_shared(KEY)),
+        RateLimits::default(),
+    )
+    .await
+    .unwrap();
+
+    // the house client's monitors go over the encrypted channel
+    let client = HouseClient::connect_with(
+        "test".to_string(),
+        server.tcp_address.unwrap(),
+        server.udp_address,
+        "127.0.0.1:0".parse().unwrap(),
+        ClientOptions::default().with_encryption(Encryption::pre_shared(KEY)),
+    )
+    .await
+    .unwrap();
+    client
+        .authenticate(credentials("owner", "owner"))
+        .await
+        .unwrap();
+    let response = client
+        .send_and_receive(RequestMessage::new(RegisterDeviceMonitor {
+            location: location("kitchen", "socket4"),
+            reliable: false,
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(response.body, MonitorRegistered));
+
+    let mut relay = Relay::start(server.udp_address).await;
+    let mut udp_client = UdpClient::connect_encrypted_with(
+        relay.address,
+        "127.0.0.1:0".parse().unwrap(),
+        ExchangeOptions::new(LengthPrefixedCodec::datagram()),
+        Encryption::pre_shared(KEY),
+    )
+    .await
+    .unwrap();
+    while relay.sent.try_recv().is_ok() {}
+    let authenticate = || Authenticate {
+        credentials: credentials("owner", "owner"),
+    };
+    assert!(matches!(
+        udp_request(&mut udp_client, authenticate()).await,
+        AccessDenied(_)
+    ));
+    let request = relay.sent.recv().await.unwrap();
+
+    let mut tampered = request.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0x01;
+    relay.inject(&request).await;
+    relay.inject(&tampered).await;
+    {
+        let mut messages = udp_client.messages.lock().await;
+        let answer = tokio::time::timeout(Duration::from_millis(500), async {
+            loop {
+                match messages.recv().await {
+                    Some(Message::Bytes(bytes)) => return Some(bytes),
+                    Some(_) => continue,
+                    None => return None,
+                }
+            }
+        })
+        .await;
+        assert!(answer.is_err(), "answered {:?}", answer);
+    }
+
+    // the client itself goes on being answered
+    assert!(matches!(
+        udp_request(&mut udp_client, authenticate()).await,
+        AccessDenied(_)
+    ));
+
+    server.shutdown_handle().shutdown();
+}
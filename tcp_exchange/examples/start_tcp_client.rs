@@ -2,16 +2,20 @@ use std::error::Error;
 
 use exchange_protocol::domain::Message;
 use tcp_exchange::tcp_client::TcpClient;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let server_address = "127.0.0.1:45932";
     let mut client = TcpClient::connect(server_address).await?;
 
     let receiver = client.messages.clone();
     while let Some(msg) = receiver.lock().await.recv().await {
         match msg {
-            Message::Connected => {
+            Message::Connected(_) => {
                 println!("client: connected to server '{server_address}'");
                 client
                     .send("hello server".as_bytes())
@@ -35,7 +39,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     }
                 }
             }
-            Message::Disconnected => println!("client: disconnected from '{server_address}'"),
+            // only WebSocket has text frames
+            Message::Text(_) => {}
+            Message::Error(error) => {
+                eprintln!("client: exchange with '{server_address}' failed: {error}")
+            }
+            Message::Reconnecting { attempt, delay } => {
+                println!(
+                    "client: reconnecting to '{server_address}' in {delay:?}, attempt {attempt}"
+                )
+            }
+            Message::Disconnected(reason) => {
+                println!("client: disconnected from '{server_address}': {reason:?}")
+            }
         };
     }
 
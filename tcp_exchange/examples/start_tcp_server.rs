@@ -2,15 +2,21 @@ use std::error::Error;
 
 use exchange_protocol::domain::Message;
 use tcp_exchange::tcp_server::TcpServer;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let server = TcpServer::start("127.0.0.1:45932").await?;
 
     let receiver = server.messages.clone();
     while let Some(notify) = receiver.lock().await.recv().await {
         match notify.message {
-            Message::Connected => println!("server: client {} connected", notify.address),
+            Message::Connected(info) => {
+                println!("server: client {} connected: {info:?}", notify.address)
+            }
             Message::Bytes(ref bytes) => match String::from_utf8(bytes.clone()) {
                 Ok(_data) => {
                     println!(
@@ -30,7 +36,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     notify.address
                 ),
             },
-            Message::Disconnected => println!("server: client {} disconnected", notify.address),
+            // only WebSocket has text frames
+            Message::Text(_) => {}
+            Message::Error(ref error) => {
+                eprintln!(
+                    "server: exchange with client {} failed: {error}",
+                    notify.address
+                )
+            }
+            // only clients reconnect
+            Message::Reconnecting { .. } => {}
+            Message::Disconnected(reason) => {
+                println!("server: client {} disconnected: {reason:?}", notify.address)
+            }
         };
     }
 
@@ -1,2 +1,7 @@
+pub mod registry;
 pub mod tcp_client;
 pub mod tcp_server;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix_server;
+pub mod ws_server;
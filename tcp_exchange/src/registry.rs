@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+
+use exchange_protocol::domain::{ConnectionInfo, SendMessage};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::error::ExchangeError::{QueueOverflow, SendNotifyError};
+
+/// A live connection of a `TcpServer` or a `UnixServer`.
+#[derive(Debug, Clone)]
+pub struct ConnectedClient {
+    pub address: SocketAddr,
+    pub info: ConnectionInfo,
+    pub connected_at: SystemTime,
+}
+
+struct Entry {
+    client: ConnectedClient,
+    sender: Sender<SendMessage>,
+    queue_size: usize,
+    close: Arc<Notify>,
+}
+
+/// The connections a server can reach on its own, kept until their receiving ends.
+#[derive(Clone, Default)]
+pub(crate) struct Registry {
+    entries: Arc<Mutex<HashMap<SocketAddr, Entry>>>,
+}
+
+impl Registry {
+    /// Returns what the connection waits on to be closed by the server.
+    pub(crate) fn insert(
+        &self,
+        client: ConnectedClient,
+        sender: Sender<SendMessage>,
+        queue_size: usize,
+    ) -> Arc<Notify> {
+        let close = Arc::new(Notify::new());
+        let entry = Entry {
+            client,
+            sender,
+            queue_size,
+            close: close.clone(),
+        };
+        self.lock().insert(entry.client.address, entry);
+        close
+    }
+
+    pub(crate) fn remove(&self, address: &SocketAddr) {
+        self.lock().remove(address);
+    }
+
+    pub(crate) fn clients(&self) -> Vec<ConnectedClient> {
+        self.lock()
+            .values()
+            .map(|entry| entry.client.clone())
+            .collect()
+    }
+
+    pub(crate) async fn send(
+        &self,
+        address: &SocketAddr,
+        bytes: &[u8],
+    ) -> Result<(), ExchangeError> {
+        let sender = self
+            .lock()
+            .get(address)
+            .map(|entry| entry.sender.clone())
+            .ok_or(ExchangeError::UnknownClient(*address))?;
+        sender
+            .send(SendMessage {
+                bytes: bytes.to_vec(),
+                client_address: *address,
+            })
+            .await
+            .map_err(|e| SendNotifyError(*address, e.to_string()))
+    }
+
+    pub(crate) fn try_send(&self, address: &SocketAddr, bytes: &[u8]) -> Result<(), ExchangeError> {
+        let (sender, queue_size) = self
+            .lock()
+            .get(address)
+            .map(|entry| (entry.sender.clone(), entry.queue_size))
+            .ok_or(ExchangeError::UnknownClient(*address))?;
+        sender
+            .try_send(SendMessage {
+                bytes: bytes.to_vec(),
+                client_address: *address,
+            })
+            .map_err(|e| match e {
+                TrySendError::Full(_) => QueueOverflow(*address, queue_size),
+                TrySendError::Closed(_) => SendNotifyError(*address, e.to_string()),
+            })
+    }
+
+    pub(crate) fn broadcast(&self, bytes: &[u8]) -> usize {
+        let senders: Vec<_> = self
+            .lock()
+            .iter()
+            .map(|(address, entry)| (*address, entry.sender.clone()))
+            .collect();
+        senders
+            .into_iter()
+            .filter(|(address, sender)| {
+                sender
+                    .try_send(SendMessage {
+                        bytes: bytes.to_vec(),
+                        client_address: *address,
+                    })
+                    .is_ok()
+            })
+            .count()
+    }
+
+    pub(crate) fn close(&self, address: &SocketAddr) -> Result<(), ExchangeError> {
+        match self.lock().get(address) {
+            // a stored permit still closes a connection that isn't waiting yet
+            Some(entry) => {
+                entry.close.notify_one();
+                Ok(())
+            }
+            None => Err(ExchangeError::UnknownClient(*address)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<SocketAddr, Entry>> {
+        self.entries.lock().expect("connection registry lock")
+    }
+}
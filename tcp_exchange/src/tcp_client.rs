@@ -1,22 +1,38 @@
+use std::collections::VecDeque;
+use std::io;
 use std::net::SocketAddr;
-use std::sync::Arc;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
 
-use tokio::io::AsyncWriteExt;
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::io::{AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use exchange_protocol::codecs::{decode_bytes_async, encode_bytes};
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::frame_reader::FrameReader;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::compression::Compressor;
 use exchange_protocol::domain::*;
 use exchange_protocol::error::ExchangeError;
 use exchange_protocol::error::ExchangeError::SendNotifyError;
+use exchange_protocol::handshake::{client_handshake, HandshakeInfo};
+use exchange_protocol::heartbeat::{Control, Heartbeat};
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::reconnect::Reconnect;
 
-pub struct TcpClient {
+use crate::tls::{BoxedStream, ClientTls};
+
+pub struct TcpClient<C: Codec = LengthPrefixedCodec> {
+    // of the first connection, every reconnect announces its own with `Connected`
     pub address: SocketAddr,
     pub server_address: SocketAddr,
     pub messages: Arc<Mutex<Receiver<Message>>>,
-    send_writer: OwnedWriteHalf,
+    pub handshake: HandshakeInfo,
+    pub codec: C,
+    link: Arc<Mutex<Link<C>>>,
 }
 
 /*impl Clone for TcpClient {
@@ -32,68 +48,471 @@ pub struct TcpClient {
 
 impl TcpClient {
     pub async fn connect<T: ToSocketAddrs>(address: T) -> Result<TcpClient, ExchangeError> {
-        let stream = TcpStream::connect(address).await?;
-        let server_address: SocketAddr = stream.peer_addr()?;
-        let client_address = stream.local_addr()?;
+        Self::connect_with(address, ExchangeOptions::default()).await
+    }
 
-        let (message_notifier_tx, message_notifier_rx) = mpsc::channel::<Message>(1000);
+    pub async fn connect_tls<T: ToSocketAddrs>(
+        address: T,
+        tls: ClientTls,
+    ) -> Result<TcpClient, ExchangeError> {
+        Self::connect_tls_with(address, ExchangeOptions::default(), tls).await
+    }
 
-        let (reader, writer) = TcpStream::into_split(stream);
-        tokio::spawn(async move {
-            TcpClient::process_receiving_messages(reader, server_address, message_notifier_tx)
-                .await
-                .unwrap_or_else(|error| {
-                    eprintln!(
-                        "tcp_client: receiving messages failed from '{server_address}': {error:?}"
-                    )
-                });
-        });
-
-        Ok(TcpClient {
-            address: client_address,
+    #[cfg(unix)]
+    pub async fn connect_unix<P: AsRef<Path>>(path: P) -> Result<TcpClient, ExchangeError> {
+        Self::connect_unix_with(path, ExchangeOptions::default()).await
+    }
+}
+
+impl<C: Codec> TcpClient<C> {
+    pub async fn connect_with<T: ToSocketAddrs>(
+        address: T,
+        options: ExchangeOptions<C>,
+    ) -> Result<TcpClient<C>, ExchangeError> {
+        Self::open(address, options, None).await
+    }
+
+    pub async fn connect_tls_with<T: ToSocketAddrs>(
+        address: T,
+        options: ExchangeOptions<C>,
+        tls: ClientTls,
+    ) -> Result<TcpClient<C>, ExchangeError> {
+        Self::open(address, options, Some(tls)).await
+    }
+
+    /// Connects to a `UnixServer`, the addresses are made up as no unix peer has one.
+    #[cfg(unix)]
+    pub async fn connect_unix_with<P: AsRef<Path>>(
+        path: P,
+        options: ExchangeOptions<C>,
+    ) -> Result<TcpClient<C>, ExchangeError> {
+        let endpoint = Endpoint::Unix(path.as_ref().to_path_buf());
+        let dialed = endpoint.dial().await?;
+        Self::open_dialed(endpoint, dialed, options, None).await
+    }
+
+    async fn open<T: ToSocketAddrs>(
+        address: T,
+        options: ExchangeOptions<C>,
+        tls: Option<ClientTls>,
+    ) -> Result<TcpClient<C>, ExchangeError> {
+        let dialed = Dialed::tcp(TcpStream::connect(address).await?)?;
+        let endpoint = Endpoint::Tcp(dialed.server_address);
+        Self::open_dialed(endpoint, dialed, options, tls).await
+    }
+
+    async fn open_dialed(
+        endpoint: Endpoint,
+        dialed: Dialed,
+        options: ExchangeOptions<C>,
+        tls: Option<ClientTls>,
+    ) -> Result<TcpClient<C>, ExchangeError> {
+        let established = Self::establish(dialed, &options, tls.as_ref()).await?;
+        let server_address = established.server_address;
+
+        let (message_notifier_tx, message_notifier_rx) =
+            mpsc::channel::<Message>(options.limits.queue_size);
+
+        let link = Arc::new(Mutex::new(Link {
+            writer: None,
+            codec: established.codec.clone(),
+            compressor: established.compressor,
+            pending: VecDeque::new(),
+            max_pending: options.reconnect.map(|reconnect| reconnect.max_pending),
+            replay: Vec::new(),
+        }));
+        let client = TcpClient {
+            address: established.client_address,
             server_address,
             messages: Arc::new(Mutex::new(message_notifier_rx)),
-            send_writer: writer,
+            handshake: established.info.handshake,
+            codec: established.codec.clone(),
+            link: link.clone(),
+        };
+
+        let attached = Self::attach(&link, established, &options).await?;
+        let session = Session {
+            endpoint,
+            server_address,
+            options,
+            tls,
+            link: Arc::downgrade(&link),
+            message_notifier_tx,
+        };
+        let span = info_span!("tcp_client", server = %server_address);
+        tokio::spawn(
+            async move {
+                session
+                    .run(attached)
+                    .await
+                    .unwrap_or_else(|error| warn!(?error, "receiving messages failed"));
+            }
+            .instrument(span),
+        );
+
+        Ok(client)
+    }
+
+    async fn establish(
+        dialed: Dialed,
+        options: &ExchangeOptions<C>,
+        tls: Option<&ClientTls>,
+    ) -> Result<Established<C>, ExchangeError> {
+        let Dialed {
+            stream,
+            client_address,
+            server_address,
+            identity,
+        } = dialed;
+
+        let (mut stream, identity, hello) = match tls {
+            Some(tls) => {
+                let (stream, identity) = tls.connect(stream, options.handshake_timeout).await?;
+                (stream, identity, tls.hello(options.hello()))
+            }
+            None => (stream, identity, options.hello()),
+        };
+
+        let handshake = if options.handshake {
+            client_handshake(&mut stream, &hello, options.handshake_timeout).await?
+        } else {
+            options.assumed_handshake()
+        };
+        Ok(Established {
+            stream,
+            client_address,
+            server_address,
+            codec: options.negotiated_codec(&handshake),
+            compressor: options.compressor(&handshake),
+            info: ConnectionInfo::new(handshake, identity),
         })
     }
 
+    /// Makes the connection the one sends go to, after the replay and the pending sends.
+    async fn attach(
+        link: &Arc<Mutex<Link<C>>>,
+        established: Established<C>,
+        options: &ExchangeOptions<C>,
+    ) -> Result<Attached<C>, ExchangeError> {
+        let Established {
+            stream,
+            codec,
+            compressor,
+            info,
+            ..
+        } = established;
+        let (reader, writer) = tokio::io::split(stream);
+        let mut frames = FrameReader::new(reader, codec.clone());
+        // dropped once receiving ends, which stops the pings
+        let (closed_tx, closed_rx) = oneshot::channel::<()>();
+        if let Some(heartbeat) = options.active_heartbeat() {
+            frames = frames.with_read_timeout(heartbeat.idle_timeout);
+            let ping = codec.encode_frame(&compressor.pack(&Control::Ping.encode())?)?;
+            let pings = Self::send_pings(Arc::downgrade(link), ping, heartbeat, closed_rx);
+            tokio::spawn(pings);
+        }
+
+        let mut link = link.lock().await;
+        link.codec = codec;
+        link.compressor = compressor;
+        link.writer = Some(writer);
+        link.flush().await;
+
+        Ok(Attached {
+            frames,
+            compressor,
+            info,
+            _closed_tx: closed_tx,
+        })
+    }
+
+    async fn send_pings(
+        link: Weak<Mutex<Link<C>>>,
+        ping: Vec<u8>,
+        heartbeat: Heartbeat,
+        mut closed: oneshot::Receiver<()>,
+    ) {
+        let mut interval = tokio::time::interval(heartbeat.interval);
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // the client is gone
+                    let link = match link.upgrade() {
+                        Some(link) => link,
+                        None => break,
+                    };
+                    let mut link = link.lock().await;
+                    if link.write_frame(&ping).await.is_err() {
+                        // the receiving side notices the broken connection
+                        break;
+                    }
+                }
+                _ = &mut closed => break,
+            }
+        }
+    }
+
     async fn process_receiving_messages(
-        mut reader: OwnedReadHalf,
+        mut frames: FrameReader<ReadHalf<BoxedStream>, C>,
+        compressor: Compressor,
         server_address: SocketAddr,
-        message_notifier_tx: Sender<Message>,
-    ) -> Result<(), ExchangeError> {
-        message_notifier_tx
-            .send(Message::Connected)
-            .await
-            .map_err(|e| SendNotifyError(server_address, e.to_string()))?;
-
-        while let Ok(bytes) = decode_bytes_async(&mut reader).await {
-            message_notifier_tx
-                .send(Message::Bytes(bytes))
+        message_notifier_tx: &Sender<Message>,
+    ) -> Result<DisconnectReason, ExchangeError> {
+        let reason = loop {
+            let bytes = frames
+                .read_frame()
                 .await
-                .map_err(|e| SendNotifyError(server_address, e.to_string()))?;
+                .and_then(|frame| frame.map(|frame| compressor.unpack(frame)).transpose());
+            match bytes {
+                Ok(Some(bytes)) => match Control::decode(&bytes) {
+                    Some(Control::Shutdown) => break DisconnectReason::ServerShutdown,
+                    Some(Control::Overloaded) => break DisconnectReason::Overloaded,
+                    Some(Control::Closed) => break DisconnectReason::ClosedByServer,
+                    Some(Control::Ping | Control::Pong) => {}
+                    None => message_notifier_tx
+                        .send(Message::Bytes(bytes))
+                        .await
+                        .map_err(|e| SendNotifyError(server_address, e.to_string()))?,
+                },
+                Ok(None) | Err(ExchangeError::Io(_)) => break DisconnectReason::PeerClosed,
+                Err(ExchangeError::ReadTimeout(timeout)) => {
+                    break DisconnectReason::IdleTimeout(timeout)
+                }
+                Err(error) => break DisconnectReason::ProtocolError(error.to_string()),
+            }
+        };
+        Ok(reason)
+    }
+
+    /// While a reconnecting client is away, `bytes` wait for it to come back.
+    pub async fn send(&mut self, bytes: &[u8]) -> Result<(), ExchangeError> {
+        let mut link = self.link.lock().await;
+        if link.max_pending.is_none() {
+            return link.write(bytes).await;
+        }
+        if link.writer.is_some() && link.write(bytes).await.is_ok() {
+            return Ok(());
         }
+        link.queue(bytes)
+    }
 
-        message_notifier_tx
-            .send(Message::Disconnected)
-            .await
-            .map_err(|e| SendNotifyError(server_address, e.to_string()))
+    /// Sets what is sent first whenever the client got its server back, e.g. to
+    /// authenticate again.
+    pub async fn replay_on_reconnect(&self, frames: Vec<Vec<u8>>) {
+        self.link.lock().await.replay = frames;
     }
+}
 
-    pub async fn send(&mut self, bytes: &[u8]) -> Result<(), ExchangeError> {
-        let encoded = encode_bytes(bytes);
+/// Where sends go, the connection is replaced on every reconnect.
+struct Link<C: Codec> {
+    writer: Option<WriteHalf<BoxedStream>>,
+    codec: C,
+    compressor: Compressor,
+    pending: VecDeque<Vec<u8>>,
+    /// Only reconnecting clients keep sends.
+    max_pending: Option<usize>,
+    replay: Vec<Vec<u8>>,
+}
 
-        let server_address = self.send_writer.peer_addr()?;
+impl<C: Codec> Link<C> {
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), ExchangeError> {
+        let encoded = self.codec.encode_frame(&self.compressor.pack(bytes)?)?;
+        self.write_frame(&encoded).await
+    }
 
-        self.send_writer
-            .write_all(&encoded)
-            .await
-            .unwrap_or_else(|error| {
-                eprintln!(
-                    "tcp_client: sending message to server '{server_address}' failed: {error}"
-                )
-            });
+    async fn write_frame(&mut self, frame: &[u8]) -> Result<(), ExchangeError> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        writer.write_all(frame).await.map_err(ExchangeError::Io)
+    }
 
+    fn queue(&mut self, bytes: &[u8]) -> Result<(), ExchangeError> {
+        let max_pending = self.max_pending.unwrap_or_default();
+        if self.pending.len() >= max_pending {
+            return Err(ExchangeError::PendingOverflow(max_pending));
+        }
+        self.pending.push_back(bytes.to_vec());
         Ok(())
     }
+
+    /// What can't be written stays pending, the receiving side notices the broken
+    /// connection.
+    async fn flush(&mut self) {
+        for frame in self.replay.clone() {
+            if self.write(&frame).await.is_err() {
+                return;
+            }
+        }
+        while let Some(bytes) = self.pending.pop_front() {
+            if self.write(&bytes).await.is_err() {
+                self.pending.push_front(bytes);
+                return;
+            }
+        }
+    }
+}
+
+/// Where a client connects to, again on every reconnect.
+enum Endpoint {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    async fn dial(&self) -> Result<Dialed, ExchangeError> {
+        match self {
+            Endpoint::Tcp(address) => Dialed::tcp(TcpStream::connect(address).await?),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path).await?;
+                let identity = crate::unix_server::peer_identity(&stream)?;
+                let address = crate::unix_server::local_address(0);
+                Ok(Dialed {
+                    stream: Box::new(stream),
+                    client_address: address,
+                    server_address: address,
+                    identity: Some(Arc::new(identity)),
+                })
+            }
+        }
+    }
+}
+
+/// A connection before TLS and the handshake.
+struct Dialed {
+    stream: BoxedStream,
+    client_address: SocketAddr,
+    server_address: SocketAddr,
+    identity: Option<Arc<PeerIdentity>>,
+}
+
+impl Dialed {
+    fn tcp(stream: TcpStream) -> Result<Dialed, ExchangeError> {
+        Ok(Dialed {
+            client_address: stream.local_addr()?,
+            server_address: stream.peer_addr()?,
+            stream: Box::new(stream),
+            identity: None,
+        })
+    }
+}
+
+/// A connection that did the handshake.
+struct Established<C: Codec> {
+    stream: BoxedStream,
+    client_address: SocketAddr,
+    server_address: SocketAddr,
+    codec: C,
+    compressor: Compressor,
+    info: ConnectionInfo,
+}
+
+/// A connection sends go to, ready to receive.
+struct Attached<C: Codec> {
+    frames: FrameReader<ReadHalf<BoxedStream>, C>,
+    compressor: Compressor,
+    info: ConnectionInfo,
+    _closed_tx: oneshot::Sender<()>,
+}
+
+/// Receives for a client over its connections, one after the other.
+struct Session<C: Codec> {
+    endpoint: Endpoint,
+    server_address: SocketAddr,
+    options: ExchangeOptions<C>,
+    tls: Option<ClientTls>,
+    link: Weak<Mutex<Link<C>>>,
+    message_notifier_tx: Sender<Message>,
+}
+
+impl<C: Codec> Session<C> {
+    async fn run(self, mut attached: Attached<C>) -> Result<(), ExchangeError> {
+        loop {
+            let Attached {
+                frames,
+                compressor,
+                info,
+                _closed_tx: closed_tx,
+            } = attached;
+            debug!("connected");
+            self.notify(Message::Connected(info)).await?;
+            let reason = TcpClient::process_receiving_messages(
+                frames,
+                compressor,
+                self.server_address,
+                &self.message_notifier_tx,
+            )
+            .await?;
+            drop(closed_tx);
+            if let Some(link) = self.link.upgrade() {
+                // sends fail or wait from now on, not only once the broken pipe shows
+                link.lock().await.writer = None;
+            }
+
+            let reconnect = self
+                .options
+                .reconnect
+                .filter(|reconnect| reconnect.applies_to(&reason));
+            debug!(?reason, "disconnected");
+            self.notify(Message::Disconnected(reason)).await?;
+            attached = match reconnect {
+                Some(reconnect) => match self.reconnect(reconnect).await? {
+                    Some(attached) => attached,
+                    None => break,
+                },
+                None => break,
+            };
+        }
+
+        if let Some(link) = self.link.upgrade() {
+            // nothing waits for a connection that won't come back
+            let mut link = link.lock().await;
+            link.max_pending = None;
+            link.pending.clear();
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&self, reconnect: Reconnect) -> Result<Option<Attached<C>>, ExchangeError> {
+        let mut attempt = 1;
+        while !reconnect.gives_up(attempt) {
+            let delay = reconnect.delay(attempt);
+            info!(attempt, ?delay, "reconnecting");
+            self.notify(Message::Reconnecting { attempt, delay })
+                .await?;
+            tokio::time::sleep(delay).await;
+            if self.link.strong_count() == 0 {
+                // the client is gone
+                return Ok(None);
+            }
+            let established = match self.endpoint.dial().await {
+                Ok(dialed) => TcpClient::establish(dialed, &self.options, self.tls.as_ref()).await,
+                Err(error) => Err(error),
+            };
+            match established {
+                Ok(established) => {
+                    return match self.link.upgrade() {
+                        Some(link) => TcpClient::attach(&link, established, &self.options)
+                            .await
+                            .map(Some),
+                        None => Ok(None),
+                    }
+                }
+                Err(error) => self.notify(Message::Error(error)).await?,
+            }
+            attempt += 1;
+        }
+        Ok(None)
+    }
+
+    async fn notify(&self, message: Message) -> Result<(), ExchangeError> {
+        self.message_notifier_tx
+            .send(message)
+            .await
+            .map_err(|e| SendNotifyError(self.server_address, e.to_string()))
+    }
 }
@@ -1,117 +1,551 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use tokio::io::AsyncWriteExt;
-use tokio::net::tcp::OwnedReadHalf;
-use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::io::{AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, ToSocketAddrs};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use exchange_protocol::codecs::{decode_bytes_async, encode_bytes};
-use exchange_protocol::domain::{Message, NotifyMessage, SendMessage};
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::frame_reader::FrameReader;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::compression::Compressor;
+use exchange_protocol::domain::{
+    ConnectionInfo, DisconnectReason, Message, NotifyMessage, PeerIdentity, SendMessage,
+};
 use exchange_protocol::error::ExchangeError;
 use exchange_protocol::error::ExchangeError::SendNotifyError;
+use exchange_protocol::handshake::{reject_handshake, server_handshake, HandshakeInfo};
+use exchange_protocol::heartbeat::Control;
+use exchange_protocol::inbox::Inbox;
+use exchange_protocol::metrics::TransportMetrics;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::shutdown::{ShutdownHandle, ShutdownSignal};
 
-pub struct TcpServer {
+use crate::registry::{ConnectedClient, Registry};
+use crate::tls::{BoxedStream, ServerTls};
+
+pub struct TcpServer<C: Codec = LengthPrefixedCodec> {
     pub address: SocketAddr,
     pub messages: Arc<Mutex<Receiver<NotifyMessage>>>,
+    pub options: ExchangeOptions<C>,
+    shutdown: ShutdownHandle,
+    registry: Registry,
 }
 
 impl TcpServer {
     pub async fn start<Addrs: ToSocketAddrs>(address: Addrs) -> Result<TcpServer, ExchangeError> {
+        Self::start_with(address, ExchangeOptions::default()).await
+    }
+
+    pub async fn start_tls<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        tls: ServerTls,
+    ) -> Result<TcpServer, ExchangeError> {
+        Self::start_tls_with(address, ExchangeOptions::default(), tls).await
+    }
+}
+
+impl<C: Codec> TcpServer<C> {
+    pub async fn start_with<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+    ) -> Result<TcpServer<C>, ExchangeError> {
+        Self::listen(address, options, None).await
+    }
+
+    pub async fn start_tls_with<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+        tls: ServerTls,
+    ) -> Result<TcpServer<C>, ExchangeError> {
+        Self::listen(address, options, Some(tls)).await
+    }
+
+    async fn listen<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+        tls: Option<ServerTls>,
+    ) -> Result<TcpServer<C>, ExchangeError> {
         let listener = TcpListener::bind(address).await?;
         let server_address = listener.local_addr()?;
 
-        let (message_notifier_tx, message_notifier_rx) = mpsc::channel::<NotifyMessage>(1000);
+        let (shared, message_notifier_rx) = Shared::new(&options, "tcp");
+        let registry = shared.registry.clone();
+        let (shutdown, mut signal) = ShutdownHandle::new();
 
-        tokio::spawn(async move {
-            loop {
-                Self::start_stream_processing(&listener, message_notifier_tx.clone())
-                    .await
-                    .unwrap_or_else(|error| {
-                        eprintln!("tcp_server: connection process failed: {error:?}")
-                    });
+        let listener_options = options.clone();
+        let span = info_span!("tcp_server", address = %server_address);
+        tokio::spawn(
+            async move {
+                loop {
+                    let accepted = tokio::select! {
+                        accepted = listener.accept() => accepted,
+                        // dropping the listener releases the port
+                        _ = signal.triggered() => break,
+                    };
+                    let (client_stream, client_address) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(error) => {
+                            warn!(?error, "accepting connection failed");
+                            continue;
+                        }
+                    };
+
+                    let incoming = Incoming {
+                        stream: Box::new(client_stream),
+                        address: client_address,
+                        identity: None,
+                        admission: shared.admit(),
+                    };
+                    let options = listener_options.clone();
+                    Self::spawn_connection(incoming, options, tls.clone(), shared.clone(), &signal);
+                }
             }
-        });
+            .instrument(span),
+        );
 
-        println!("tcp_server: started at {server_address}");
+        info!(address = %server_address, "tcp server started");
 
         Ok(TcpServer {
             address: server_address,
             messages: Arc::new(Mutex::new(message_notifier_rx)),
+            options,
+            shutdown,
+            registry,
         })
     }
 
+    /// The clients connected right now.
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        self.registry.clients()
+    }
+
+    /// Queues `bytes` for a connected client, waiting while its queue is full.
+    pub async fn send(
+        &self,
+        client_address: &SocketAddr,
+        bytes: &[u8],
+    ) -> Result<(), ExchangeError> {
+        self.registry.send(client_address, bytes).await
+    }
+
+    /// Queues `bytes` for a connected client without waiting, fails with `QueueOverflow`
+    /// while its queue is full.
+    pub fn try_send(&self, client_address: &SocketAddr, bytes: &[u8]) -> Result<(), ExchangeError> {
+        self.registry.try_send(client_address, bytes)
+    }
+
+    /// Queues `bytes` for every connected client and returns how many of them got it.
+    /// A client whose queue is full misses it rather than holding up the others.
+    pub fn broadcast(&self, bytes: &[u8]) -> usize {
+        self.registry.broadcast(bytes)
+    }
+
+    /// Closes the connection of one client once what is queued for it is sent. The
+    /// application gets `Disconnected(ClosedByServer)` for it.
+    pub fn disconnect(&self, client_address: &SocketAddr) -> Result<(), ExchangeError> {
+        self.registry.close(client_address)
+    }
+
+    /// Stops accepting, tells connected clients and closes their connections once the
+    /// replies to requests already received are sent or the drain deadline has passed.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Runs the handshake and the connection apart, so it doesn't hold up accepting
+    /// other clients. Everything about the connection is traced in its own span.
+    pub(crate) fn spawn_connection(
+        incoming: Incoming,
+        options: ExchangeOptions<C>,
+        tls: Option<ServerTls>,
+        shared: Shared,
+        signal: &ShutdownSignal,
+    ) {
+        let span = info_span!("connection", client = %incoming.address);
+        let signal = signal.clone();
+        tokio::spawn(
+            async move {
+                Self::start_stream_processing(incoming, options, tls, shared, signal)
+                    .await
+                    .unwrap_or_else(|error| warn!(%error, "connection process failed"));
+            }
+            .instrument(span),
+        );
+    }
+
     async fn start_stream_processing(
-        listener: &TcpListener,
-        message_notifier_tx: Sender<NotifyMessage>,
+        incoming: Incoming,
+        options: ExchangeOptions<C>,
+        tls: Option<ServerTls>,
+        shared: Shared,
+        mut signal: ShutdownSignal,
     ) -> Result<(), ExchangeError> {
-        let (client_stream, client_address) = listener.accept().await?;
+        let (client_sender_tx, client_sender_rx) =
+            mpsc::channel::<SendMessage>(options.limits.queue_size);
+
+        let Incoming {
+            stream: client_stream,
+            address: client_address,
+            identity,
+            admission,
+        } = incoming;
+        let (permit, refusal) = match admission {
+            Ok(permit) => (permit, None),
+            Err(error) => (None, Some(error)),
+        };
+        let accepted = tokio::select! {
+            accepted = Self::accept_connection(client_stream, identity, &options, tls, refusal) => accepted,
+            _ = signal.triggered() => return Ok(()),
+        };
+        let (client_stream, identity, info) = match accepted {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                // the client never got connected, only its failed attempt is reported
+                debug!(%error, "connection refused");
+                return shared
+                    .inbox
+                    .push(NotifyMessage::new(
+                        Message::Error(error),
+                        client_address,
+                        client_sender_tx,
+                    ))
+                    .await;
+            }
+        };
+        let codec = options.negotiated_codec(&info);
+        let compressor = options.compressor(&info);
 
-        let (client_sender_tx, mut client_sender_rx) = mpsc::channel::<SendMessage>(1000);
+        let (reader, writer) = tokio::io::split(client_stream);
 
-        let (reader, mut writer) = TcpStream::into_split(client_stream);
+        let notifier = shared.notifier(client_address, identity.clone(), client_sender_tx);
+        let info = ConnectionInfo::new(info, identity);
+        // registered before the application hears of it, so it can send right away
+        let closing = notifier.registry.insert(
+            ConnectedClient {
+                address: client_address,
+                info: info.clone(),
+                connected_at: SystemTime::now(),
+            },
+            notifier.client_sender_tx.clone(),
+            options.limits.queue_size,
+        );
+        debug!(identity = ?info.peer_identity, "connected");
+        if let Err(error) = notifier.notify(Message::Connected(info)).await {
+            notifier.registry.remove(&client_address);
+            return Err(error);
+        }
 
-        message_notifier_tx
-            .send(NotifyMessage::new(
-                Message::Connected,
-                client_address,
-                client_sender_tx.clone(),
-            ))
-            .await
-            .map_err(|e| SendNotifyError(client_address, e.to_string()))?;
-
-        tokio::spawn(async move {
-            TcpServer::process_receiving_messages(
-                reader,
-                client_address,
-                client_sender_tx.clone(),
-                message_notifier_tx.clone(),
-            )
-            .await
-            .unwrap_or_else(|error| {
-                eprintln!("tcp_server: receiving messages failed for '{client_address}': {error:?}")
-            });
-        });
-
-        tokio::spawn(async move {
-            while let Some(SendMessage { bytes, .. }) = client_sender_rx.recv().await {
-                let encoded = encode_bytes(bytes.as_slice());
-                writer.write_all(&encoded).await.unwrap_or_else(|error| {
-                    eprintln!(
-                        "tcp_server: sending message to client '{client_address}' failed: {error}"
-                    )
-                });
+        let mut frames = FrameReader::new(reader, codec.clone());
+        if let Some(heartbeat) = options.active_heartbeat() {
+            frames = frames.with_read_timeout(heartbeat.idle_timeout);
+        }
+        // dropped once receiving ends, which closes the connection
+        let (closed_tx, closed_rx) = oneshot::channel::<()>();
+        let answers_pings = options.handshake;
+        let receive_notifier = notifier.clone();
+        let receive_signal = signal.clone();
+        tokio::spawn(
+            async move {
+                // the connection counts against the limit until receiving ends
+                let _permit = permit;
+                let _closed_tx = closed_tx;
+                let metrics = receive_notifier.metrics.clone();
+                metrics.connected();
+                Self::process_receiving_messages(
+                    frames,
+                    compressor,
+                    answers_pings,
+                    receive_notifier,
+                    receive_signal,
+                    closing,
+                )
+                .await
+                .unwrap_or_else(|error| warn!(?error, "receiving messages failed"));
+                metrics.disconnected();
             }
-        });
+            .in_current_span(),
+        );
+
+        // only peers that did the handshake understand control frames
+        let goodbye = options.handshake.then(|| Control::Shutdown.encode());
+        let encode = move |bytes: &[u8]| {
+            compressor
+                .pack(bytes)
+                .and_then(|frame| codec.encode_frame(&frame))
+        };
+        tokio::spawn(
+            Self::process_sending_messages(
+                writer,
+                client_sender_rx,
+                closed_rx,
+                signal,
+                encode,
+                goodbye,
+                notifier,
+            )
+            .in_current_span(),
+        );
 
         Ok(())
     }
 
+    async fn accept_connection(
+        client_stream: BoxedStream,
+        identity: Option<Arc<PeerIdentity>>,
+        options: &ExchangeOptions<C>,
+        tls: Option<ServerTls>,
+        refusal: Option<ExchangeError>,
+    ) -> Result<(BoxedStream, Option<Arc<PeerIdentity>>, HandshakeInfo), ExchangeError> {
+        let (mut client_stream, identity, hello) = match tls {
+            Some(tls) => {
+                let (stream, identity) =
+                    tls.accept(client_stream, options.handshake_timeout).await?;
+                (stream, identity, tls.hello(options.hello()))
+            }
+            None => (client_stream, identity, options.hello()),
+        };
+
+        // a refused client still gets the handshake answered, so it learns why
+        if let Some(error) = refusal {
+            if options.handshake {
+                reject_handshake(
+                    &mut client_stream,
+                    error.to_string(),
+                    options.handshake_timeout,
+                )
+                .await?;
+            }
+            return Err(error);
+        }
+
+        let info = if options.handshake {
+            server_handshake(&mut client_stream, &hello, options.handshake_timeout).await?
+        } else {
+            options.assumed_handshake()
+        };
+        Ok((client_stream, identity, info))
+    }
+
     async fn process_receiving_messages(
-        mut reader: OwnedReadHalf,
+        mut frames: FrameReader<ReadHalf<BoxedStream>, C>,
+        compressor: Compressor,
+        answers_pings: bool,
+        notifier: ConnectionNotifier,
+        mut signal: ShutdownSignal,
+        closing: Arc<Notify>,
+    ) -> Result<(), ExchangeError> {
+        let reason = loop {
+            let message = tokio::select! {
+                frame = frames.read_frame() => {
+                    frame.and_then(|frame| frame.map(|frame| compressor.unpack(frame)).transpose())
+                }
+                _ = signal.triggered() => break DisconnectReason::ServerShutdown,
+                _ = closing.notified() => {
+                    if answers_pings {
+                        notifier.queue(Control::Closed).await;
+                    }
+                    break DisconnectReason::ClosedByServer;
+                }
+            };
+            if let Ok(Some(message)) = &message {
+                notifier.metrics.received(message.len());
+            }
+            match message {
+                Ok(Some(message)) => match Control::decode(&message).filter(|_| answers_pings) {
+                    Some(Control::Ping) => notifier
+                        .client_sender_tx
+                        .send(SendMessage {
+                            bytes: Control::Pong.encode(),
+                            client_address: notifier.client_address,
+                        })
+                        .await
+                        .map_err(|e| SendNotifyError(notifier.client_address, e.to_string()))?,
+                    Some(_) => {}
+                    None => match notifier.notify(Message::Bytes(message)).await {
+                        Ok(()) => {}
+                        Err(ExchangeError::QueueOverflow(..)) => {
+                            if answers_pings {
+                                notifier.queue(Control::Overloaded).await;
+                            }
+                            break DisconnectReason::Overloaded;
+                        }
+                        Err(error) => return Err(error),
+                    },
+                },
+                Ok(None) | Err(ExchangeError::Io(_)) => break DisconnectReason::PeerClosed,
+                Err(ExchangeError::ReadTimeout(timeout)) => {
+                    break DisconnectReason::IdleTimeout(timeout)
+                }
+                Err(error) => {
+                    // the stream can't be resynchronized after a bad frame
+                    notifier.metrics.decode_failed();
+                    break DisconnectReason::ProtocolError(error.to_string());
+                }
+            }
+        };
+
+        notifier.registry.remove(&notifier.client_address);
+        debug!(?reason, "disconnected");
+        notifier.notify(Message::Disconnected(reason)).await
+    }
+
+    async fn process_sending_messages(
+        mut writer: WriteHalf<BoxedStream>,
+        mut client_sender_rx: Receiver<SendMessage>,
+        mut closed: oneshot::Receiver<()>,
+        mut signal: ShutdownSignal,
+        encode: impl Fn(&[u8]) -> Result<Vec<u8>, ExchangeError>,
+        goodbye: Option<Vec<u8>>,
+        notifier: ConnectionNotifier,
+    ) {
+        let metrics = notifier.metrics.clone();
+        let deadline = loop {
+            let bytes = tokio::select! {
+                // a shutdown also ends receiving, it must win to get the replies drained,
+                // and what receiving queued last must be sent before the connection closes
+                biased;
+                deadline = signal.triggered() => break deadline,
+                message = client_sender_rx.recv() => match message {
+                    Some(SendMessage { bytes, .. }) => bytes,
+                    None => return,
+                },
+                _ = &mut closed => return,
+            };
+            let sent = match encode(&bytes) {
+                Ok(encoded) => writer.write_all(&encoded).await.map_err(ExchangeError::Io),
+                Err(error) => Err(error),
+            };
+            if sent.is_ok() {
+                metrics.sent(bytes.len());
+            }
+            if let Err(error) = sent {
+                if notifier.notify(Message::Error(error)).await.is_err() {
+                    return;
+                }
+            }
+        };
+
+        // the channel closes once every reply to a received request is sent
+        drop(notifier);
+        while let Ok(Some(SendMessage { bytes, .. })) =
+            tokio::time::timeout_at(deadline, client_sender_rx.recv()).await
+        {
+            let encoded = match encode(&bytes) {
+                Ok(encoded) => encoded,
+                Err(_) => continue,
+            };
+            if writer.write_all(&encoded).await.is_err() {
+                return;
+            }
+            metrics.sent(bytes.len());
+        }
+        if let Some(Ok(goodbye)) = goodbye.map(|goodbye| encode(&goodbye)) {
+            let _ = writer.write_all(&goodbye).await;
+        }
+        let _ = writer.shutdown().await;
+    }
+}
+
+/// Where a connection reports to, shared by its receiving and sending halves.
+#[derive(Clone)]
+pub(crate) struct ConnectionNotifier {
+    pub(crate) client_address: SocketAddr,
+    identity: Option<Arc<PeerIdentity>>,
+    pub(crate) client_sender_tx: Sender<SendMessage>,
+    inbox: Inbox,
+    pub(crate) registry: Registry,
+    pub(crate) metrics: TransportMetrics,
+}
+
+/// A connection just accepted, before TLS and the handshake.
+pub(crate) struct Incoming {
+    pub(crate) stream: BoxedStream,
+    pub(crate) address: SocketAddr,
+    /// What the operating system tells about the peer, TLS may prove more.
+    pub(crate) identity: Option<Arc<PeerIdentity>>,
+    /// The connection slot, or why the peer is turned away.
+    pub(crate) admission: Result<Option<OwnedSemaphorePermit>, ExchangeError>,
+}
+
+/// What every connection of a server shares.
+#[derive(Clone)]
+pub(crate) struct Shared {
+    inbox: Inbox,
+    pub(crate) registry: Registry,
+    connections: Option<(Arc<Semaphore>, usize)>,
+    metrics: TransportMetrics,
+}
+
+impl Shared {
+    /// `transport` labels the metrics of the server.
+    pub(crate) fn new<C: Codec>(
+        options: &ExchangeOptions<C>,
+        transport: &str,
+    ) -> (Shared, Receiver<NotifyMessage>) {
+        let (message_notifier_tx, message_notifier_rx) =
+            mpsc::channel::<NotifyMessage>(options.limits.queue_size);
+        let shared = Shared {
+            inbox: Inbox::start(options.limits, message_notifier_tx),
+            registry: Registry::default(),
+            connections: options
+                .limits
+                .max_connections
+                .map(|max| (Arc::new(Semaphore::new(max)), max)),
+            metrics: TransportMetrics::new(transport),
+        };
+        (shared, message_notifier_rx)
+    }
+
+    pub(crate) fn notifier(
+        self,
         client_address: SocketAddr,
+        identity: Option<Arc<PeerIdentity>>,
         client_sender_tx: Sender<SendMessage>,
-        message_notifier_tx: Sender<NotifyMessage>,
-    ) -> Result<(), ExchangeError> {
-        while let Ok(message) = decode_bytes_async(&mut reader).await {
-            message_notifier_tx
-                .send(NotifyMessage::new(
-                    Message::Bytes(message),
-                    client_address,
-                    client_sender_tx.clone(),
-                ))
-                .await
-                .map_err(|e| SendNotifyError(client_address, e.to_string()))?;
+    ) -> ConnectionNotifier {
+        ConnectionNotifier {
+            client_address,
+            identity,
+            client_sender_tx,
+            inbox: self.inbox,
+            registry: self.registry,
+            metrics: self.metrics,
         }
+    }
 
-        message_notifier_tx
-            .send(NotifyMessage::new(
-                Message::Disconnected,
-                client_address,
-                client_sender_tx,
-            ))
+    pub(crate) fn admit(&self) -> Result<Option<OwnedSemaphorePermit>, ExchangeError> {
+        match &self.connections {
+            Some((connections, max)) => connections
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| ExchangeError::ConnectionLimit(*max)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ConnectionNotifier {
+    pub(crate) async fn notify(&self, message: Message) -> Result<(), ExchangeError> {
+        self.inbox
+            .push(
+                NotifyMessage::new(message, self.client_address, self.client_sender_tx.clone())
+                    .with_identity(self.identity.clone()),
+            )
             .await
-            .map_err(|e| SendNotifyError(client_address, e.to_string()))
+    }
+
+    /// Queues a control frame to be written before the connection closes.
+    async fn queue(&self, control: Control) {
+        let _ = self
+            .client_sender_tx
+            .send(SendMessage {
+                bytes: control.encode(),
+                client_address: self.client_address,
+            })
+            .await;
     }
 }
@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{ClientConfig, ServerConfig, ServerName};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+pub use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore};
+
+use exchange_protocol::domain::PeerIdentity;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::{AuthMethod, Hello};
+
+/// Any stream a connection can run over, plain or encrypted.
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Stream for S {}
+
+pub(crate) type BoxedStream = Box<dyn Stream>;
+
+/// TLS settings of a `TcpServer`, optionally requiring client certificates (mutual TLS).
+#[derive(Clone)]
+pub struct ServerTls {
+    acceptor: TlsAcceptor,
+    client_auth: bool,
+}
+
+impl ServerTls {
+    pub fn new(
+        certificates: Vec<Certificate>,
+        key: PrivateKey,
+    ) -> Result<ServerTls, ExchangeError> {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .map_err(tls_error)?;
+        Ok(ServerTls {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            client_auth: false,
+        })
+    }
+
+    /// Only accepts clients with a certificate issued by one of `client_roots`.
+    pub fn with_client_auth(
+        certificates: Vec<Certificate>,
+        key: PrivateKey,
+        client_roots: RootCertStore,
+    ) -> Result<ServerTls, ExchangeError> {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(client_roots))
+            .with_single_cert(certificates, key)
+            .map_err(tls_error)?;
+        Ok(ServerTls {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            client_auth: true,
+        })
+    }
+
+    pub fn from_pem_files<P: AsRef<Path>>(
+        certificate_path: P,
+        key_path: P,
+    ) -> Result<ServerTls, ExchangeError> {
+        Self::new(
+            load_certificates(certificate_path)?,
+            load_private_key(key_path)?,
+        )
+    }
+
+    pub(crate) fn hello(&self, hello: Hello) -> Hello {
+        if self.client_auth {
+            prefer_certificate_auth(hello)
+        } else {
+            hello
+        }
+    }
+
+    pub(crate) async fn accept(
+        &self,
+        stream: BoxedStream,
+        timeout: Duration,
+    ) -> Result<(BoxedStream, Option<Arc<PeerIdentity>>), ExchangeError> {
+        let stream = tokio::time::timeout(timeout, self.acceptor.accept(stream))
+            .await
+            .map_err(|_| ExchangeError::TlsError(format!("no TLS handshake within {timeout:?}")))?
+            .map_err(|error| ExchangeError::TlsError(error.to_string()))?;
+
+        let identity = first_identity(stream.get_ref().1.peer_certificates())?;
+        Ok((Box::new(stream), identity))
+    }
+}
+
+/// TLS settings of a `TcpClient`, optionally presenting a client certificate.
+#[derive(Clone)]
+pub struct ClientTls {
+    connector: TlsConnector,
+    server_name: ServerName,
+    identity: bool,
+}
+
+impl ClientTls {
+    pub fn new(roots: RootCertStore, server_name: &str) -> Result<ClientTls, ExchangeError> {
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Self::from_config(config, server_name, false)
+    }
+
+    pub fn with_identity(
+        roots: RootCertStore,
+        server_name: &str,
+        certificates: Vec<Certificate>,
+        key: PrivateKey,
+    ) -> Result<ClientTls, ExchangeError> {
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_single_cert(certificates, key)
+            .map_err(tls_error)?;
+        Self::from_config(config, server_name, true)
+    }
+
+    fn from_config(
+        config: ClientConfig,
+        server_name: &str,
+        identity: bool,
+    ) -> Result<ClientTls, ExchangeError> {
+        let server_name = ServerName::try_from(server_name)
+            .map_err(|_| ExchangeError::TlsError(format!("invalid server name '{server_name}'")))?;
+        Ok(ClientTls {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name,
+            identity,
+        })
+    }
+
+    pub(crate) fn hello(&self, hello: Hello) -> Hello {
+        if self.identity {
+            prefer_certificate_auth(hello)
+        } else {
+            hello
+        }
+    }
+
+    pub(crate) async fn connect(
+        &self,
+        stream: BoxedStream,
+        timeout: Duration,
+    ) -> Result<(BoxedStream, Option<Arc<PeerIdentity>>), ExchangeError> {
+        let stream = tokio::time::timeout(
+            timeout,
+            self.connector.connect(self.server_name.clone(), stream),
+        )
+        .await
+        .map_err(|_| ExchangeError::TlsError(format!("no TLS handshake within {timeout:?}")))?
+        .map_err(|error| ExchangeError::TlsError(error.to_string()))?;
+
+        let identity = first_identity(stream.get_ref().1.peer_certificates())?;
+        Ok((Box::new(stream), identity))
+    }
+}
+
+pub fn load_certificates<P: AsRef<Path>>(path: P) -> Result<Vec<Certificate>, ExchangeError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader)?;
+    if certificates.is_empty() {
+        return Err(ExchangeError::TlsError("no certificates found".to_string()));
+    }
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+pub fn load_private_key<P: AsRef<Path>>(path: P) -> Result<PrivateKey, ExchangeError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(ExchangeError::TlsError("no private key found".to_string()))
+}
+
+pub fn root_store(certificates: &[Certificate]) -> Result<RootCertStore, ExchangeError> {
+    let mut roots = RootCertStore::empty();
+    for certificate in certificates {
+        roots
+            .add(certificate)
+            .map_err(|error| ExchangeError::TlsError(error.to_string()))?;
+    }
+    Ok(roots)
+}
+
+pub fn peer_identity(certificate: &Certificate) -> Result<PeerIdentity, ExchangeError> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(&certificate.0)
+        .map_err(|error| ExchangeError::TlsError(error.to_string()))?;
+
+    let common_name = parsed
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.as_str().ok())
+        .map(str::to_string);
+    let dns_names = match parsed.subject_alternative_name() {
+        Ok(Some(names)) => names
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                x509_parser::extensions::GeneralName::DNSName(name) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok(PeerIdentity::Certificate {
+        common_name,
+        dns_names,
+        der: certificate.0.clone(),
+    })
+}
+
+fn first_identity(
+    certificates: Option<&[Certificate]>,
+) -> Result<Option<Arc<PeerIdentity>>, ExchangeError> {
+    match certificates {
+        Some([certificate, ..]) => Ok(Some(Arc::new(peer_identity(certificate)?))),
+        _ => Ok(None),
+    }
+}
+
+fn prefer_certificate_auth(mut hello: Hello) -> Hello {
+    let auth_methods = &mut hello.capabilities.auth_methods;
+    auth_methods.retain(|method| *method != AuthMethod::ClientCertificate);
+    auth_methods.insert(0, AuthMethod::ClientCertificate);
+    hello
+}
+
+fn tls_error(error: tokio_rustls::rustls::Error) -> ExchangeError {
+    ExchangeError::TlsError(error.to_string())
+}
@@ -0,0 +1,194 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Mutex;
+use tracing::{info, info_span, warn, Instrument};
+
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{NotifyMessage, PeerIdentity};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::shutdown::ShutdownHandle;
+
+use crate::registry::{ConnectedClient, Registry};
+use crate::tcp_server::{Incoming, Shared, TcpServer};
+
+/// Which local users may connect, by the credentials the kernel reports for a peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LocalAccess {
+    /// Only the user the server runs as.
+    #[default]
+    Owner,
+    /// Any of the users or any member of the groups.
+    Users {
+        uids: Vec<u32>,
+        gids: Vec<u32>,
+    },
+    Anyone,
+}
+
+impl LocalAccess {
+    fn allows(&self, owner: u32, uid: u32, gid: u32) -> bool {
+        match self {
+            LocalAccess::Owner => uid == owner,
+            LocalAccess::Users { uids, gids } => uids.contains(&uid) || gids.contains(&gid),
+            LocalAccess::Anyone => true,
+        }
+    }
+}
+
+/// A server on a unix domain socket, for clients on the same machine. It works like a
+/// `TcpServer`, a `TcpClient` connects to it with `connect_unix`.
+pub struct UnixServer<C: Codec = LengthPrefixedCodec> {
+    pub path: PathBuf,
+    pub messages: Arc<Mutex<Receiver<NotifyMessage>>>,
+    pub options: ExchangeOptions<C>,
+    shutdown: ShutdownHandle,
+    registry: Registry,
+}
+
+impl UnixServer {
+    pub async fn start<P: AsRef<Path>>(path: P) -> Result<UnixServer, ExchangeError> {
+        Self::start_with(path, ExchangeOptions::default(), LocalAccess::default()).await
+    }
+}
+
+impl<C: Codec> UnixServer<C> {
+    /// A socket file left behind by a server that didn't stop cleanly is replaced.
+    pub async fn start_with<P: AsRef<Path>>(
+        path: P,
+        options: ExchangeOptions<C>,
+        access: LocalAccess,
+    ) -> Result<UnixServer<C>, ExchangeError> {
+        let path = path.as_ref().to_path_buf();
+        if std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        // the socket file belongs to the user the server runs as
+        let owner = std::fs::metadata(&path)?.uid();
+
+        let (shared, message_notifier_rx) = Shared::new(&options, "unix");
+        let registry = shared.registry.clone();
+        let (shutdown, mut signal) = ShutdownHandle::new();
+
+        let listener_options = options.clone();
+        let listener_path = path.clone();
+        let span = info_span!("unix_server", path = %path.display());
+        tokio::spawn(
+            async move {
+                let mut connections: u64 = 0;
+                loop {
+                    let accepted = tokio::select! {
+                        accepted = listener.accept() => accepted,
+                        _ = signal.triggered() => break,
+                    };
+                    let client_stream = match accepted {
+                        Ok((client_stream, _)) => client_stream,
+                        Err(error) => {
+                            warn!(?error, "accepting connection failed");
+                            continue;
+                        }
+                    };
+                    connections += 1;
+
+                    let identity = match peer_identity(&client_stream) {
+                        Ok(identity) => identity,
+                        Err(error) => {
+                            warn!(?error, "reading peer credentials failed");
+                            continue;
+                        }
+                    };
+                    let admission = match identity {
+                        PeerIdentity::Process { uid, gid, .. }
+                            if !access.allows(owner, uid, gid) =>
+                        {
+                            Err(ExchangeError::LocalAccessDenied(uid, gid))
+                        }
+                        _ => shared.admit(),
+                    };
+                    let incoming = Incoming {
+                        stream: Box::new(client_stream),
+                        address: local_address(connections),
+                        identity: Some(Arc::new(identity)),
+                        admission,
+                    };
+                    let options = listener_options.clone();
+                    TcpServer::<C>::spawn_connection(
+                        incoming,
+                        options,
+                        None,
+                        shared.clone(),
+                        &signal,
+                    );
+                }
+                let _ = std::fs::remove_file(&listener_path);
+            }
+            .instrument(span),
+        );
+
+        info!(path = %path.display(), "unix server started");
+
+        Ok(UnixServer {
+            path,
+            messages: Arc::new(Mutex::new(message_notifier_rx)),
+            options,
+            shutdown,
+            registry,
+        })
+    }
+
+    /// The clients connected right now.
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        self.registry.clients()
+    }
+
+    /// Queues `bytes` for a connected client, waiting while its queue is full.
+    pub async fn send(
+        &self,
+        client_address: &SocketAddr,
+        bytes: &[u8],
+    ) -> Result<(), ExchangeError> {
+        self.registry.send(client_address, bytes).await
+    }
+
+    pub fn try_send(&self, client_address: &SocketAddr, bytes: &[u8]) -> Result<(), ExchangeError> {
+        self.registry.try_send(client_address, bytes)
+    }
+
+    /// Queues `bytes` for every connected client and returns how many of them got it.
+    pub fn broadcast(&self, bytes: &[u8]) -> usize {
+        self.registry.broadcast(bytes)
+    }
+
+    pub fn disconnect(&self, client_address: &SocketAddr) -> Result<(), ExchangeError> {
+        self.registry.close(client_address)
+    }
+
+    /// Stops accepting and removes the socket file, the connections close as they do
+    /// for a `TcpServer`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+}
+
+/// Unix peers have no address, each connection gets one made up from 0.0.0.0/8, which
+/// no tcp peer can have, so it is told apart like any other client.
+pub(crate) fn local_address(connection: u64) -> SocketAddr {
+    let host = Ipv4Addr::from((connection >> 16) as u32 & 0x00ff_ffff);
+    SocketAddr::new(host.into(), connection as u16)
+}
+
+pub(crate) fn peer_identity(stream: &UnixStream) -> Result<PeerIdentity, ExchangeError> {
+    let credentials = stream.peer_cred()?;
+    Ok(PeerIdentity::Process {
+        uid: credentials.uid(),
+        gid: credentials.gid(),
+        pid: credentials.pid(),
+    })
+}
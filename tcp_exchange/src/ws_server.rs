@@ -0,0 +1,460 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::{mpsc, Mutex, Notify, OwnedSemaphorePermit};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::error::ProtocolError;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as Frame};
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{
+    ConnectionInfo, DisconnectReason, Message, NotifyMessage, SendMessage,
+};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::HandshakeInfo;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::shutdown::{ShutdownHandle, ShutdownSignal};
+
+use crate::registry::{ConnectedClient, Registry};
+use crate::tcp_server::{ConnectionNotifier, Shared};
+
+type Socket = WebSocketStream<TcpStream>;
+
+/// A server for clients that can only speak WebSocket, like web pages. Every text or
+/// binary message is one `Bytes`, replies go out as the kind of message the client sent
+/// last. The codec of the options only bounds the message size, WebSocket frames itself.
+pub struct WsServer<C: Codec = LengthPrefixedCodec> {
+    pub address: SocketAddr,
+    pub messages: Arc<Mutex<Receiver<NotifyMessage>>>,
+    pub options: ExchangeOptions<C>,
+    shutdown: ShutdownHandle,
+    registry: Registry,
+}
+
+impl WsServer {
+    pub async fn start<Addrs: ToSocketAddrs>(address: Addrs) -> Result<WsServer, ExchangeError> {
+        Self::start_with(address, ExchangeOptions::default()).await
+    }
+}
+
+impl<C: Codec> WsServer<C> {
+    pub async fn start_with<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+    ) -> Result<WsServer<C>, ExchangeError> {
+        let listener = TcpListener::bind(address).await?;
+        let server_address = listener.local_addr()?;
+
+        let (shared, message_notifier_rx) = Shared::new(&options, "ws");
+        let registry = shared.registry.clone();
+        let (shutdown, mut signal) = ShutdownHandle::new();
+
+        let listener_options = options.clone();
+        let span = info_span!("ws_server", address = %server_address);
+        tokio::spawn(
+            async move {
+                loop {
+                    let accepted = tokio::select! {
+                        accepted = listener.accept() => accepted,
+                        _ = signal.triggered() => break,
+                    };
+                    let (client_stream, client_address) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(error) => {
+                            warn!(?error, "accepting connection failed");
+                            continue;
+                        }
+                    };
+
+                    let admission = shared.admit();
+                    let options = listener_options.clone();
+                    let shared = shared.clone();
+                    let signal = signal.clone();
+                    let span = info_span!("connection", client = %client_address);
+                    tokio::spawn(
+                        async move {
+                            let connection = Connection {
+                                address: client_address,
+                                options,
+                                shared,
+                                signal,
+                            };
+                            connection
+                                .run(client_stream, admission)
+                                .await
+                                .unwrap_or_else(|error| warn!(%error, "connection process failed"));
+                        }
+                        .instrument(span),
+                    );
+                }
+            }
+            .instrument(span),
+        );
+
+        info!(address = %server_address, "ws server started");
+
+        Ok(WsServer {
+            address: server_address,
+            messages: Arc::new(Mutex::new(message_notifier_rx)),
+            options,
+            shutdown,
+            registry,
+        })
+    }
+
+    /// The clients connected right now.
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        self.registry.clients()
+    }
+
+    /// Queues `bytes` for a connected client, waiting while its queue is full.
+    pub async fn send(
+        &self,
+        client_address: &SocketAddr,
+        bytes: &[u8],
+    ) -> Result<(), ExchangeError> {
+        self.registry.send(client_address, bytes).await
+    }
+
+    pub fn try_send(&self, client_address: &SocketAddr, bytes: &[u8]) -> Result<(), ExchangeError> {
+        self.registry.try_send(client_address, bytes)
+    }
+
+    /// Queues `bytes` for every connected client and returns how many of them got it.
+    pub fn broadcast(&self, bytes: &[u8]) -> usize {
+        self.registry.broadcast(bytes)
+    }
+
+    pub fn disconnect(&self, client_address: &SocketAddr) -> Result<(), ExchangeError> {
+        self.registry.close(client_address)
+    }
+
+    /// Stops accepting, sends what is queued until the drain deadline and closes every
+    /// connection with a close frame.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+}
+
+/// One WebSocket connection, from the upgrade to the close frame.
+struct Connection<C: Codec> {
+    address: SocketAddr,
+    options: ExchangeOptions<C>,
+    shared: Shared,
+    signal: ShutdownSignal,
+}
+
+impl<C: Codec> Connection<C> {
+    async fn run(
+        mut self,
+        client_stream: TcpStream,
+        admission: Result<Option<OwnedSemaphorePermit>, ExchangeError>,
+    ) -> Result<(), ExchangeError> {
+        let (client_sender_tx, client_sender_rx) =
+            mpsc::channel::<SendMessage>(self.options.limits.queue_size);
+        let notifier = self
+            .shared
+            .clone()
+            .notifier(self.address, None, client_sender_tx.clone());
+
+        let max_size = self.options.codec.max_frame_size();
+        let config = WebSocketConfig {
+            max_message_size: Some(max_size),
+            max_frame_size: Some(max_size),
+            ..WebSocketConfig::default()
+        };
+        let timeout = self.options.handshake_timeout;
+        let upgrade = tokio::time::timeout(
+            timeout,
+            tokio_tungstenite::accept_async_with_config(client_stream, Some(config)),
+        );
+        let upgraded = tokio::select! {
+            upgraded = upgrade => upgraded,
+            _ = self.signal.triggered() => return Ok(()),
+        };
+        let mut socket = match upgraded {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(error)) => return notifier.notify(Message::Error(ws_error(error))).await,
+            Err(_) => {
+                let error =
+                    ExchangeError::HandshakeFailed(format!("no upgrade within {timeout:?}"));
+                return notifier.notify(Message::Error(error)).await;
+            }
+        };
+        let permit = match admission {
+            Ok(permit) => permit,
+            Err(error) => {
+                // the page learns why from the close frame
+                debug!(%error, "connection refused");
+                let _ = socket
+                    .close(Some(close_frame(CloseCode::Again, &error)))
+                    .await;
+                return notifier.notify(Message::Error(error)).await;
+            }
+        };
+
+        let info = ConnectionInfo::new(self.handshake(), None);
+        let closing = notifier.registry.insert(
+            ConnectedClient {
+                address: self.address,
+                info: info.clone(),
+                connected_at: SystemTime::now(),
+            },
+            client_sender_tx,
+            self.options.limits.queue_size,
+        );
+        debug!("connected");
+        if let Err(error) = notifier.notify(Message::Connected(info)).await {
+            notifier.registry.remove(&self.address);
+            return Err(error);
+        }
+
+        let (sink, stream) = socket.split();
+        // replies mirror the kind of message the client sent last
+        let text = Arc::new(AtomicBool::new(false));
+        let (closed_tx, closed_rx) = mpsc::channel::<CloseFrame<'static>>(1);
+        let receiving = Receiving {
+            notifier: notifier.clone(),
+            text: text.clone(),
+            idle_timeout: self
+                .options
+                .heartbeat
+                .map(|heartbeat| heartbeat.idle_timeout),
+            closing,
+            closed_tx,
+        };
+        let signal = self.signal.clone();
+        tokio::spawn(
+            async move {
+                let _permit = permit;
+                let metrics = receiving.notifier.metrics.clone();
+                metrics.connected();
+                receiving
+                    .process(stream, signal)
+                    .await
+                    .unwrap_or_else(|error| warn!(?error, "receiving messages failed"));
+                metrics.disconnected();
+            }
+            .in_current_span(),
+        );
+
+        let sending = Sending {
+            text,
+            ping_interval: self.options.heartbeat.map(|heartbeat| heartbeat.interval),
+            notifier,
+        };
+        tokio::spawn(
+            sending
+                .process(sink, client_sender_rx, closed_rx, self.signal)
+                .in_current_span(),
+        );
+        Ok(())
+    }
+
+    /// There is no protocol handshake over WebSocket, the client gets what it sent.
+    fn handshake(&self) -> HandshakeInfo {
+        HandshakeInfo {
+            compression: None,
+            ..self.options.assumed_handshake()
+        }
+    }
+}
+
+struct Receiving {
+    notifier: ConnectionNotifier,
+    text: Arc<AtomicBool>,
+    idle_timeout: Option<Duration>,
+    closing: Arc<Notify>,
+    /// Tells the sending half how to close the connection once receiving ends.
+    closed_tx: mpsc::Sender<CloseFrame<'static>>,
+}
+
+impl Receiving {
+    async fn process(
+        self,
+        mut stream: SplitStream<Socket>,
+        mut signal: ShutdownSignal,
+    ) -> Result<(), ExchangeError> {
+        let (reason, close) = loop {
+            let next = async {
+                match self.idle_timeout {
+                    Some(idle_timeout) => tokio::time::timeout(idle_timeout, stream.next()).await,
+                    None => Ok(stream.next().await),
+                }
+            };
+            let frame = tokio::select! {
+                frame = next => frame,
+                _ = signal.triggered() => break (DisconnectReason::ServerShutdown, None),
+                _ = self.closing.notified() => {
+                    break (DisconnectReason::ClosedByServer, Some(CloseCode::Normal))
+                }
+            };
+            let (message, size) = match frame {
+                Ok(Some(Ok(Frame::Text(text)))) => {
+                    self.text.store(true, Ordering::Relaxed);
+                    let size = text.len();
+                    (Message::Text(text), size)
+                }
+                Ok(Some(Ok(Frame::Binary(bytes)))) => {
+                    self.text.store(false, Ordering::Relaxed);
+                    let size = bytes.len();
+                    (Message::Bytes(bytes), size)
+                }
+                // tungstenite answers pings by itself
+                Ok(Some(Ok(Frame::Ping(_) | Frame::Pong(_) | Frame::Frame(_)))) => continue,
+                Ok(Some(Ok(Frame::Close(_)))) | Ok(None) => {
+                    break (DisconnectReason::PeerClosed, None)
+                }
+                // a page that goes away often doesn't say goodbye
+                Ok(Some(Err(
+                    WsError::Io(_)
+                    | WsError::ConnectionClosed
+                    | WsError::AlreadyClosed
+                    | WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake),
+                ))) => break (DisconnectReason::PeerClosed, None),
+                Ok(Some(Err(error))) => {
+                    self.notifier.metrics.decode_failed();
+                    let reason = DisconnectReason::ProtocolError(ws_error(error).to_string());
+                    break (reason, Some(CloseCode::Protocol));
+                }
+                Err(_) => {
+                    let idle_timeout = self.idle_timeout.unwrap_or_default();
+                    break (
+                        DisconnectReason::IdleTimeout(idle_timeout),
+                        Some(CloseCode::Away),
+                    );
+                }
+            };
+            self.notifier.metrics.received(size);
+            match self.notifier.notify(message).await {
+                Ok(()) => {}
+                Err(ExchangeError::QueueOverflow(..)) => {
+                    break (DisconnectReason::Overloaded, Some(CloseCode::Again))
+                }
+                Err(error) => return Err(error),
+            }
+        };
+
+        if let Some(code) = close {
+            let _ = self.closed_tx.try_send(CloseFrame {
+                code,
+                reason: format!("{reason:?}").into(),
+            });
+        }
+        drop(self.closed_tx);
+        self.notifier.registry.remove(&self.notifier.client_address);
+        debug!(?reason, "disconnected");
+        self.notifier.notify(Message::Disconnected(reason)).await
+    }
+}
+
+struct Sending {
+    text: Arc<AtomicBool>,
+    ping_interval: Option<Duration>,
+    notifier: ConnectionNotifier,
+}
+
+impl Sending {
+    async fn process(
+        self,
+        mut sink: SplitSink<Socket, Frame>,
+        mut client_sender_rx: Receiver<SendMessage>,
+        mut closed_rx: mpsc::Receiver<CloseFrame<'static>>,
+        mut signal: ShutdownSignal,
+    ) {
+        let metrics = self.notifier.metrics.clone();
+        let mut pings = self
+            .ping_interval
+            .map(|period| tokio::time::interval_at(Instant::now() + period, period));
+        let deadline = loop {
+            let ping = async {
+                match pings.as_mut() {
+                    Some(pings) => pings.tick().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let frame = tokio::select! {
+                biased;
+                deadline = signal.triggered() => break deadline,
+                message = client_sender_rx.recv() => match message {
+                    Some(SendMessage { bytes, .. }) => frame(&self.text, bytes),
+                    None => return,
+                },
+                close = closed_rx.recv() => {
+                    let _ = sink.send(Frame::Close(close)).await;
+                    return;
+                }
+                _ = ping => Frame::Ping(Vec::new()),
+            };
+            // pings are the connection's own business
+            let size = (!frame.is_ping()).then(|| frame.len());
+            match sink.send(frame).await {
+                Ok(()) => metrics.sent(size.unwrap_or_default()),
+                Err(error) => {
+                    if self
+                        .notifier
+                        .notify(Message::Error(ws_error(error)))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        };
+
+        // the channel closes once every reply to a received request is sent
+        let text = self.text;
+        drop(self.notifier);
+        while let Ok(Some(SendMessage { bytes, .. })) =
+            tokio::time::timeout_at(deadline, client_sender_rx.recv()).await
+        {
+            let size = bytes.len();
+            if sink.send(frame(&text, bytes)).await.is_err() {
+                return;
+            }
+            metrics.sent(size);
+        }
+        let going_away = CloseFrame {
+            code: CloseCode::Away,
+            reason: "server shutdown".into(),
+        };
+        let _ = sink.send(Frame::Close(Some(going_away))).await;
+    }
+}
+
+fn frame(text: &AtomicBool, bytes: Vec<u8>) -> Frame {
+    if text.load(Ordering::Relaxed) {
+        match String::from_utf8(bytes) {
+            Ok(text) => Frame::Text(text),
+            Err(error) => Frame::Binary(error.into_bytes()),
+        }
+    } else {
+        Frame::Binary(bytes)
+    }
+}
+
+fn close_frame(code: CloseCode, error: &ExchangeError) -> CloseFrame<'static> {
+    CloseFrame {
+        code,
+        reason: error.to_string().into(),
+    }
+}
+
+fn ws_error(error: WsError) -> ExchangeError {
+    match error {
+        WsError::Io(error) => ExchangeError::Io(error),
+        error => ExchangeError::WebSocketError(error.to_string()),
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::heartbeat::Heartbeat;
+use exchange_protocol::options::ExchangeOptions;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+fn heartbeat() -> Heartbeat {
+    Heartbeat::new(Duration::from_millis(50), Duration::from_millis(300))
+}
+
+async fn next_message(server: &TcpServer) -> Message {
+    let mut messages = server.messages.lock().await;
+    let notify = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    notify.message
+}
+
+#[tokio::test]
+async fn test_server_drops_silent_clients() {
+    let options = ExchangeOptions::default().with_heartbeat(heartbeat());
+    let server = TcpServer::start_with("127.0.0.1:0", options).await.unwrap();
+    let client = TcpClient::connect_with(
+        server.address,
+        ExchangeOptions::default().without_heartbeat(),
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(next_message(&server).await, Message::Connected(_)));
+    assert!(matches!(
+        next_message(&server).await,
+        Message::Disconnected(DisconnectReason::IdleTimeout(timeout)) if timeout == heartbeat().idle_timeout
+    ));
+
+    let mut messages = client.messages.lock().await;
+    assert!(matches!(messages.recv().await, Some(Message::Connected(_))));
+    assert!(matches!(
+        messages.recv().await,
+        Some(Message::Disconnected(DisconnectReason::PeerClosed))
+    ));
+}
+
+#[tokio::test]
+async fn test_heartbeats_keep_idle_connections_open() {
+    let options = ExchangeOptions::default().with_heartbeat(heartbeat());
+    let server = TcpServer::start_with("127.0.0.1:0", options.clone())
+        .await
+        .unwrap();
+    let mut client = TcpClient::connect_with(server.address, options)
+        .await
+        .unwrap();
+    assert!(matches!(next_message(&server).await, Message::Connected(_)));
+
+    tokio::time::sleep(heartbeat().idle_timeout * 3).await;
+    client.send(b"still here").await.unwrap();
+    assert!(matches!(
+        next_message(&server).await,
+        Message::Bytes(bytes) if bytes == b"still here"
+    ));
+}
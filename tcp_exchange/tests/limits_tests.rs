@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::limits::{Limits, OverflowPolicy};
+use exchange_protocol::options::ExchangeOptions;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_server_rejects_clients_over_the_connection_limit() {
+    let options = ExchangeOptions::default().with_limits(Limits::default().with_max_connections(1));
+    let server = TcpServer::start_with("127.0.0.1:0", options).await.unwrap();
+    let first = TcpClient::connect(server.address).await.unwrap();
+
+    assert!(matches!(
+        TcpClient::connect(server.address).await,
+        Err(ExchangeError::HandshakeRejected(_))
+    ));
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(_)
+    ));
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Error(ExchangeError::ConnectionLimit(1))
+    ));
+
+    drop(first);
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Disconnected(_)
+    ));
+    TcpClient::connect(server.address).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_server_disconnects_clients_flooding_a_full_queue() {
+    let limits = Limits::default()
+        .with_queue_size(2)
+        .with_overflow(OverflowPolicy::Disconnect);
+    let options = ExchangeOptions::default().with_limits(limits);
+    let server = TcpServer::start_with("127.0.0.1:0", options).await.unwrap();
+    let mut client = TcpClient::connect(server.address).await.unwrap();
+
+    // nobody reads the server's messages
+    for _ in 0..100 {
+        if client.send(b"reading").await.is_err() {
+            break;
+        }
+    }
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(replies.recv().await, Some(Message::Connected(_))));
+    assert!(matches!(
+        tokio::time::timeout(TIMEOUT, replies.recv()).await,
+        Ok(Some(Message::Disconnected(DisconnectReason::Overloaded)))
+    ));
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::reconnect::Reconnect;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn reconnecting() -> ExchangeOptions {
+    let reconnect = Reconnect::new(Duration::from_millis(20), Duration::from_millis(100));
+    ExchangeOptions::default().with_reconnect(reconnect.with_max_attempts(20))
+}
+
+async fn stop(server: TcpServer) {
+    let shutdown = server.shutdown_handle();
+    // nobody takes the server's messages, there is nothing to drain
+    shutdown.shutdown_within(Duration::from_millis(100));
+    shutdown.stopped().await;
+}
+
+#[tokio::test]
+async fn test_client_reconnects_to_a_restarted_server() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let address = server.address;
+    let mut client = TcpClient::connect_with(address, reconnecting())
+        .await
+        .unwrap();
+    let messages = client.messages.clone();
+    let mut messages = messages.lock().await;
+    assert!(matches!(messages.recv().await, Some(Message::Connected(_))));
+
+    stop(server).await;
+    assert!(matches!(
+        messages.recv().await,
+        Some(Message::Disconnected(DisconnectReason::ServerShutdown))
+    ));
+    // waits for the server to come back
+    client.send(b"while away").await.unwrap();
+
+    let server = TcpServer::start(address).await.unwrap();
+    loop {
+        match tokio::time::timeout(TIMEOUT, messages.recv())
+            .await
+            .unwrap()
+        {
+            Some(Message::Reconnecting { .. } | Message::Error(_)) => {}
+            Some(Message::Connected(_)) => break,
+            message => panic!("unexpected {message:?}"),
+        }
+    }
+
+    let mut received = server.messages.lock().await;
+    assert!(matches!(
+        received.recv().await.unwrap().message,
+        Message::Connected(_)
+    ));
+    assert!(matches!(
+        received.recv().await.unwrap().message,
+        Message::Bytes(bytes) if bytes == b"while away"
+    ));
+}
+
+#[tokio::test]
+async fn test_client_gives_up_reconnecting() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let reconnect = Reconnect::new(Duration::from_millis(10), Duration::from_millis(10));
+    let options = ExchangeOptions::default().with_reconnect(reconnect.with_max_attempts(2));
+    let mut client = TcpClient::connect_with(server.address, options)
+        .await
+        .unwrap();
+    stop(server).await;
+
+    let mut messages = client.messages.lock().await;
+    let mut attempts = Vec::new();
+    while let Some(message) = tokio::time::timeout(TIMEOUT, messages.recv())
+        .await
+        .unwrap()
+    {
+        if let Message::Reconnecting { attempt, .. } = message {
+            attempts.push(attempt);
+        }
+    }
+    drop(messages);
+    assert_eq!(attempts, [1, 2]);
+    assert!(matches!(
+        client.send(b"too late").await,
+        Err(ExchangeError::Io(_))
+    ));
+}
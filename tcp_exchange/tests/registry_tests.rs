@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::error::ExchangeError;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_server_sends_and_broadcasts_to_connected_clients() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let first = TcpClient::connect(server.address).await.unwrap();
+    let second = TcpClient::connect(server.address).await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    for _ in 0..2 {
+        assert!(matches!(
+            messages.recv().await.unwrap().message,
+            Message::Connected(_)
+        ));
+    }
+    assert_eq!(server.clients().len(), 2);
+
+    server.send(&first.address, b"only first").await.unwrap();
+    server.try_send(&second.address, b"only second").unwrap();
+    assert_eq!(server.broadcast(b"everybody"), 2);
+
+    let mut first_messages = first.messages.lock().await;
+    assert!(matches!(
+        first_messages.recv().await,
+        Some(Message::Connected(_))
+    ));
+    assert!(
+        matches!(first_messages.recv().await, Some(Message::Bytes(bytes)) if bytes == b"only first")
+    );
+    assert!(
+        matches!(first_messages.recv().await, Some(Message::Bytes(bytes)) if bytes == b"everybody")
+    );
+    let mut second_messages = second.messages.lock().await;
+    assert!(matches!(
+        second_messages.recv().await,
+        Some(Message::Connected(_))
+    ));
+    assert!(
+        matches!(second_messages.recv().await, Some(Message::Bytes(bytes)) if bytes == b"only second")
+    );
+    assert!(
+        matches!(second_messages.recv().await, Some(Message::Bytes(bytes)) if bytes == b"everybody")
+    );
+}
+
+#[tokio::test]
+async fn test_server_disconnects_a_single_client() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let client = TcpClient::connect(server.address).await.unwrap();
+    let other = TcpClient::connect(server.address).await.unwrap();
+
+    let client_address = client.address;
+    let mut messages = server.messages.lock().await;
+    for _ in 0..2 {
+        messages.recv().await.unwrap();
+    }
+    server.disconnect(&client_address).unwrap();
+
+    let notify = messages.recv().await.unwrap();
+    assert_eq!(notify.address, client_address);
+    assert!(matches!(
+        notify.message,
+        Message::Disconnected(DisconnectReason::ClosedByServer)
+    ));
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(replies.recv().await, Some(Message::Connected(_))));
+    assert!(matches!(
+        tokio::time::timeout(TIMEOUT, replies.recv()).await,
+        Ok(Some(Message::Disconnected(
+            DisconnectReason::ClosedByServer
+        )))
+    ));
+
+    assert_eq!(server.clients().len(), 1);
+    assert_eq!(server.clients()[0].address, other.address);
+    assert!(matches!(
+        server.send(&client_address, b"gone").await,
+        Err(ExchangeError::UnknownClient(_))
+    ));
+    assert!(matches!(
+        server.try_send(&client_address, b"gone"),
+        Err(ExchangeError::UnknownClient(_))
+    ));
+}
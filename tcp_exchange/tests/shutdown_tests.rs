@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_shutdown_drains_replies_and_releases_the_port() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let mut client = TcpClient::connect(server.address).await.unwrap();
+    client.send(b"turn off").await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(_)
+    ));
+    let request = messages.recv().await.unwrap();
+    assert!(matches!(&request.message, Message::Bytes(bytes) if bytes == b"turn off"));
+
+    let shutdown = server.shutdown_handle();
+    shutdown.shutdown_within(TIMEOUT);
+    request.reply(b"done".to_vec()).await.unwrap();
+    drop(request);
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Disconnected(DisconnectReason::ServerShutdown)
+    ));
+    assert!(messages.recv().await.is_none());
+    tokio::time::timeout(TIMEOUT, shutdown.stopped())
+        .await
+        .unwrap();
+
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(replies.recv().await, Some(Message::Connected(_))));
+    assert!(matches!(replies.recv().await, Some(Message::Bytes(bytes)) if bytes == b"done"));
+    assert!(matches!(
+        replies.recv().await,
+        Some(Message::Disconnected(DisconnectReason::ServerShutdown))
+    ));
+
+    assert!(TcpStream::connect(server.address).await.is_err());
+    TcpListener::bind(server.address).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_stops_waiting_for_replies_at_the_deadline() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let mut client = TcpClient::connect(server.address).await.unwrap();
+    client.send(b"turn off").await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    messages.recv().await.unwrap();
+    let _unanswered = messages.recv().await.unwrap();
+
+    let shutdown = server.shutdown_handle();
+    shutdown.shutdown_within(Duration::from_millis(200));
+    tokio::time::timeout(TIMEOUT, shutdown.stopped())
+        .await
+        .unwrap();
+}
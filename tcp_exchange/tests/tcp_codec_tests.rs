@@ -0,0 +1,78 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use exchange_protocol::codecs::codec::CodecKind;
+use exchange_protocol::codecs::json_lines_codec::JsonLinesCodec;
+use exchange_protocol::domain::Message;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::CompressionKind;
+use exchange_protocol::options::ExchangeOptions;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+
+#[tokio::test]
+async fn test_json_lines_server_talks_to_text_clients() {
+    let options = ExchangeOptions::new(JsonLinesCodec::default()).without_handshake();
+    let server = TcpServer::start_with("127.0.0.1:0", options).await.unwrap();
+
+    let mut client = TcpStream::connect(server.address).await.unwrap();
+    client.write_all(b"{\"ping\":1}\r\n").await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(info) if info.handshake.codec == CodecKind::JsonLines
+    ));
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == b"{\"ping\":1}"));
+
+    notify.reply(b"{\"pong\":1}".to_vec()).await.unwrap();
+    let mut line = String::new();
+    BufReader::new(client).read_line(&mut line).await.unwrap();
+    assert_eq!(line, "{\"pong\":1}\n");
+}
+
+#[tokio::test]
+async fn test_handshake_rejects_clients_with_another_codec() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+
+    let client = TcpClient::connect_with(
+        server.address,
+        ExchangeOptions::new(JsonLinesCodec::default()),
+    )
+    .await;
+    assert!(matches!(client, Err(ExchangeError::HandshakeRejected(_))));
+
+    let client = TcpClient::connect(server.address).await.unwrap();
+    assert_eq!(client.handshake.codec, CodecKind::LengthPrefixed);
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Error(_)
+    ));
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(info) if info.handshake == client.handshake
+    ));
+}
+
+#[tokio::test]
+async fn test_large_frames_are_compressed_transparently() {
+    let server = TcpServer::start("127.0.0.1:0").await.unwrap();
+    let mut client = TcpClient::connect(server.address).await.unwrap();
+    assert_eq!(client.handshake.compression, Some(CompressionKind::Zstd));
+
+    let report = "{\"device\":\"thermometer\",\"celsius\":21.5}"
+        .repeat(1_000)
+        .into_bytes();
+    client.send(&report).await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    let notify = loop {
+        let notify = messages.recv().await.unwrap();
+        if let Message::Bytes(_) = notify.message {
+            break notify;
+        }
+    };
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == &report));
+}
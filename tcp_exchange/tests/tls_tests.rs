@@ -0,0 +1,129 @@
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa};
+
+use exchange_protocol::domain::{Message, PeerIdentity};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::handshake::AuthMethod;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::tcp_server::TcpServer;
+use tcp_exchange::tls::*;
+
+struct Authority(rcgen::Certificate);
+
+impl Authority {
+    fn new(name: &str) -> Authority {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, name);
+        Authority(rcgen::Certificate::from_params(params).unwrap())
+    }
+
+    fn certificate(&self) -> Certificate {
+        Certificate(self.0.serialize_der().unwrap())
+    }
+
+    fn issue(&self, common_name: &str, dns_names: &[&str]) -> (Vec<Certificate>, PrivateKey) {
+        let mut params = CertificateParams::new(
+            dns_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>(),
+        );
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        let issued = rcgen::Certificate::from_params(params).unwrap();
+        (
+            vec![Certificate(
+                issued.serialize_der_with_signer(&self.0).unwrap(),
+            )],
+            PrivateKey(issued.serialize_private_key_der()),
+        )
+    }
+}
+
+#[tokio::test]
+async fn test_tls_exchange() {
+    let authority = Authority::new("house ca");
+    let (certificates, key) = authority.issue("house server", &["localhost"]);
+    let server = TcpServer::start_tls("127.0.0.1:0", ServerTls::new(certificates, key).unwrap())
+        .await
+        .unwrap();
+
+    let roots = root_store(&[authority.certificate()]).unwrap();
+    let tls = ClientTls::new(roots, "localhost").unwrap();
+    let mut client = TcpClient::connect_tls(server.address, tls).await.unwrap();
+    assert_eq!(client.handshake.auth_method, AuthMethod::Credentials);
+
+    client.send(b"turn off").await.unwrap();
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(_)
+    ));
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == b"turn off"));
+    assert_eq!(notify.identity, None);
+
+    notify.reply(b"done".to_vec()).await.unwrap();
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(
+        replies.recv().await.unwrap(),
+        Message::Connected(info) if matches!(
+            info.peer_identity.as_deref(),
+            Some(PeerIdentity::Certificate { common_name: Some(name), .. }) if name == "house server"
+        )
+    ));
+    assert!(matches!(replies.recv().await.unwrap(), Message::Bytes(bytes) if bytes == b"done"));
+}
+
+#[tokio::test]
+async fn test_tls_client_rejects_untrusted_server() {
+    let (certificates, key) = Authority::new("rogue ca").issue("rogue", &["localhost"]);
+    let server = TcpServer::start_tls("127.0.0.1:0", ServerTls::new(certificates, key).unwrap())
+        .await
+        .unwrap();
+
+    let roots = root_store(&[Authority::new("house ca").certificate()]).unwrap();
+    let tls = ClientTls::new(roots, "localhost").unwrap();
+    assert!(matches!(
+        TcpClient::connect_tls(server.address, tls).await,
+        Err(ExchangeError::TlsError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_mutual_tls_identifies_clients_by_certificate() {
+    let authority = Authority::new("house ca");
+    let roots = root_store(&[authority.certificate()]).unwrap();
+    let (certificates, key) = authority.issue("house server", &["localhost"]);
+    let server_tls = ServerTls::with_client_auth(certificates, key, roots.clone()).unwrap();
+    let server = TcpServer::start_tls("127.0.0.1:0", server_tls)
+        .await
+        .unwrap();
+
+    let anonymous = ClientTls::new(roots.clone(), "localhost").unwrap();
+    assert!(TcpClient::connect_tls(server.address, anonymous)
+        .await
+        .is_err());
+
+    let (certificates, key) = authority.issue("kitchen panel", &["panel.house"]);
+    let tls = ClientTls::with_identity(roots, "localhost", certificates, key).unwrap();
+    let client = TcpClient::connect_tls(server.address, tls).await.unwrap();
+    assert_eq!(client.handshake.auth_method, AuthMethod::ClientCertificate);
+
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Error(ExchangeError::TlsError(_))
+    ));
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(
+        &notify.message,
+        Message::Connected(info) if info.peer_identity == notify.identity
+    ));
+    assert!(matches!(
+        notify.identity.as_deref(),
+        Some(PeerIdentity::Certificate { common_name: Some(name), dns_names, .. })
+            if name == "kitchen panel" && dns_names == &["panel.house"]
+    ));
+}
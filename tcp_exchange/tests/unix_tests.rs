@@ -0,0 +1,69 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+use exchange_protocol::domain::{Message, PeerIdentity};
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::options::ExchangeOptions;
+use tcp_exchange::tcp_client::TcpClient;
+use tcp_exchange::unix_server::{LocalAccess, UnixServer};
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tcp_exchange_{name}_{}.sock", std::process::id()))
+}
+
+#[tokio::test]
+async fn test_local_client_is_identified_by_its_credentials() {
+    let server = UnixServer::start(socket_path("owner")).await.unwrap();
+    let owner = std::fs::metadata(&server.path).unwrap().uid();
+    let mut client = TcpClient::connect_unix(&server.path).await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(
+        notify.identity.as_deref(),
+        Some(PeerIdentity::Process { uid, pid: Some(pid), .. })
+            if *uid == owner && *pid == std::process::id() as i32
+    ));
+    assert_eq!(server.clients()[0].address, notify.address);
+
+    client.send(b"ping").await.unwrap();
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == b"ping"));
+    notify.reply(b"pong".to_vec()).await.unwrap();
+
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(
+        replies.recv().await,
+        Some(Message::Connected(info))
+            if matches!(info.peer_identity.as_deref(), Some(PeerIdentity::Process { uid, .. }) if *uid == owner)
+    ));
+    assert!(matches!(replies.recv().await, Some(Message::Bytes(bytes)) if bytes == b"pong"));
+}
+
+#[tokio::test]
+async fn test_local_client_not_allowed_is_rejected() {
+    let access = LocalAccess::Users {
+        uids: vec![],
+        gids: vec![],
+    };
+    let server = UnixServer::start_with(socket_path("nobody"), ExchangeOptions::default(), access)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        TcpClient::connect_unix(&server.path).await,
+        Err(ExchangeError::HandshakeRejected(_))
+    ));
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Error(ExchangeError::LocalAccessDenied(..))
+    ));
+    assert!(server.clients().is_empty());
+
+    server.shutdown_handle().shutdown();
+    server.shutdown_handle().stopped().await;
+    assert!(!server.path.exists());
+}
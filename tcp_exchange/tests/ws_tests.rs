@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message as Frame;
+
+use exchange_protocol::domain::{DisconnectReason, Message};
+use tcp_exchange::ws_server::WsServer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_replies_go_out_as_the_kind_of_message_received() {
+    let server = WsServer::start("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", server.address);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    assert!(matches!(
+        messages.recv().await.unwrap().message,
+        Message::Connected(_)
+    ));
+
+    socket.send(Frame::Text("{\"id\":1}".into())).await.unwrap();
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(&notify.message, Message::Text(text) if text == "{\"id\":1}"));
+    notify.reply(b"{\"id\":1}".to_vec()).await.unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        Frame::Text("{\"id\":1}".into())
+    );
+
+    socket.send(Frame::Binary(vec![1, 2, 3])).await.unwrap();
+    let notify = messages.recv().await.unwrap();
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == &[1, 2, 3]));
+    notify.reply(vec![3, 2, 1]).await.unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        Frame::Binary(vec![3, 2, 1])
+    );
+
+    assert_eq!(server.broadcast(b"pushed"), 1);
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        Frame::Binary(b"pushed".to_vec())
+    );
+
+    drop(socket);
+    assert!(matches!(
+        tokio::time::timeout(TIMEOUT, messages.recv()).await,
+        Ok(Some(notify)) if matches!(notify.message, Message::Disconnected(DisconnectReason::PeerClosed))
+    ));
+    assert!(server.clients().is_empty());
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_with_going_away() {
+    let server = WsServer::start("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", server.address);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    server.messages.lock().await.recv().await.unwrap();
+
+    server
+        .shutdown_handle()
+        .shutdown_within(Duration::from_millis(100));
+    let close = tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap();
+    assert!(matches!(
+        close,
+        Some(Ok(Frame::Close(Some(frame)))) if frame.code == CloseCode::Away
+    ));
+}
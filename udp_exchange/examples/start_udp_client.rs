@@ -1,10 +1,14 @@
 use std::error::Error;
 
 use exchange_protocol::domain::Message;
+use tracing_subscriber::EnvFilter;
 use udp_exchange::udp_client::UdpClient;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let server_address = "127.0.0.1:45959";
     let udp_local_address = "127.0.0.1:41868";
     let mut client = UdpClient::connect(server_address, udp_local_address).await?;
@@ -12,7 +16,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let receiver = client.messages.clone();
     while let Some(msg) = receiver.lock().await.recv().await {
         match msg {
-            Message::Connected => {
+            Message::Connected(_) => {
                 println!("client: connected to server '{server_address}'");
                 client
                     .send("hello server".as_bytes())
@@ -36,7 +40,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     }
                 }
             }
-            Message::Disconnected => println!("client: disconnected from '{server_address}'"),
+            // only WebSocket has text frames
+            Message::Text(_) => {}
+            Message::Error(error) => {
+                eprintln!("client: exchange with '{server_address}' failed: {error}")
+            }
+            // udp clients don't reconnect by themselves
+            Message::Reconnecting { .. } => {}
+            Message::Disconnected(reason) => {
+                println!("client: disconnected from '{server_address}': {reason:?}")
+            }
         };
     }
 
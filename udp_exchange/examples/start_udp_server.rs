@@ -1,10 +1,14 @@
 use std::error::Error;
 
 use exchange_protocol::domain::Message;
+use tracing_subscriber::EnvFilter;
 use udp_exchange::udp_server::UdpServer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
     let server_address = "127.0.0.1:45959";
     let server = UdpServer::start(server_address).await?;
 
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use exchange_protocol::codecs::codec::Codec;
+use exchange_protocol::compression::Compressor;
+use exchange_protocol::error::ExchangeError;
+
+/// CRC32C of the rest of the datagram followed by the sender session id.
+pub const HEADER_SIZE: usize = 4 + 8;
+
+pub fn new_session_id() -> u64 {
+    rand::random()
+}
+
+/// Puts the integrity header in front of an encoded frame.
+pub fn seal(session_id: u64, frame: &[u8]) -> Vec<u8> {
+    let session = session_id.to_be_bytes();
+    let checksum = crc32c::crc32c_append(crc32c::crc32c(&session), frame);
+
+    let mut datagram = Vec::with_capacity(HEADER_SIZE + frame.len());
+    datagram.extend_from_slice(&checksum.to_be_bytes());
+    datagram.extend_from_slice(&session);
+    datagram.extend_from_slice(frame);
+    datagram
+}
+
+/// Checks the integrity header, returning the sender session and the frame.
+pub fn open(datagram: &[u8]) -> Result<(u64, &[u8]), ExchangeError> {
+    if datagram.len() < HEADER_SIZE {
+        return Err(ExchangeError::CorruptedDatagram(format!(
+            "{} bytes are too short for the header",
+            datagram.len()
+        )));
+    }
+    let (checksum, sealed) = datagram.split_at(4);
+    let checksum = u32::from_be_bytes(checksum.try_into().expect("checksum is 4 bytes"));
+    if crc32c::crc32c(sealed) != checksum {
+        return Err(ExchangeError::CorruptedDatagram(
+            "checksum mismatch".to_string(),
+        ));
+    }
+
+    let (session, frame) = sealed.split_at(8);
+    let session_id = u64::from_be_bytes(session.try_into().expect("session is 8 bytes"));
+    Ok((session_id, frame))
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DatagramCounts {
+    pub delivered: u64,
+    /// Failed the checksum or decryption, or too short for the header.
+    pub corrupted: u64,
+    /// Sent by another session of a known peer, e.g. a restarted client reusing the port.
+    pub foreign_session: u64,
+    /// Intact, but not a valid frame for the codec.
+    pub malformed: u64,
+    /// Authentic, but already received or too old to tell.
+    pub replayed: u64,
+}
+
+impl DatagramCounts {
+    pub fn dropped(&self) -> u64 {
+        self.corrupted + self.foreign_session + self.malformed + self.replayed
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DatagramStats {
+    pub(crate) delivered: AtomicU64,
+    pub(crate) corrupted: AtomicU64,
+    pub(crate) foreign_session: AtomicU64,
+    pub(crate) malformed: AtomicU64,
+    pub(crate) replayed: AtomicU64,
+}
+
+impl DatagramStats {
+    pub fn counts(&self) -> DatagramCounts {
+        DatagramCounts {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+            foreign_session: self.foreign_session.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            replayed: self.replayed.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Compression agreed on with each peer, peers without a handshake get the default one.
+#[derive(Debug, Clone)]
+pub struct PeerCompressors {
+    default: Compressor,
+    peers: Arc<Mutex<HashMap<SocketAddr, Compressor>>>,
+}
+
+impl PeerCompressors {
+    pub fn new(default: Compressor) -> PeerCompressors {
+        PeerCompressors {
+            default,
+            peers: Default::default(),
+        }
+    }
+
+    pub fn get(&self, peer: &SocketAddr) -> Compressor {
+        let peers = self.peers.lock().expect("peer compressors lock");
+        peers.get(peer).copied().unwrap_or(self.default)
+    }
+
+    pub fn insert(&self, peer: SocketAddr, compressor: Compressor) {
+        let mut peers = self.peers.lock().expect("peer compressors lock");
+        peers.insert(peer, compressor);
+    }
+
+    /// Whether the peer had done the handshake.
+    pub fn remove(&self, peer: &SocketAddr) -> bool {
+        let mut peers = self.peers.lock().expect("peer compressors lock");
+        peers.remove(peer).is_some()
+    }
+
+    /// The peers that did the handshake.
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        let peers = self.peers.lock().expect("peer compressors lock");
+        peers.keys().copied().collect()
+    }
+}
+
+/// Turns outgoing payloads into datagrams, sealed when `session_id` is set.
+#[derive(Debug, Clone)]
+pub struct DatagramEncoder<C: Codec> {
+    codec: C,
+    compressors: PeerCompressors,
+    session_id: Option<u64>,
+}
+
+impl<C: Codec> DatagramEncoder<C> {
+    pub fn new(
+        codec: C,
+        compressors: PeerCompressors,
+        session_id: Option<u64>,
+    ) -> DatagramEncoder<C> {
+        DatagramEncoder {
+            codec,
+            compressors,
+            session_id,
+        }
+    }
+
+    pub fn encode(&self, peer: &SocketAddr, bytes: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let payload = self.compressors.get(peer).pack(bytes)?;
+        let frame = self.codec.encode_frame(&payload)?;
+        Ok(match self.session_id {
+            Some(session_id) => seal(session_id, &frame),
+            None => frame,
+        })
+    }
+}
+
+/// Unpacks incoming datagrams, dropping and counting the bad ones.
+///
+/// With checksums every peer is bound to the session of its first intact datagram until
+/// it is forgotten, e.g. when the peer handshakes again.
+pub struct DatagramDecoder<C: Codec> {
+    codec: C,
+    compressors: PeerCompressors,
+    checksums: bool,
+    sessions: HashMap<SocketAddr, u64>,
+    stats: Arc<DatagramStats>,
+}
+
+impl<C: Codec> DatagramDecoder<C> {
+    pub fn new(
+        codec: C,
+        compressors: PeerCompressors,
+        checksums: bool,
+        stats: Arc<DatagramStats>,
+    ) -> DatagramDecoder<C> {
+        DatagramDecoder {
+            codec,
+            compressors,
+            checksums,
+            sessions: HashMap::new(),
+            stats,
+        }
+    }
+
+    pub fn is_sealed(&self, datagram: &[u8]) -> bool {
+        self.checksums && open(datagram).is_ok()
+    }
+
+    pub fn forget(&mut self, peer: &SocketAddr) {
+        self.sessions.remove(peer);
+    }
+
+    pub fn decode(&mut self, peer: SocketAddr, datagram: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let frame = if self.checksums {
+            let (session_id, frame) = open(datagram).inspect_err(|_| {
+                DatagramStats::count(&self.stats.corrupted);
+            })?;
+            let known_session = *self.sessions.entry(peer).or_insert(session_id);
+            if known_session != session_id {
+                DatagramStats::count(&self.stats.foreign_session);
+                return Err(ExchangeError::ForeignSession(session_id, known_session));
+            }
+            frame
+        } else {
+            datagram
+        };
+
+        let bytes = self
+            .codec
+            .decode_datagram(frame)
+            .and_then(|payload| self.compressors.get(&peer).unpack(payload))
+            .inspect_err(|_| DatagramStats::count(&self.stats.malformed))?;
+        DatagramStats::count(&self.stats.delivered);
+        Ok(bytes)
+    }
+}
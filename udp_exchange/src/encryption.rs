@@ -0,0 +1,429 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret};
+
+use exchange_protocol::error::ExchangeError;
+
+use crate::datagram::{new_session_id, DatagramStats};
+
+pub const KEY_SIZE: usize = 32;
+/// Sender session and datagram counter in front, authentication tag at the end.
+pub const ENCRYPTION_OVERHEAD: usize = 8 + 8 + 16;
+/// How far behind the newest datagram of a session an older one may still arrive.
+pub const REPLAY_WINDOW: u64 = 128;
+
+const KEY_EXCHANGE_MAGIC: [u8; 4] = *b"HXKX";
+const KEY_EXCHANGE_SIZE: usize = 4 + 32 + 32;
+const RETIRED_SESSIONS: usize = 4096;
+
+/// Authenticated ChaCha20-Poly1305 encryption of every datagram.
+///
+/// Both sides share a key. With key exchange a fresh key is agreed on with every peer
+/// through X25519, the shared key then only authenticates the exchange.
+#[derive(Clone)]
+pub struct Encryption {
+    pre_shared_key: [u8; KEY_SIZE],
+    key_exchange: bool,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("key_exchange", &self.key_exchange)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    pub fn pre_shared(key: [u8; KEY_SIZE]) -> Encryption {
+        Encryption {
+            pre_shared_key: key,
+            key_exchange: false,
+        }
+    }
+
+    pub fn with_key_exchange(mut self) -> Encryption {
+        self.key_exchange = true;
+        self
+    }
+
+    pub fn key_exchange(&self) -> bool {
+        self.key_exchange
+    }
+
+    fn mac(&self, public_keys: &[&PublicKey]) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.pre_shared_key)
+            .expect("hmac takes keys of any size");
+        mac.update(&KEY_EXCHANGE_MAGIC);
+        for public_key in public_keys {
+            mac.update(public_key.as_bytes());
+        }
+        mac
+    }
+
+    fn encode_share(&self, public_key: &PublicKey, mac: Hmac<Sha256>) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(KEY_EXCHANGE_SIZE);
+        datagram.extend_from_slice(&KEY_EXCHANGE_MAGIC);
+        datagram.extend_from_slice(public_key.as_bytes());
+        datagram.extend_from_slice(&mac.finalize().into_bytes());
+        datagram
+    }
+
+    fn decode_share(
+        &self,
+        datagram: &[u8],
+        acknowledged: Option<&PublicKey>,
+    ) -> Result<PublicKey, ExchangeError> {
+        if !is_key_exchange(datagram) {
+            return Err(ExchangeError::HandshakeFailed(
+                "not a key exchange".to_string(),
+            ));
+        }
+        let public_key: [u8; 32] = datagram[4..36].try_into().expect("public key is 32 bytes");
+        let public_key = PublicKey::from(public_key);
+
+        let mut signed = vec![&public_key];
+        signed.extend(acknowledged);
+        self.mac(&signed)
+            .verify_slice(&datagram[36..])
+            .map_err(|_| {
+                ExchangeError::HandshakeFailed("key exchange is not authentic".to_string())
+            })?;
+        Ok(public_key)
+    }
+
+    fn exchanged_key(
+        &self,
+        shared: SharedSecret,
+        client: &PublicKey,
+        server: &PublicKey,
+    ) -> [u8; KEY_SIZE] {
+        let mut key = [0; KEY_SIZE];
+        Hkdf::<Sha256>::new(Some(&self.pre_shared_key), shared.as_bytes())
+            .expand_multi_info(
+                &[b"exchange key", client.as_bytes(), server.as_bytes()],
+                &mut key,
+            )
+            .expect("key size is valid for hkdf");
+        key
+    }
+}
+
+pub fn is_key_exchange(datagram: &[u8]) -> bool {
+    datagram.len() == KEY_EXCHANGE_SIZE && datagram.starts_with(&KEY_EXCHANGE_MAGIC)
+}
+
+/// The client half of a key exchange, kept until the server answers.
+pub struct KeyOffer {
+    secret: EphemeralSecret,
+    public_key: PublicKey,
+}
+
+impl KeyOffer {
+    pub fn new() -> KeyOffer {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public_key = PublicKey::from(&secret);
+        KeyOffer { secret, public_key }
+    }
+
+    pub fn encode(&self, encryption: &Encryption) -> Vec<u8> {
+        encryption.encode_share(&self.public_key, encryption.mac(&[&self.public_key]))
+    }
+
+    pub fn accept(
+        self,
+        encryption: &Encryption,
+        answer: &[u8],
+    ) -> Result<[u8; KEY_SIZE], ExchangeError> {
+        let server = encryption.decode_share(answer, Some(&self.public_key))?;
+        let shared = self.secret.diffie_hellman(&server);
+        Ok(encryption.exchanged_key(shared, &self.public_key, &server))
+    }
+}
+
+impl Default for KeyOffer {
+    fn default() -> Self {
+        KeyOffer::new()
+    }
+}
+
+/// Accepts every counter once, as long as it is not too far behind the newest one.
+#[derive(Debug, Default)]
+struct ReplayWindow {
+    newest: u64,
+    seen: u128,
+}
+
+impl ReplayWindow {
+    fn is_fresh(&self, counter: u64) -> bool {
+        if counter > self.newest {
+            return true;
+        }
+        let age = self.newest - counter;
+        age < REPLAY_WINDOW && self.seen & (1 << age) == 0
+    }
+
+    fn accept(&mut self, counter: u64) {
+        if counter > self.newest {
+            let shift = counter - self.newest;
+            self.seen = if shift < REPLAY_WINDOW {
+                self.seen << shift
+            } else {
+                0
+            };
+            self.newest = counter;
+        }
+        self.seen |= 1 << (self.newest - counter);
+    }
+}
+
+struct ReceivingSession {
+    peer: SocketAddr,
+    cipher: ChaCha20Poly1305,
+    window: ReplayWindow,
+}
+
+/// Sessions are bound to the address they were first seen from, and never come back once
+/// a peer moved on to a new one.
+#[derive(Default)]
+struct ReceivingSessions {
+    active: HashMap<u64, ReceivingSession>,
+    current: HashMap<SocketAddr, u64>,
+    retired: VecDeque<u64>,
+}
+
+impl ReceivingSessions {
+    fn retire_peer(&mut self, peer: &SocketAddr) {
+        if let Some(session_id) = self.current.remove(peer) {
+            self.active.remove(&session_id);
+            if self.retired.len() == RETIRED_SESSIONS {
+                self.retired.pop_front();
+            }
+            self.retired.push_back(session_id);
+        }
+    }
+}
+
+struct AnsweredOffer {
+    offer: Vec<u8>,
+    answer: Vec<u8>,
+}
+
+/// Encrypts outgoing and decrypts incoming datagrams, dropping and counting forged and
+/// replayed ones.
+///
+/// Every sender session derives its own key from the peer key, so the datagram counter
+/// alone makes the nonces unique.
+#[derive(Clone)]
+pub struct DatagramCipher {
+    encryption: Encryption,
+    keys: Arc<Mutex<HashMap<SocketAddr, [u8; KEY_SIZE]>>>,
+    answered_offers: Arc<Mutex<HashMap<SocketAddr, AnsweredOffer>>>,
+    session_id: u64,
+    counter: Arc<AtomicU64>,
+    sessions: Arc<Mutex<ReceivingSessions>>,
+    stats: Arc<DatagramStats>,
+}
+
+impl DatagramCipher {
+    pub fn new(encryption: Encryption, stats: Arc<DatagramStats>) -> DatagramCipher {
+        DatagramCipher {
+            encryption,
+            keys: Default::default(),
+            answered_offers: Default::default(),
+            session_id: new_session_id(),
+            counter: Default::default(),
+            sessions: Default::default(),
+            stats,
+        }
+    }
+
+    pub fn encryption(&self) -> &Encryption {
+        &self.encryption
+    }
+
+    /// Uses a key agreed on with a peer from now on, its earlier sessions are over.
+    pub fn set_peer_key(&self, peer: SocketAddr, key: [u8; KEY_SIZE]) {
+        self.keys
+            .lock()
+            .expect("cipher keys lock")
+            .insert(peer, key);
+        self.sessions
+            .lock()
+            .expect("cipher sessions lock")
+            .retire_peer(&peer);
+    }
+
+    /// Agrees on a key with a client offering one, repeated offers get the same answer.
+    pub fn answer_key_offer(
+        &self,
+        peer: SocketAddr,
+        offer: &[u8],
+    ) -> Result<Vec<u8>, ExchangeError> {
+        let mut answered = self.answered_offers.lock().expect("cipher offers lock");
+        if let Some(answered) = answered
+            .get(&peer)
+            .filter(|answered| answered.offer == offer)
+        {
+            return Ok(answered.answer.clone());
+        }
+
+        let client = self.encryption.decode_share(offer, None)?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let server = PublicKey::from(&secret);
+        let answer = self
+            .encryption
+            .encode_share(&server, self.encryption.mac(&[&server, &client]));
+        let shared = secret.diffie_hellman(&client);
+
+        self.set_peer_key(
+            peer,
+            self.encryption.exchanged_key(shared, &client, &server),
+        );
+        answered.insert(
+            peer,
+            AnsweredOffer {
+                offer: offer.to_vec(),
+                answer: answer.clone(),
+            },
+        );
+        Ok(answer)
+    }
+
+    pub fn seal(&self, peer: &SocketAddr, datagram: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let cipher = self.session_cipher(peer, self.session_id)?;
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let encrypted = cipher
+            .encrypt(&nonce(counter), datagram)
+            .map_err(|_| ExchangeError::EncryptionError("encryption failed".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(16 + encrypted.len());
+        sealed.extend_from_slice(&self.session_id.to_be_bytes());
+        sealed.extend_from_slice(&counter.to_be_bytes());
+        sealed.extend_from_slice(&encrypted);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, peer: SocketAddr, datagram: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        self.open_counted(peer, datagram).inspect_err(|error| {
+            let counter = match error {
+                ExchangeError::ReplayedDatagram(..) => &self.stats.replayed,
+                _ => &self.stats.corrupted,
+            };
+            DatagramStats::count(counter);
+        })
+    }
+
+    fn open_counted(&self, peer: SocketAddr, datagram: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        if datagram.len() < ENCRYPTION_OVERHEAD {
+            return Err(ExchangeError::CorruptedDatagram(format!(
+                "{} bytes are too short for an encrypted datagram",
+                datagram.len()
+            )));
+        }
+        let session_id = u64::from_be_bytes(datagram[..8].try_into().expect("8 bytes"));
+        let counter = u64::from_be_bytes(datagram[8..16].try_into().expect("8 bytes"));
+        let encrypted = &datagram[16..];
+        let replayed = || ExchangeError::ReplayedDatagram(session_id, counter);
+
+        let mut sessions = self.sessions.lock().expect("cipher sessions lock");
+        if let Some(session) = sessions.active.get_mut(&session_id) {
+            if session.peer != peer || !session.window.is_fresh(counter) {
+                return Err(replayed());
+            }
+            let bytes = decrypt(&session.cipher, counter, encrypted)?;
+            session.window.accept(counter);
+            return Ok(bytes);
+        }
+        if sessions.retired.contains(&session_id) {
+            return Err(replayed());
+        }
+
+        let cipher = self.session_cipher(&peer, session_id)?;
+        let bytes = decrypt(&cipher, counter, encrypted)?;
+        sessions.retire_peer(&peer);
+        let mut window = ReplayWindow::default();
+        window.accept(counter);
+        sessions.current.insert(peer, session_id);
+        sessions.active.insert(
+            session_id,
+            ReceivingSession {
+                peer,
+                cipher,
+                window,
+            },
+        );
+        Ok(bytes)
+    }
+
+    fn session_cipher(
+        &self,
+        peer: &SocketAddr,
+        session_id: u64,
+    ) -> Result<ChaCha20Poly1305, ExchangeError> {
+        let peer_key = if self.encryption.key_exchange {
+            let keys = self.keys.lock().expect("cipher keys lock");
+            *keys.get(peer).ok_or_else(|| {
+                ExchangeError::EncryptionError(format!("no key agreed on with '{peer}'"))
+            })?
+        } else {
+            self.encryption.pre_shared_key
+        };
+
+        let mut key = [0; KEY_SIZE];
+        Hkdf::<Sha256>::new(None, &peer_key)
+            .expand_multi_info(&[b"session key", &session_id.to_be_bytes()], &mut key)
+            .expect("key size is valid for hkdf");
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+/// Passes datagrams through untouched when there is no encryption.
+pub fn open_with<'a>(
+    cipher: Option<&DatagramCipher>,
+    peer: SocketAddr,
+    datagram: &'a [u8],
+) -> Result<Cow<'a, [u8]>, ExchangeError> {
+    match cipher {
+        Some(cipher) => cipher.open(peer, datagram).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(datagram)),
+    }
+}
+
+pub fn seal_with(
+    cipher: Option<&DatagramCipher>,
+    peer: &SocketAddr,
+    datagram: Vec<u8>,
+) -> Result<Vec<u8>, ExchangeError> {
+    match cipher {
+        Some(cipher) => cipher.seal(peer, &datagram),
+        None => Ok(datagram),
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+fn decrypt(
+    cipher: &ChaCha20Poly1305,
+    counter: u64,
+    encrypted: &[u8],
+) -> Result<Vec<u8>, ExchangeError> {
+    cipher
+        .decrypt(&nonce(counter), encrypted)
+        .map_err(|_| ExchangeError::CorruptedDatagram("authentication failed".to_string()))
+}
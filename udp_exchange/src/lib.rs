@@ -1,3 +1,5 @@
+pub mod datagram;
+pub mod encryption;
 pub mod udp_client;
 pub mod udp_server;
 
@@ -1,20 +1,44 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::net::{ToSocketAddrs, UdpSocket};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info_span, warn, Instrument};
 
-use exchange_protocol::codecs::MAX_SIZE;
-use exchange_protocol::domain::Message;
+use exchange_protocol::codecs::codec::{Codec, MAX_DATAGRAM_SIZE};
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{ConnectionInfo, DisconnectReason, Message};
 use exchange_protocol::error::ExchangeError;
 use exchange_protocol::error::ExchangeError::{Io, SendNotifyError};
+use exchange_protocol::handshake::{is_handshake, HandshakeInfo, HandshakeReply, Hello};
+use exchange_protocol::heartbeat::{Control, Heartbeat};
+use exchange_protocol::options::ExchangeOptions;
 
-pub struct UdpClient {
+use crate::datagram::{
+    new_session_id, DatagramDecoder, DatagramEncoder, DatagramStats, PeerCompressors,
+};
+use crate::encryption::{
+    is_key_exchange, open_with, seal_with, DatagramCipher, Encryption, KeyOffer, KEY_SIZE,
+};
+
+/// Datagrams get lost, so the hello is repeated until the server answers.
+const HELLO_RESEND_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct UdpClient<C: Codec = LengthPrefixedCodec> {
     pub address: SocketAddr,
     pub server_address: SocketAddr,
     pub messages: Arc<Mutex<Receiver<Message>>>,
+    pub handshake: HandshakeInfo,
+    pub codec: C,
+    pub stats: Arc<DatagramStats>,
+    encoder: DatagramEncoder<C>,
+    cipher: Option<DatagramCipher>,
     socket: Arc<UdpSocket>,
+    heartbeat: Option<JoinHandle<()>>,
 }
 
 impl UdpClient {
@@ -22,56 +46,312 @@ impl UdpClient {
         server_addrs: Addrs,
         local_address: Addrs,
     ) -> Result<UdpClient, ExchangeError> {
+        let options = ExchangeOptions::new(LengthPrefixedCodec::datagram());
+        Self::connect_with(server_addrs, local_address, options).await
+    }
+
+    pub async fn connect_encrypted<Addrs: ToSocketAddrs>(
+        server_addrs: Addrs,
+        local_address: Addrs,
+        encryption: Encryption,
+    ) -> Result<UdpClient, ExchangeError> {
+        let options = ExchangeOptions::new(LengthPrefixedCodec::datagram());
+        Self::connect_encrypted_with(server_addrs, local_address, options, encryption).await
+    }
+}
+
+impl<C: Codec> UdpClient<C> {
+    pub async fn connect_with<Addrs: ToSocketAddrs>(
+        server_addrs: Addrs,
+        local_address: Addrs,
+        options: ExchangeOptions<C>,
+    ) -> Result<UdpClient<C>, ExchangeError> {
+        Self::open(server_addrs, local_address, options, None).await
+    }
+
+    pub async fn connect_encrypted_with<Addrs: ToSocketAddrs>(
+        server_addrs: Addrs,
+        local_address: Addrs,
+        options: ExchangeOptions<C>,
+        encryption: Encryption,
+    ) -> Result<UdpClient<C>, ExchangeError> {
+        Self::open(server_addrs, local_address, options, Some(encryption)).await
+    }
+
+    async fn open<Addrs: ToSocketAddrs>(
+        server_addrs: Addrs,
+        local_address: Addrs,
+        mut options: ExchangeOptions<C>,
+        encryption: Option<Encryption>,
+    ) -> Result<UdpClient<C>, ExchangeError> {
+        options.codec = options
+            .codec
+            .negotiate(LengthPrefixedCodec::datagram().max_frame_size());
         let socket = UdpSocket::bind(local_address).await?;
         socket.connect(server_addrs).await?;
 
         let server_address = socket.peer_addr()?;
         let client_address = socket.local_addr()?;
 
+        let stats = Arc::new(DatagramStats::default());
+        let cipher = encryption.map(|encryption| DatagramCipher::new(encryption, stats.clone()));
+        if let Some(cipher) = cipher.as_ref().filter(|c| c.encryption().key_exchange()) {
+            let key = Self::exchange_keys(&socket, cipher, options.handshake_timeout).await?;
+            cipher.set_peer_key(server_address, key);
+        }
+
+        let handshake = if options.handshake {
+            let hello = options.hello();
+            Self::handshake(&socket, cipher.as_ref(), &hello, options.handshake_timeout).await?
+        } else {
+            options.assumed_handshake()
+        };
+        let codec = options.negotiated_codec(&handshake);
+
         let socket_arc = Arc::new(socket);
+        let session_id = options.checksums.then(new_session_id);
+        let compressors = PeerCompressors::new(options.compressor(&handshake));
+        let encoder = DatagramEncoder::new(codec.clone(), compressors.clone(), session_id);
+        let decoder =
+            DatagramDecoder::new(codec.clone(), compressors, options.checksums, stats.clone());
 
-        let (message_notifier_tx, message_notifier_rx) = mpsc::channel::<Message>(1000);
+        let (message_notifier_tx, message_notifier_rx) =
+            mpsc::channel::<Message>(options.limits.queue_size);
         let receive_socket = socket_arc.clone();
-        tokio::spawn(async move {
-            UdpClient::receive_messages(receive_socket, message_notifier_tx, server_address)
-                .await
-                .unwrap_or_else(|error| {
-                    eprintln!("receiving messages failed from server '{server_address}': {error:?}")
-                })
+        let receive_cipher = cipher.clone();
+        let heartbeat = options.active_heartbeat();
+        // dropped once receiving ends, which stops the pings
+        let (closed_tx, closed_rx) = oneshot::channel::<()>();
+        let pings = heartbeat.map(|heartbeat| {
+            tokio::spawn(Self::send_pings(
+                socket_arc.clone(),
+                encoder.clone(),
+                cipher.clone(),
+                heartbeat,
+                closed_rx,
+            ))
         });
+        let span = info_span!("udp_client", server = %server_address);
+        tokio::spawn(
+            async move {
+                let _closed_tx = closed_tx;
+                Self::receive_messages(
+                    receive_socket,
+                    receive_cipher,
+                    decoder,
+                    handshake,
+                    heartbeat,
+                    message_notifier_tx,
+                    server_address,
+                )
+                .await
+                .unwrap_or_else(|error| warn!(?error, "receiving messages failed"))
+            }
+            .instrument(span),
+        );
 
         Ok(UdpClient {
             address: client_address,
             server_address,
             messages: Arc::new(Mutex::new(message_notifier_rx)),
+            handshake,
+            codec,
+            stats,
+            encoder,
+            cipher,
             socket: socket_arc,
+            heartbeat: pings,
         })
     }
 
+    async fn send_pings(
+        socket: Arc<UdpSocket>,
+        encoder: DatagramEncoder<C>,
+        cipher: Option<DatagramCipher>,
+        heartbeat: Heartbeat,
+        mut closed: oneshot::Receiver<()>,
+    ) {
+        let server_address = match socket.peer_addr() {
+            Ok(server_address) => server_address,
+            Err(_) => return,
+        };
+        let mut interval = tokio::time::interval(heartbeat.interval);
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // sealed anew every time, encrypted datagrams are never repeated
+                    let ping = encoder
+                        .encode(&server_address, &Control::Ping.encode())
+                        .and_then(|ping| seal_with(cipher.as_ref(), &server_address, ping));
+                    match ping {
+                        Ok(ping) => {
+                            // a lost ping is made up for by the next one
+                            let _ = socket.send(&ping).await;
+                        }
+                        Err(error) => {
+                            warn!(server = %server_address, %error, "cannot ping");
+                            break;
+                        }
+                    }
+                }
+                _ = &mut closed => break,
+            }
+        }
+    }
+
     async fn receive_messages(
         socket: Arc<UdpSocket>,
+        cipher: Option<DatagramCipher>,
+        mut decoder: DatagramDecoder<C>,
+        handshake: HandshakeInfo,
+        heartbeat: Option<Heartbeat>,
         message_notifier_tx: Sender<Message>,
         server_address: SocketAddr,
     ) -> Result<(), ExchangeError> {
-        let mut buf = [0; MAX_SIZE];
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
 
         message_notifier_tx
-            .send(Message::Connected)
+            .send(Message::Connected(ConnectionInfo::new(handshake, None)))
             .await
             .map_err(|e| SendNotifyError(server_address, e.to_string()))?;
 
-        while let Ok((received, server_address)) = socket.recv_from(&mut buf).await {
+        let mut last_heard = Instant::now();
+        let reason = loop {
+            let received = match heartbeat {
+                Some(heartbeat) => {
+                    let deadline = last_heard + heartbeat.idle_timeout;
+                    match tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+                        Ok(received) => received,
+                        Err(_) => break DisconnectReason::IdleTimeout(heartbeat.idle_timeout),
+                    }
+                }
+                None => socket.recv_from(&mut buf).await,
+            };
+            let (received, server_address) = match received {
+                Ok(received) => received,
+                Err(_) => break DisconnectReason::PeerClosed,
+            };
+            let received = &buf[..received];
+            if cipher.is_some() && is_key_exchange(received) {
+                // a late answer to a repeated key offer
+                continue;
+            }
+            let datagram = match open_with(cipher.as_ref(), server_address, received) {
+                Ok(datagram) => datagram,
+                Err(_) => continue,
+            };
+            let datagram = datagram.as_ref();
+            if is_handshake(datagram) && !decoder.is_sealed(datagram) {
+                // a late answer to a repeated hello
+                continue;
+            }
+            let bytes = match decoder.decode(server_address, datagram) {
+                Ok(bytes) => bytes,
+                Err(ExchangeError::CorruptedDatagram(_) | ExchangeError::ForeignSession(..)) => {
+                    continue;
+                }
+                Err(error) => {
+                    message_notifier_tx
+                        .send(Message::Error(error))
+                        .await
+                        .map_err(|e| SendNotifyError(server_address, e.to_string()))?;
+                    continue;
+                }
+            };
+            last_heard = Instant::now();
+            match Control::decode(&bytes) {
+                Some(Control::Shutdown) => break DisconnectReason::ServerShutdown,
+                Some(Control::Overloaded) => break DisconnectReason::Overloaded,
+                Some(Control::Closed) => break DisconnectReason::ClosedByServer,
+                Some(Control::Ping | Control::Pong) => continue,
+                None => {}
+            }
             message_notifier_tx
-                .send(Message::Bytes(buf[..received].into()))
+                .send(Message::Bytes(bytes))
                 .await
                 .map_err(|e| SendNotifyError(server_address, e.to_string()))?;
-        }
+        };
 
-        Ok(())
+        message_notifier_tx
+            .send(Message::Disconnected(reason))
+            .await
+            .map_err(|e| SendNotifyError(server_address, e.to_string()))
+    }
+
+    async fn handshake(
+        socket: &UdpSocket,
+        cipher: Option<&DatagramCipher>,
+        hello: &Hello,
+        timeout: Duration,
+    ) -> Result<HandshakeInfo, ExchangeError> {
+        let server_address = socket.peer_addr()?;
+        let hello = seal_with(cipher, &server_address, hello.encode())?;
+        let answer = async {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            let mut resend = tokio::time::interval(HELLO_RESEND_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = resend.tick() => {
+                        socket.send(&hello).await?;
+                    }
+                    received = socket.recv(&mut buf) => {
+                        let received = &buf[..received?];
+                        let datagram = match open_with(cipher, server_address, received) {
+                            Ok(datagram) => datagram,
+                            Err(_) => continue,
+                        };
+                        if is_handshake(&datagram) {
+                            return HandshakeReply::decode(&datagram)?.into_result();
+                        }
+                    }
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, answer)
+            .await
+            .map_err(|_| ExchangeError::HandshakeFailed(format!("no answer within {timeout:?}")))?
+    }
+
+    async fn exchange_keys(
+        socket: &UdpSocket,
+        cipher: &DatagramCipher,
+        timeout: Duration,
+    ) -> Result<[u8; KEY_SIZE], ExchangeError> {
+        let offer = KeyOffer::new();
+        let encoded = offer.encode(cipher.encryption());
+        let answer = async {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            let mut resend = tokio::time::interval(HELLO_RESEND_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = resend.tick() => {
+                        socket.send(&encoded).await?;
+                    }
+                    received = socket.recv(&mut buf) => {
+                        let datagram = &buf[..received?];
+                        if is_key_exchange(datagram) {
+                            return Ok::<_, ExchangeError>(datagram.to_vec());
+                        }
+                    }
+                }
+            }
+        };
+
+        let answer = tokio::time::timeout(timeout, answer).await.map_err(|_| {
+            ExchangeError::HandshakeFailed(format!("no key exchange within {timeout:?}"))
+        })??;
+        offer.accept(cipher.encryption(), &answer)
     }
 
     pub async fn send(&mut self, bytes: &[u8]) -> Result<(), ExchangeError> {
-        self.socket.send(bytes).await.map(|_| ()).map_err(Io)
+        let encoded = seal_with(
+            self.cipher.as_ref(),
+            &self.server_address,
+            self.encoder.encode(&self.server_address, bytes)?,
+        )?;
+        self.socket.send(&encoded).await.map(|_| ()).map_err(Io)
     }
 
     /*pub async fn send(&self, bytes: &[u8]) -> io::Result<()> {
@@ -100,3 +380,11 @@ impl UdpClient {
         self.socket.try_clone()
     }*/
 }
+
+impl<C: Codec> Drop for UdpClient<C> {
+    fn drop(&mut self) {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.abort();
+        }
+    }
+}
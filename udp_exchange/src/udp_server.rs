@@ -1,102 +1,517 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::net::{ToSocketAddrs, UdpSocket};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{mpsc, Mutex};
+use tokio::time::Interval;
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use exchange_protocol::codecs::MAX_SIZE;
-use exchange_protocol::domain::{Message, NotifyMessage, SendMessage};
+use exchange_protocol::codecs::codec::{Codec, MAX_DATAGRAM_SIZE};
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{
+    ConnectionInfo, DisconnectReason, Message, NotifyMessage, SendMessage,
+};
 use exchange_protocol::error::ExchangeError;
 use exchange_protocol::error::ExchangeError::{Io, SendNotifyError};
+use exchange_protocol::handshake::{is_handshake, HandshakeInfo, HandshakeReply, Hello};
+use exchange_protocol::heartbeat::Control;
+use exchange_protocol::inbox::Inbox;
+use exchange_protocol::metrics::TransportMetrics;
+use exchange_protocol::options::ExchangeOptions;
+use exchange_protocol::shutdown::{ShutdownHandle, ShutdownSignal};
 
-pub struct UdpServer {
+use crate::datagram::{
+    new_session_id, DatagramDecoder, DatagramEncoder, DatagramStats, PeerCompressors,
+};
+use crate::encryption::{is_key_exchange, open_with, seal_with, DatagramCipher, Encryption};
+
+pub struct UdpServer<C: Codec = LengthPrefixedCodec> {
     pub address: SocketAddr,
     pub messages: Arc<Mutex<Receiver<NotifyMessage>>>,
     pub socket: Arc<UdpSocket>,
+    pub options: ExchangeOptions<C>,
+    pub stats: Arc<DatagramStats>,
+    encoder: DatagramEncoder<C>,
+    cipher: Option<DatagramCipher>,
+    metrics: TransportMetrics,
+    shutdown: ShutdownHandle,
 }
 
 impl UdpServer {
     pub async fn start<Addrs: ToSocketAddrs>(address: Addrs) -> Result<UdpServer, ExchangeError> {
+        Self::start_with(
+            address,
+            ExchangeOptions::new(LengthPrefixedCodec::datagram()),
+        )
+        .await
+    }
+
+    pub async fn start_encrypted<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        encryption: Encryption,
+    ) -> Result<UdpServer, ExchangeError> {
+        Self::start_encrypted_with(
+            address,
+            ExchangeOptions::new(LengthPrefixedCodec::datagram()),
+            encryption,
+        )
+        .await
+    }
+}
+
+impl<C: Codec> UdpServer<C> {
+    /// Every datagram carries one frame, so the codec is capped to the datagram size.
+    pub async fn start_with<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+    ) -> Result<UdpServer<C>, ExchangeError> {
+        Self::listen(address, options, None).await
+    }
+
+    /// Drops every datagram that is not encrypted with the agreed key.
+    pub async fn start_encrypted_with<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        options: ExchangeOptions<C>,
+        encryption: Encryption,
+    ) -> Result<UdpServer<C>, ExchangeError> {
+        Self::listen(address, options, Some(encryption)).await
+    }
+
+    async fn listen<Addrs: ToSocketAddrs>(
+        address: Addrs,
+        mut options: ExchangeOptions<C>,
+        encryption: Option<Encryption>,
+    ) -> Result<UdpServer<C>, ExchangeError> {
+        options.codec = options
+            .codec
+            .negotiate(LengthPrefixedCodec::datagram().max_frame_size());
         let socket = UdpSocket::bind(address).await?;
         let server_address = socket.local_addr()?;
 
         let socket_arc = Arc::new(socket);
+        let stats = Arc::new(DatagramStats::default());
+        let cipher = encryption.map(|encryption| DatagramCipher::new(encryption, stats.clone()));
+        let session_id = options.checksums.then(new_session_id);
+        let compressors = PeerCompressors::new(options.compressor(&options.assumed_handshake()));
+        let encoder = DatagramEncoder::new(options.codec.clone(), compressors.clone(), session_id);
 
-        let (client_sender_tx, client_sender_rx) = mpsc::channel::<SendMessage>(1000);
-        let socket_clone = socket_arc.clone();
-        tokio::spawn(async move {
-            Self::send_messages(socket_clone, client_sender_rx).await;
-        });
+        let queue_size = options.limits.queue_size;
+        let (client_sender_tx, client_sender_rx) = mpsc::channel::<SendMessage>(queue_size);
+        let (message_notifier_tx, message_notifier_rx) = mpsc::channel::<NotifyMessage>(queue_size);
+        let metrics = TransportMetrics::new("udp");
+        let notifier = PeerNotifier {
+            client_sender_tx,
+            inbox: Inbox::start(options.limits, message_notifier_tx),
+            metrics: metrics.clone(),
+        };
+        let (shutdown, signal) = ShutdownHandle::new();
 
-        let (message_notifier_tx, message_notifier_rx) = mpsc::channel::<NotifyMessage>(1000);
         let socket_clone = socket_arc.clone();
-        tokio::spawn(async move {
-            Self::receive_messages(socket_clone, client_sender_tx, message_notifier_tx)
+        let send_encoder = encoder.clone();
+        let send_cipher = cipher.clone();
+        let send_compressors = compressors.clone();
+        let send_notifier = notifier.clone();
+        let send_signal = signal.clone();
+        let span = info_span!("udp_server", address = %server_address);
+        tokio::spawn(
+            async move {
+                Self::send_messages(
+                    socket_clone,
+                    send_encoder,
+                    send_cipher,
+                    send_compressors,
+                    client_sender_rx,
+                    send_notifier,
+                    send_signal,
+                )
+                .await;
+            }
+            .instrument(span.clone()),
+        );
+        let socket_clone = socket_arc.clone();
+        let receive_options = options.clone();
+        let receive_cipher = cipher.clone();
+        let decoder = DatagramDecoder::new(
+            options.codec.clone(),
+            compressors.clone(),
+            options.checksums,
+            stats.clone(),
+        );
+        tokio::spawn(
+            async move {
+                Self::receive_messages(
+                    socket_clone,
+                    receive_options,
+                    decoder,
+                    compressors,
+                    receive_cipher,
+                    notifier,
+                    signal,
+                )
                 .await
-                .unwrap_or_else(|error| {
-                    eprintln!("udp_server: receiving messages failed: {error:?}")
-                })
-        });
+                .unwrap_or_else(|error| warn!(?error, "receiving messages failed"))
+            }
+            .instrument(span),
+        );
 
-        println!("udp_server: started at {}", server_address.clone());
+        info!(address = %server_address, "udp server started");
 
         Ok(UdpServer {
             address: server_address,
             messages: Arc::new(Mutex::new(message_notifier_rx)),
             socket: socket_arc,
+            options,
+            stats,
+            encoder,
+            cipher,
+            metrics,
+            shutdown,
         })
     }
 
-    async fn send_messages(socket: Arc<UdpSocket>, mut client_sender_rx: Receiver<SendMessage>) {
-        while let Some(msg) = client_sender_rx.recv().await {
-            socket
-                .send_to(msg.bytes.as_slice(), msg.client_address)
-                .await
-                .unwrap_or_else(|error| {
-                    eprintln!(
-                        "udp_server: sending message to client '{}' failed: {error}",
-                        msg.client_address
-                    );
-                    0
-                });
+    /// Stops receiving, drains the replies until the deadline and tells connected peers.
+    /// The port is released once this server is dropped as well.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    async fn send_messages(
+        socket: Arc<UdpSocket>,
+        encoder: DatagramEncoder<C>,
+        cipher: Option<DatagramCipher>,
+        compressors: PeerCompressors,
+        mut client_sender_rx: Receiver<SendMessage>,
+        notifier: PeerNotifier,
+        mut signal: ShutdownSignal,
+    ) {
+        let metrics = notifier.metrics.clone();
+        let deadline = loop {
+            let msg = tokio::select! {
+                message = client_sender_rx.recv() => match message {
+                    Some(message) => message,
+                    None => return,
+                },
+                deadline = signal.triggered() => break deadline,
+            };
+            let sent = Self::send_by(
+                &socket,
+                &encoder,
+                cipher.as_ref(),
+                &metrics,
+                &msg.client_address,
+                msg.bytes.as_slice(),
+            )
+            .await;
+            if let Err(error) = sent {
+                if notifier
+                    .notify(Message::Error(error), msg.client_address)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        };
+
+        // the channel closes once every reply to a received datagram is sent
+        drop(notifier);
+        while let Ok(Some(msg)) = tokio::time::timeout_at(deadline, client_sender_rx.recv()).await {
+            let _ = Self::send_by(
+                &socket,
+                &encoder,
+                cipher.as_ref(),
+                &metrics,
+                &msg.client_address,
+                msg.bytes.as_slice(),
+            )
+            .await;
+        }
+        for client_address in compressors.peers() {
+            let goodbye = Control::Shutdown.encode();
+            let _ = Self::send_by(
+                &socket,
+                &encoder,
+                cipher.as_ref(),
+                &metrics,
+                &client_address,
+                &goodbye,
+            )
+            .await;
         }
     }
 
     async fn receive_messages(
         socket: Arc<UdpSocket>,
-        client_sender_tx: Sender<SendMessage>,
-        message_notifier_tx: Sender<NotifyMessage>,
+        options: ExchangeOptions<C>,
+        mut decoder: DatagramDecoder<C>,
+        compressors: PeerCompressors,
+        cipher: Option<DatagramCipher>,
+        notifier: PeerNotifier,
+        mut signal: ShutdownSignal,
     ) -> Result<(), ExchangeError> {
-        let mut buf = [0; MAX_SIZE];
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let hello = options.hello();
+        let heartbeat = options.active_heartbeat();
+        // peers that connected with a handshake, the only ones expected to send heartbeats
+        let mut last_seen = HashMap::<SocketAddr, Instant>::new();
+        let mut sweep = heartbeat.map(|heartbeat| tokio::time::interval(heartbeat.interval));
+
+        loop {
+            let (received, client_address) = tokio::select! {
+                received = socket.recv_from(&mut buf) => match received {
+                    Ok(received) => received,
+                    Err(_) => break,
+                },
+                _ = Self::next_sweep(&mut sweep) => {
+                    let idle_timeout = heartbeat.map(|heartbeat| heartbeat.idle_timeout);
+                    for client_address in idle_timeout
+                        .map(|idle_timeout| Self::idle_peers(&mut last_seen, idle_timeout))
+                        .unwrap_or_default()
+                    {
+                        decoder.forget(&client_address);
+                        if compressors.remove(&client_address) {
+                            notifier.metrics.disconnected();
+                        }
+                        let idle_timeout = idle_timeout.expect("only swept with a heartbeat");
+                        debug!(client = %client_address, "idle peer forgotten");
+                        notifier
+                            .notify(
+                                Message::Disconnected(DisconnectReason::IdleTimeout(idle_timeout)),
+                                client_address,
+                            )
+                            .await?;
+                    }
+                    continue;
+                }
+                _ = signal.triggered() => {
+                    for client_address in compressors.peers() {
+                        notifier.metrics.disconnected();
+                        notifier
+                            .notify(
+                                Message::Disconnected(DisconnectReason::ServerShutdown),
+                                client_address,
+                            )
+                            .await?;
+                    }
+                    break;
+                }
+            };
+            let received = &buf[..received];
+            if let Some(cipher) = cipher.as_ref().filter(|_| is_key_exchange(received)) {
+                if let Err(error) =
+                    Self::answer_key_offer(&socket, cipher, received, client_address).await
+                {
+                    notifier
+                        .notify(Message::Error(error), client_address)
+                        .await?;
+                }
+                continue;
+            }
+            let datagram = match open_with(cipher.as_ref(), client_address, received) {
+                Ok(datagram) => datagram,
+                // only counted, like the corrupted ones below
+                Err(_) => {
+                    notifier.metrics.decode_failed();
+                    continue;
+                }
+            };
+            let datagram = datagram.as_ref();
 
-        while let Ok((received, client_address)) = socket.recv_from(&mut buf).await {
-            message_notifier_tx
-                .send(NotifyMessage::new(
-                    Message::Bytes(buf[..received].into()),
+            if options.handshake && is_handshake(datagram) && !decoder.is_sealed(datagram) {
+                // a new handshake starts a new session
+                decoder.forget(&client_address);
+                let peers = compressors.peers();
+                let limit_reached = options
+                    .limits
+                    .max_connections
+                    .filter(|max| !peers.contains(&client_address) && peers.len() >= *max);
+                let answer = Self::answer_handshake(
+                    &socket,
+                    cipher.as_ref(),
+                    &hello,
+                    limit_reached,
+                    datagram,
                     client_address,
-                    client_sender_tx.clone(),
-                ))
-                .await
-                .map_err(|e| SendNotifyError(client_address, e.to_string()))?;
+                );
+                let message = match answer.await {
+                    Ok(info) => {
+                        if !peers.contains(&client_address) {
+                            notifier.metrics.connected();
+                        }
+                        debug!(client = %client_address, "connected");
+                        compressors.insert(client_address, options.compressor(&info));
+                        if heartbeat.is_some() {
+                            last_seen.insert(client_address, Instant::now());
+                        }
+                        Message::Connected(ConnectionInfo::new(info, None))
+                    }
+                    Err(error) => Message::Error(error),
+                };
+                notifier.notify(message, client_address).await?;
+                continue;
+            }
+
+            let bytes = match decoder.decode(client_address, datagram) {
+                Ok(bytes) => bytes,
+                Err(ExchangeError::CorruptedDatagram(_) | ExchangeError::ForeignSession(..)) => {
+                    // only counted, a stray sender must not flood the log
+                    notifier.metrics.decode_failed();
+                    continue;
+                }
+                Err(error) => {
+                    notifier.metrics.decode_failed();
+                    notifier
+                        .notify(Message::Error(error), client_address)
+                        .await?;
+                    continue;
+                }
+            };
+            notifier.metrics.received(bytes.len());
+            if let Some(seen) = last_seen.get_mut(&client_address) {
+                *seen = Instant::now();
+            }
+            match Control::decode(&bytes).filter(|_| options.handshake) {
+                Some(Control::Ping) => {
+                    notifier
+                        .client_sender_tx
+                        .send(SendMessage {
+                            bytes: Control::Pong.encode(),
+                            client_address,
+                        })
+                        .await
+                        .map_err(|e| SendNotifyError(client_address, e.to_string()))?;
+                    continue;
+                }
+                Some(_) => continue,
+                None => {}
+            }
+            match notifier.notify(Message::Bytes(bytes), client_address).await {
+                Ok(()) => {}
+                Err(ExchangeError::QueueOverflow(..)) => {
+                    debug!(client = %client_address, "overloaded peer forgotten");
+                    decoder.forget(&client_address);
+                    if compressors.remove(&client_address) {
+                        notifier.metrics.disconnected();
+                    }
+                    last_seen.remove(&client_address);
+                    if options.handshake {
+                        notifier
+                            .client_sender_tx
+                            .send(SendMessage {
+                                bytes: Control::Overloaded.encode(),
+                                client_address,
+                            })
+                            .await
+                            .map_err(|e| SendNotifyError(client_address, e.to_string()))?;
+                    }
+                    notifier
+                        .notify(
+                            Message::Disconnected(DisconnectReason::Overloaded),
+                            client_address,
+                        )
+                        .await?;
+                }
+                Err(error) => return Err(error),
+            }
         }
 
         Ok(())
     }
 
+    async fn next_sweep(sweep: &mut Option<Interval>) {
+        match sweep {
+            Some(sweep) => {
+                sweep.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    fn idle_peers(
+        last_seen: &mut HashMap<SocketAddr, Instant>,
+        idle_timeout: Duration,
+    ) -> Vec<SocketAddr> {
+        let idle: Vec<SocketAddr> = last_seen
+            .iter()
+            .filter(|(_, seen)| seen.elapsed() >= idle_timeout)
+            .map(|(client_address, _)| *client_address)
+            .collect();
+        for client_address in &idle {
+            last_seen.remove(client_address);
+        }
+        idle
+    }
+
+    async fn answer_key_offer(
+        socket: &UdpSocket,
+        cipher: &DatagramCipher,
+        offer: &[u8],
+        client_address: SocketAddr,
+    ) -> Result<(), ExchangeError> {
+        let answer = cipher.answer_key_offer(client_address, offer)?;
+        socket.send_to(&answer, client_address).await?;
+        Ok(())
+    }
+
+    async fn answer_handshake(
+        socket: &UdpSocket,
+        cipher: Option<&DatagramCipher>,
+        hello: &Hello,
+        limit_reached: Option<usize>,
+        datagram: &[u8],
+        client_address: SocketAddr,
+    ) -> Result<HandshakeInfo, ExchangeError> {
+        let client = Hello::decode(datagram)?;
+        let reply = match limit_reached {
+            Some(max) => HandshakeReply::Reject(ExchangeError::ConnectionLimit(max).to_string()),
+            None => match hello.negotiate(&client) {
+                Ok(info) => HandshakeReply::Accept(info),
+                Err(reason) => HandshakeReply::Reject(reason),
+            },
+        };
+        let sealed = seal_with(cipher, &client_address, reply.encode())?;
+        socket.send_to(&sealed, client_address).await?;
+        match limit_reached {
+            Some(max) => Err(ExchangeError::ConnectionLimit(max)),
+            None => reply.into_result(),
+        }
+    }
+
     pub async fn send(
         &mut self,
         client_address: &SocketAddr,
         bytes: &[u8],
     ) -> Result<(), ExchangeError> {
-        self.socket
-            .send_to(bytes, client_address)
-            .await
-            .map(|_| ())
-            .map_err(Io)
-        /*.unwrap_or_else(|error| {
-            eprintln!("sending message to client '{client_address}' failed: {error}");
-        })*/
+        Self::send_by(
+            &self.socket,
+            &self.encoder,
+            self.cipher.as_ref(),
+            &self.metrics,
+            client_address,
+            bytes,
+        )
+        .await
+    }
+
+    async fn send_by(
+        socket: &UdpSocket,
+        encoder: &DatagramEncoder<C>,
+        cipher: Option<&DatagramCipher>,
+        metrics: &TransportMetrics,
+        client_address: &SocketAddr,
+        bytes: &[u8],
+    ) -> Result<(), ExchangeError> {
+        let encoded = seal_with(
+            cipher,
+            client_address,
+            encoder.encode(client_address, bytes)?,
+        )?;
+        socket.send_to(&encoded, client_address).await.map_err(Io)?;
+        metrics.sent(bytes.len());
+        Ok(())
     }
 
     /*pub async fn send_by(
@@ -114,3 +529,27 @@ impl UdpServer {
         Ok(())*/
     }*/
 }
+
+/// Reports to the application on behalf of any peer.
+#[derive(Clone)]
+struct PeerNotifier {
+    client_sender_tx: Sender<SendMessage>,
+    inbox: Inbox,
+    metrics: TransportMetrics,
+}
+
+impl PeerNotifier {
+    async fn notify(
+        &self,
+        message: Message,
+        client_address: SocketAddr,
+    ) -> Result<(), ExchangeError> {
+        self.inbox
+            .push(NotifyMessage::new(
+                message,
+                client_address,
+                self.client_sender_tx.clone(),
+            ))
+            .await
+    }
+}
@@ -0,0 +1,109 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use exchange_protocol::codecs::codec::MAX_DATAGRAM_SIZE;
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::compression::Compressor;
+use exchange_protocol::domain::Message;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::options::ExchangeOptions;
+use udp_exchange::datagram::*;
+use udp_exchange::udp_client::UdpClient;
+use udp_exchange::udp_server::UdpServer;
+
+#[test]
+fn test_datagram_decoder_drops_and_counts_bad_datagrams() {
+    let codec = LengthPrefixedCodec::datagram();
+    let stats = Arc::new(DatagramStats::default());
+    let compressors = PeerCompressors::new(Compressor::new(None, 0, MAX_DATAGRAM_SIZE));
+    let mut decoder = DatagramDecoder::new(codec, compressors.clone(), true, stats.clone());
+    let peer: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+
+    let datagram = DatagramEncoder::new(codec, compressors.clone(), Some(1))
+        .encode(&peer, b"21.5")
+        .unwrap();
+    assert_eq!(decoder.decode(peer, &datagram).unwrap(), b"21.5");
+
+    let mut flipped = datagram.clone();
+    flipped[HEADER_SIZE + 5] ^= 0x01;
+    assert!(matches!(
+        decoder.decode(peer, &flipped),
+        Err(ExchangeError::CorruptedDatagram(_))
+    ));
+    assert!(matches!(
+        decoder.decode(peer, b"{}"),
+        Err(ExchangeError::CorruptedDatagram(_))
+    ));
+
+    let other_session = DatagramEncoder::new(codec, compressors, Some(2))
+        .encode(&peer, b"99")
+        .unwrap();
+    assert!(matches!(
+        decoder.decode(peer, &other_session),
+        Err(ExchangeError::ForeignSession(2, 1))
+    ));
+    decoder.forget(&peer);
+    assert_eq!(decoder.decode(peer, &other_session).unwrap(), b"99");
+
+    let counts = stats.counts();
+    assert_eq!(counts.delivered, 2);
+    assert_eq!(counts.corrupted, 2);
+    assert_eq!(counts.foreign_session, 1);
+    assert_eq!(counts.dropped(), 3);
+}
+
+#[tokio::test]
+async fn test_checksummed_exchange_ignores_stray_datagrams() {
+    let options = ExchangeOptions::new(LengthPrefixedCodec::datagram()).with_checksums();
+    let server = UdpServer::start_with("127.0.0.1:0", options.clone())
+        .await
+        .unwrap();
+    let mut client =
+        UdpClient::connect_with(server.address, "127.0.0.1:0".parse().unwrap(), options)
+            .await
+            .unwrap();
+
+    let stray = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    stray
+        .send_to(b"\x00\x00\x00\x02hi", server.address)
+        .await
+        .unwrap();
+    client.send(b"reading").await.unwrap();
+
+    let mut messages = server.messages.lock().await;
+    let notify = loop {
+        let notify = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let Message::Bytes(_) = notify.message {
+            break notify;
+        }
+    };
+    assert!(matches!(&notify.message, Message::Bytes(bytes) if bytes == b"reading"));
+    assert_eq!(notify.address, client.address);
+
+    let counts = server.stats.counts();
+    assert_eq!(counts.delivered, 1);
+    assert_eq!(counts.corrupted, 1);
+}
+
+#[tokio::test]
+async fn test_failed_handshakes_are_reported_to_the_server() {
+    let server = UdpServer::start("127.0.0.1:0").await.unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut hello = exchange_protocol::handshake::MAGIC.to_vec();
+    hello.extend_from_slice(b"garbage");
+    socket.send_to(&hello, server.address).await.unwrap();
+
+    let notify = tokio::time::timeout(Duration::from_secs(5), async {
+        server.messages.lock().await.recv().await.unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(notify.address, socket.local_addr().unwrap());
+    assert!(matches!(notify.message, Message::Error(_)));
+}
@@ -0,0 +1,129 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::Message;
+use exchange_protocol::error::ExchangeError;
+use exchange_protocol::options::ExchangeOptions;
+use udp_exchange::datagram::DatagramStats;
+use udp_exchange::encryption::*;
+use udp_exchange::udp_client::UdpClient;
+use udp_exchange::udp_server::UdpServer;
+
+const KEY: [u8; KEY_SIZE] = [7; KEY_SIZE];
+
+#[test]
+fn test_cipher_rejects_forged_and_replayed_datagrams() {
+    let sender = DatagramCipher::new(
+        Encryption::pre_shared(KEY),
+        Arc::new(DatagramStats::default()),
+    );
+    let stats = Arc::new(DatagramStats::default());
+    let receiver = DatagramCipher::new(Encryption::pre_shared(KEY), stats.clone());
+    let peer: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+    let other_peer: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+
+    let first = sender.seal(&peer, b"register").unwrap();
+    let second = sender.seal(&peer, b"21.5").unwrap();
+    assert!(!first.windows(8).any(|window| window == b"register"));
+
+    assert_eq!(receiver.open(peer, &second).unwrap(), b"21.5");
+    assert_eq!(receiver.open(peer, &first).unwrap(), b"register");
+    assert!(matches!(
+        receiver.open(peer, &first),
+        Err(ExchangeError::ReplayedDatagram(_, 1))
+    ));
+    assert!(matches!(
+        receiver.open(other_peer, &second),
+        Err(ExchangeError::ReplayedDatagram(_, 2))
+    ));
+
+    let mut forged = sender.seal(&peer, b"22.0").unwrap();
+    let last = forged.len() - 1;
+    forged[last] ^= 0x01;
+    assert!(matches!(
+        receiver.open(peer, &forged),
+        Err(ExchangeError::CorruptedDatagram(_))
+    ));
+    let stranger = DatagramCipher::new(
+        Encryption::pre_shared([8; KEY_SIZE]),
+        Arc::new(DatagramStats::default()),
+    );
+    assert!(receiver
+        .open(peer, &stranger.seal(&peer, b"off").unwrap())
+        .is_err());
+
+    let counts = stats.counts();
+    assert_eq!(counts.replayed, 2);
+    assert_eq!(counts.corrupted, 2);
+}
+
+async fn next_bytes(server: &UdpServer) -> (Vec<u8>, SocketAddr) {
+    let mut messages = server.messages.lock().await;
+    loop {
+        let notify = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let Message::Bytes(bytes) = notify.message {
+            return (bytes, notify.address);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_exchange_drops_plain_datagrams() {
+    let server = UdpServer::start_encrypted("127.0.0.1:0", Encryption::pre_shared(KEY))
+        .await
+        .unwrap();
+    let mut client = UdpClient::connect_encrypted(
+        server.address,
+        "127.0.0.1:0".parse().unwrap(),
+        Encryption::pre_shared(KEY),
+    )
+    .await
+    .unwrap();
+
+    let plain = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    plain
+        .send_to(b"\x00\x00\x00\x02hi", server.address)
+        .await
+        .unwrap();
+    client.send(b"reading").await.unwrap();
+
+    assert_eq!(
+        next_bytes(&server).await,
+        (b"reading".to_vec(), client.address)
+    );
+    assert_eq!(server.stats.counts().corrupted, 1);
+}
+
+#[tokio::test]
+async fn test_key_exchange_needs_the_pre_shared_key() {
+    let encryption = Encryption::pre_shared(KEY).with_key_exchange();
+    let server = UdpServer::start_encrypted("127.0.0.1:0", encryption.clone())
+        .await
+        .unwrap();
+    let mut client =
+        UdpClient::connect_encrypted(server.address, "127.0.0.1:0".parse().unwrap(), encryption)
+            .await
+            .unwrap();
+    client.send(b"reading").await.unwrap();
+    assert_eq!(next_bytes(&server).await.0, b"reading");
+
+    let options = ExchangeOptions {
+        handshake_timeout: Duration::from_millis(300),
+        ..ExchangeOptions::new(LengthPrefixedCodec::datagram())
+    };
+    let impostor = UdpClient::connect_encrypted_with(
+        server.address,
+        "127.0.0.1:0".parse().unwrap(),
+        options,
+        Encryption::pre_shared([8; KEY_SIZE]).with_key_exchange(),
+    )
+    .await;
+    assert!(matches!(impostor, Err(ExchangeError::HandshakeFailed(_))));
+}
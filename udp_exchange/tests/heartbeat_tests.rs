@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::heartbeat::Heartbeat;
+use exchange_protocol::options::ExchangeOptions;
+use udp_exchange::udp_client::UdpClient;
+use udp_exchange::udp_server::UdpServer;
+
+fn heartbeat() -> Heartbeat {
+    Heartbeat::new(Duration::from_millis(50), Duration::from_millis(300))
+}
+
+fn options() -> ExchangeOptions {
+    ExchangeOptions::new(LengthPrefixedCodec::datagram())
+}
+
+#[tokio::test]
+async fn test_server_forgets_silent_clients_but_not_heartbeating_ones() {
+    let server = UdpServer::start_with("127.0.0.1:0", options().with_heartbeat(heartbeat()))
+        .await
+        .unwrap();
+    let silent = UdpClient::connect_with(
+        server.address,
+        "127.0.0.1:0".parse().unwrap(),
+        options().without_heartbeat(),
+    )
+    .await
+    .unwrap();
+    let alive = UdpClient::connect_with(
+        server.address,
+        "127.0.0.1:0".parse().unwrap(),
+        options().with_heartbeat(heartbeat()),
+    )
+    .await
+    .unwrap();
+
+    let mut messages = server.messages.lock().await;
+    let disconnected = loop {
+        let notify = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let Message::Disconnected(reason) = notify.message {
+            break (notify.address, reason);
+        }
+    };
+    assert_eq!(
+        disconnected,
+        (
+            silent.address,
+            DisconnectReason::IdleTimeout(heartbeat().idle_timeout)
+        )
+    );
+
+    tokio::time::sleep(heartbeat().idle_timeout * 2).await;
+    while let Ok(notify) = messages.try_recv() {
+        assert!(!matches!(notify.message, Message::Disconnected(_)));
+    }
+    drop(alive);
+}
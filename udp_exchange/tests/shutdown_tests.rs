@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use exchange_protocol::codecs::length_prefixed_codec::LengthPrefixedCodec;
+use exchange_protocol::domain::{DisconnectReason, Message};
+use exchange_protocol::options::ExchangeOptions;
+use udp_exchange::udp_client::UdpClient;
+use udp_exchange::udp_server::UdpServer;
+
+fn options() -> ExchangeOptions {
+    ExchangeOptions::new(LengthPrefixedCodec::datagram())
+}
+
+#[tokio::test]
+async fn test_shutdown_is_announced_to_connected_clients() {
+    let server = UdpServer::start_with("127.0.0.1:0", options())
+        .await
+        .unwrap();
+    let client = UdpClient::connect_with(server.address, "127.0.0.1:0".parse().unwrap(), options())
+        .await
+        .unwrap();
+
+    let shutdown = server.shutdown_handle();
+    shutdown.shutdown_within(Duration::from_secs(1));
+    let mut messages = server.messages.lock().await;
+    let mut reasons = vec![];
+    while let Some(notify) = messages.recv().await {
+        if let Message::Disconnected(reason) = notify.message {
+            reasons.push((notify.address, reason));
+        }
+    }
+    assert_eq!(
+        reasons,
+        [(client.address, DisconnectReason::ServerShutdown)]
+    );
+    tokio::time::timeout(Duration::from_secs(5), shutdown.stopped())
+        .await
+        .unwrap();
+
+    let mut replies = client.messages.lock().await;
+    assert!(matches!(replies.recv().await, Some(Message::Connected(_))));
+    assert!(matches!(
+        tokio::time::timeout(Duration::from_secs(5), replies.recv()).await,
+        Ok(Some(Message::Disconnected(
+            DisconnectReason::ServerShutdown
+        )))
+    ));
+}
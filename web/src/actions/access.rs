@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::Deref;
+
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorInternalServerError, InternalError};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures::future::LocalBoxFuture;
+use serde::Serialize;
+use serde_json::json;
+
+use house::access::domain::{Action, Credentials, Role, SessionToken, User, UserName};
+use house::access::user_store::UserStore;
+use house::audit::domain::{user_target, AuditContext, AuditOperation, AuditSource};
+use house::errors::intelligent_house_error::AccessError;
+use house::errors::intelligent_house_error::AccessError::AccessUnauthenticated;
+use house::errors::intelligent_house_error::IntelligentHouseError::AccessErr;
+use house::house::domain::RoomName;
+
+use crate::domain::{AppState, NewUser};
+use crate::trace::TraceId;
+
+/// User of the session given by the `Authorization: Bearer <token>` header.
+pub struct AuthUser {
+    pub user: User,
+    pub token: SessionToken,
+    pub address: Option<SocketAddr>,
+    pub trace_id: Option<String>,
+}
+
+impl AuthUser {
+    pub fn audit_context(&self) -> AuditContext {
+        let context = AuditContext::new(self.user.name.0.clone(), AuditSource::Web, self.address);
+        match &self.trace_id {
+            Some(trace_id) => context.with_trace_id(trace_id.clone()),
+            None => context,
+        }
+    }
+}
+
+impl Deref for AuthUser {
+    type Target = User;
+
+    fn deref(&self) -> &User {
+        &self.user
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = req.app_data::<Data<AppState>>().cloned();
+        let address = req.peer_addr();
+        let trace_id = req
+            .extensions()
+            .get::<TraceId>()
+            .map(|trace_id| trace_id.0.clone());
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| SessionToken(token.trim().to_string()));
+
+        Box::pin(async move {
+            let state = state.ok_or_else(|| ErrorInternalServerError("app state is missing"))?;
+            let token = token.ok_or_else(|| unauthorized(AccessUnauthenticated))?;
+            let user_name = state.sessions.get(&token).map_err(unauthorized)?;
+            let user = state
+                .users
+                .get_user(&user_name)
+                .await
+                .map_err(|_| unauthorized(AccessUnauthenticated))?;
+
+            Ok(AuthUser {
+                user,
+                token,
+                address,
+                trace_id,
+            })
+        })
+    }
+}
+
+fn unauthorized(err: AccessError) -> actix_web::Error {
+    let message = err.to_string();
+    let response = HttpResponse::Unauthorized().json(AccessErr(err));
+    InternalError::from_response(message, response).into()
+}
+
+pub fn forbidden(err: AccessError) -> HttpResponse {
+    HttpResponse::Forbidden().json(AccessErr(err))
+}
+
+#[derive(Debug, Serialize)]
+struct UserView {
+    name: UserName,
+    role: Role,
+    rooms: HashMap<RoomName, Vec<Action>>,
+}
+
+impl From<User> for UserView {
+    fn from(user: User) -> Self {
+        UserView {
+            name: user.name,
+            role: user.role,
+            rooms: user.rooms,
+        }
+    }
+}
+
+pub async fn login(state: Data<AppState>, credentials: Json<Credentials>) -> HttpResponse {
+    match state.users.authenticate(&credentials).await {
+        Ok(user) => HttpResponse::Ok().json(state.sessions.open(user.name)),
+        Err(err) => HttpResponse::Unauthorized().json(AccessErr(err)),
+    }
+}
+
+pub async fn logout(state: Data<AppState>, user: AuthUser) -> HttpResponse {
+    state.sessions.close(&user.token);
+    HttpResponse::Ok().finish()
+}
+
+pub async fn get_users(state: Data<AppState>, user: AuthUser) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    match state.users.get_users().await {
+        Ok(users) => HttpResponse::Ok().json(
+            users
+                .into_iter()
+                .map(UserView::from)
+                .collect::<Vec<UserView>>(),
+        ),
+        Err(err) => HttpResponse::InternalServerError().json(AccessErr(err)),
+    }
+}
+
+pub async fn add_user(
+    state: Data<AppState>,
+    user: AuthUser,
+    new_user: Json<NewUser>,
+) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    let new_user = new_user.into_inner();
+    let target = user_target(&new_user.name);
+    let after = json!({ "role": new_user.role });
+    if let Err(err) = state
+        .users
+        .add_user(User::new(new_user.name, new_user.role, &new_user.password))
+        .await
+    {
+        return HttpResponse::InternalServerError().json(AccessErr(err));
+    }
+    let entry = user
+        .audit_context()
+        .entry(AuditOperation::AddUser, target, None, Some(after));
+    match state.data.record(entry).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn delete_user(
+    state: Data<AppState>,
+    user: AuthUser,
+    name: Path<UserName>,
+) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    let user_name = name.into_inner();
+    let before = match state.users.get_user(&user_name).await {
+        Ok(removed) => json!({ "role": removed.role, "rooms": removed.rooms }),
+        Err(err) => return HttpResponse::InternalServerError().json(AccessErr(err)),
+    };
+    if let Err(err) = state.users.remove_user(&user_name).await {
+        return HttpResponse::InternalServerError().json(AccessErr(err));
+    }
+    state.sessions.close_user(&user_name);
+    let entry = user.audit_context().entry(
+        AuditOperation::RemoveUser,
+        user_target(&user_name),
+        Some(before),
+        None,
+    );
+    match state.data.record(entry).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn grant_room(
+    state: Data<AppState>,
+    user: AuthUser,
+    params: Path<(UserName, RoomName)>,
+    actions: Json<Vec<Action>>,
+) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    let (user_name, room_name) = params.into_inner();
+    let actions = actions.into_inner();
+    let before = match state.users.get_user(&user_name).await {
+        Ok(granted) => json!(granted.rooms.get(&room_name)),
+        Err(err) => return HttpResponse::InternalServerError().json(AccessErr(err)),
+    };
+    let after = json!(actions);
+    if let Err(err) = state.users.grant(&user_name, &room_name, actions).await {
+        return HttpResponse::InternalServerError().json(AccessErr(err));
+    }
+    let entry = user.audit_context().entry(
+        AuditOperation::GrantRoom,
+        format!("{}/{room_name}", user_target(&user_name)),
+        Some(before),
+        Some(after),
+    );
+    match state.data.record(entry).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
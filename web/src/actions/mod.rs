@@ -1,35 +1,70 @@
+pub mod access;
 pub mod service;
 
-use crate::domain::AppState;
-use actix_web::web::{Data, Json, Path};
+use crate::actions::access::{forbidden, AuthUser};
+use crate::domain::{AppState, HistoryWindow};
+use actix_web::web::{Data, Json, Path, Query};
 use actix_web::HttpResponse;
+use house::access::domain::Action;
+use house::audit::domain::{to_json_lines, AuditFilter};
 use house::devices::power_socket::PowerSocket;
 use house::devices::temperature_sensor::TemperatureSensor;
+use house::errors::intelligent_house_error::IntelligentHouseError::AuditErr;
+use house::history::domain::now_millis;
 use house::house::domain::*;
 
-pub async fn get_rooms(state: Data<AppState>) -> HttpResponse {
+const DEFAULT_HISTORY_WINDOW_MILLIS: u64 = 60 * 60 * 1000;
+
+pub async fn get_rooms(state: Data<AppState>, user: AuthUser) -> HttpResponse {
     match state.data.get_rooms().await {
-        Ok(data) => HttpResponse::Ok().json(data),
+        Ok(mut data) => {
+            data.retain(|room| user.is_allowed(Some(&room.name), Action::Read));
+            HttpResponse::Ok().json(data)
+        }
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
-pub async fn add_room(state: Data<AppState>, name: Path<RoomName>) -> HttpResponse {
-    match state.data.add_room(name.into_inner()).await {
+pub async fn add_room(state: Data<AppState>, user: AuthUser, name: Path<RoomName>) -> HttpResponse {
+    let room_name = name.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
+    match state.data.add_room(&user.audit_context(), room_name).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
-pub async fn delete_room(state: Data<AppState>, name: Path<RoomName>) -> HttpResponse {
-    match state.data.delete_room(name.into_inner()).await {
+pub async fn delete_room(
+    state: Data<AppState>,
+    user: AuthUser,
+    name: Path<RoomName>,
+) -> HttpResponse {
+    let room_name = name.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
+    match state
+        .data
+        .delete_room(&user.audit_context(), room_name)
+        .await
+    {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
-pub async fn get_room_devices(state: Data<AppState>, name: Path<RoomName>) -> HttpResponse {
-    match state.data.get_room_devices(name.into_inner()).await {
+pub async fn get_room_devices(
+    state: Data<AppState>,
+    user: AuthUser,
+    name: Path<RoomName>,
+) -> HttpResponse {
+    let room_name = name.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Read) {
+        return forbidden(err);
+    }
+    match state.data.get_room_devices(room_name).await {
         Ok(data) => HttpResponse::Ok().json(data),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
@@ -37,10 +72,18 @@ pub async fn get_room_devices(state: Data<AppState>, name: Path<RoomName>) -> Ht
 
 pub async fn add_room_device(
     state: Data<AppState>,
+    user: AuthUser,
     params: Path<(RoomName, DeviceName)>,
 ) -> HttpResponse {
     let (room_name, device_name) = params.into_inner();
-    match state.data.add_room_device(room_name, device_name).await {
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
+    match state
+        .data
+        .add_room_device(&user.audit_context(), room_name, device_name)
+        .await
+    {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
@@ -48,38 +91,61 @@ pub async fn add_room_device(
 
 pub async fn delete_room_device(
     state: Data<AppState>,
+    user: AuthUser,
     params: Path<(RoomName, DeviceName)>,
 ) -> HttpResponse {
     let (room_name, device_name) = params.into_inner();
-    match state.data.delete_room_device(room_name, device_name).await {
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
+    match state
+        .data
+        .delete_room_device(&user.audit_context(), room_name, device_name)
+        .await
+    {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
-pub async fn get_house_report(state: Data<AppState>) -> HttpResponse {
+pub async fn get_house_report(state: Data<AppState>, user: AuthUser) -> HttpResponse {
+    if let Err(err) = user.check(None, Action::Read) {
+        return forbidden(err);
+    }
     match state.data.get_house_report().await {
         Ok(data) => HttpResponse::Ok().json(data),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
-pub async fn get_inventory_devices(state: Data<AppState>) -> HttpResponse {
+pub async fn get_inventory_devices(state: Data<AppState>, user: AuthUser) -> HttpResponse {
     match state.data.get_inventory_devices().await {
-        Ok(data) => HttpResponse::Ok().json(data),
+        Ok(mut data) => {
+            data.retain(|room| user.is_allowed(Some(&room.name), Action::Read));
+            HttpResponse::Ok().json(data)
+        }
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
 
 pub async fn add_socket(
     state: Data<AppState>,
+    user: AuthUser,
     params: Path<(RoomName, DeviceName)>,
     socket: Json<PowerSocket>,
 ) -> HttpResponse {
     let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
     match state
         .data
-        .add_inventory_socket(room_name, device_name, socket.into_inner())
+        .add_inventory_socket(
+            &user.audit_context(),
+            room_name,
+            device_name,
+            socket.into_inner(),
+        )
         .await
     {
         Ok(_) => HttpResponse::Ok().finish(),
@@ -89,13 +155,22 @@ pub async fn add_socket(
 
 pub async fn add_sensor(
     state: Data<AppState>,
+    user: AuthUser,
     params: Path<(RoomName, DeviceName)>,
     sensor: Json<TemperatureSensor>,
 ) -> HttpResponse {
     let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
     match state
         .data
-        .add_inventory_sensor(room_name, device_name, sensor.into_inner())
+        .add_inventory_sensor(
+            &user.audit_context(),
+            room_name,
+            device_name,
+            sensor.into_inner(),
+        )
         .await
     {
         Ok(_) => HttpResponse::Ok().finish(),
@@ -105,15 +180,112 @@ pub async fn add_sensor(
 
 pub async fn delete_inventory_device(
     state: Data<AppState>,
+    user: AuthUser,
     params: Path<(RoomName, DeviceName)>,
 ) -> HttpResponse {
     let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Configure) {
+        return forbidden(err);
+    }
     match state
         .data
-        .delete_inventory_device(room_name, device_name)
+        .delete_inventory_device(&user.audit_context(), room_name, device_name)
         .await
     {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => HttpResponse::InternalServerError().json(err),
     }
 }
+
+pub async fn get_device_health(
+    state: Data<AppState>,
+    user: AuthUser,
+    params: Path<(RoomName, DeviceName)>,
+) -> HttpResponse {
+    let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Read) {
+        return forbidden(err);
+    }
+    match state.data.get_device_health(room_name, device_name).await {
+        Ok(data) => HttpResponse::Ok().json(data),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn report_device_seen(
+    state: Data<AppState>,
+    user: AuthUser,
+    params: Path<(RoomName, DeviceName)>,
+) -> HttpResponse {
+    let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Control) {
+        return forbidden(err);
+    }
+    match state
+        .data
+        .report_device_seen(room_name, device_name, now_millis())
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn get_device_history(
+    state: Data<AppState>,
+    user: AuthUser,
+    params: Path<(RoomName, DeviceName)>,
+    window: Query<HistoryWindow>,
+) -> HttpResponse {
+    let (room_name, device_name) = params.into_inner();
+    if let Err(err) = user.check(Some(&room_name), Action::Read) {
+        return forbidden(err);
+    }
+    let to = window.to.unwrap_or_else(now_millis);
+    let from = window
+        .from
+        .unwrap_or_else(|| to.saturating_sub(DEFAULT_HISTORY_WINDOW_MILLIS));
+    match state
+        .data
+        .get_device_history(room_name, device_name, from, to)
+        .await
+    {
+        Ok(data) => HttpResponse::Ok().json(data),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn get_audit(
+    state: Data<AppState>,
+    user: AuthUser,
+    filter: Query<AuditFilter>,
+) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    match state.data.get_audit(&filter).await {
+        Ok(data) => HttpResponse::Ok().json(data),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
+
+pub async fn export_audit(
+    state: Data<AppState>,
+    user: AuthUser,
+    filter: Query<AuditFilter>,
+) -> HttpResponse {
+    if let Err(err) = user.check_owner() {
+        return forbidden(err);
+    }
+    match state
+        .data
+        .get_audit(&filter)
+        .await
+        .and_then(|entries| to_json_lines(&entries).map_err(AuditErr))
+    {
+        Ok(lines) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .body(lines),
+        Err(err) => HttpResponse::InternalServerError().json(err),
+    }
+}
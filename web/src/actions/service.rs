@@ -1,42 +1,91 @@
+use house::audit::audit_log::AuditLog;
+use house::audit::audited_inventory::AuditedInventory;
+use house::audit::domain::{device_target, AuditContext, AuditEntry, AuditFilter, AuditOperation};
 use house::devices::power_socket::PowerSocket;
 use house::devices::temperature_sensor::TemperatureSensor;
 use house::errors::intelligent_house_error::HouseError::RoomAlreadyAdded;
 use house::errors::intelligent_house_error::IntelligentHouseError;
-use house::errors::intelligent_house_error::IntelligentHouseError::{HouseErr, InventoryErr};
+use house::errors::intelligent_house_error::IntelligentHouseError::{
+    AuditErr, HistoryErr, HouseErr, InventoryErr,
+};
+use house::history::device_history::DeviceHistory;
+use house::history::domain::{DeviceSeries, Timestamp};
 use house::house::domain::{DeviceName, Room, RoomName};
 use house::house::intelligent_house::IntelligentHouse;
 use house::inventory::device_inventory::DeviceInventory;
-use house::inventory::domain::{DeviceItem, RoomDevices};
+use house::inventory::domain::{DeviceHealth, DeviceItem, RoomDevices};
 
 #[derive(Clone)]
-pub struct DataService<T: DeviceInventory + Sync, H: IntelligentHouse> {
+pub struct DataService<T, H, D, A>
+where
+    T: DeviceInventory + Sync,
+    H: IntelligentHouse,
+    D: DeviceHistory,
+    A: AuditLog,
+{
     inventory: T,
     house: H,
+    history: D,
+    audit: A,
 }
 
-impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
-    pub fn create(inventory: T, house: H) -> Self {
-        DataService { inventory, house }
+impl<T, H, D, A> DataService<T, H, D, A>
+where
+    T: DeviceInventory + Send + Sync + Clone,
+    H: IntelligentHouse,
+    D: DeviceHistory,
+    A: AuditLog + Send + Sync + Clone,
+{
+    pub fn create(inventory: T, house: H, history: D, audit: A) -> Self {
+        DataService {
+            inventory,
+            house,
+            history,
+            audit,
+        }
+    }
+
+    fn audited(&self, context: &AuditContext) -> AuditedInventory<T, A> {
+        AuditedInventory::new(self.inventory.clone(), self.audit.clone(), context.clone())
+    }
+
+    pub async fn record(&self, entry: AuditEntry) -> Result<(), IntelligentHouseError> {
+        self.audit.record(entry).await.map_err(AuditErr)
+    }
+
+    pub async fn get_audit(
+        &self,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEntry>, IntelligentHouseError> {
+        self.audit.query(filter).await.map_err(AuditErr)
     }
 
     pub async fn get_rooms(&self) -> Result<Vec<Room>, IntelligentHouseError> {
         self.house.get_rooms().await.map_err(HouseErr)
     }
 
-    pub async fn add_room(&self, room_name: RoomName) -> Result<(), IntelligentHouseError> {
+    pub async fn add_room(
+        &self,
+        context: &AuditContext,
+        room_name: RoomName,
+    ) -> Result<(), IntelligentHouseError> {
         if self.house.get_room(&room_name).await.is_ok() {
             return Err(HouseErr(RoomAlreadyAdded(room_name)));
         }
         self.house.add_room(&room_name).await?;
-        self.inventory
+        self.audited(context)
             .add_room(&room_name)
             .await
             .map_err(InventoryErr)
     }
 
-    pub async fn delete_room(&self, room_name: RoomName) -> Result<(), IntelligentHouseError> {
+    pub async fn delete_room(
+        &self,
+        context: &AuditContext,
+        room_name: RoomName,
+    ) -> Result<(), IntelligentHouseError> {
         self.house.remove_room(&room_name).await?;
-        self.inventory
+        self.audited(context)
             .remove_room(&room_name)
             .await
             .map_err(InventoryErr)
@@ -51,24 +100,40 @@ impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
 
     pub async fn add_room_device(
         &self,
+        context: &AuditContext,
         room_name: RoomName,
         device_name: DeviceName,
     ) -> Result<(), IntelligentHouseError> {
         self.house
             .add_device(&room_name, &device_name)
             .await
-            .map_err(HouseErr)
+            .map_err(HouseErr)?;
+        self.record(context.entry(
+            AuditOperation::AttachDevice,
+            device_target(&room_name, &device_name),
+            None,
+            None,
+        ))
+        .await
     }
 
     pub async fn delete_room_device(
         &self,
+        context: &AuditContext,
         room_name: RoomName,
         device_name: DeviceName,
     ) -> Result<(), IntelligentHouseError> {
         self.house
             .remove_device(&room_name, &device_name)
             .await
-            .map_err(HouseErr)
+            .map_err(HouseErr)?;
+        self.record(context.entry(
+            AuditOperation::DetachDevice,
+            device_target(&room_name, &device_name),
+            None,
+            None,
+        ))
+        .await
     }
 
     pub async fn get_inventory_devices(&self) -> Result<Vec<RoomDevices>, IntelligentHouseError> {
@@ -80,11 +145,12 @@ impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
 
     pub async fn add_inventory_socket(
         &self,
+        context: &AuditContext,
         room_name: RoomName,
         device_name: DeviceName,
         socket: PowerSocket,
     ) -> Result<(), IntelligentHouseError> {
-        self.inventory
+        self.audited(context)
             .add_device(&room_name, &device_name, DeviceItem::inject(socket))
             .await
             .map_err(InventoryErr)
@@ -92,11 +158,12 @@ impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
 
     pub async fn add_inventory_sensor(
         &self,
+        context: &AuditContext,
         room_name: RoomName,
         device_name: DeviceName,
         sensor: TemperatureSensor,
     ) -> Result<(), IntelligentHouseError> {
-        self.inventory
+        self.audited(context)
             .add_device(&room_name, &device_name, DeviceItem::inject(sensor))
             .await
             .map_err(InventoryErr)
@@ -104,15 +171,39 @@ impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
 
     pub async fn delete_inventory_device(
         &self,
+        context: &AuditContext,
         room_name: RoomName,
         device_name: DeviceName,
     ) -> Result<(), IntelligentHouseError> {
-        self.inventory
+        self.audited(context)
             .remove_device(&room_name, &device_name)
             .await
             .map_err(InventoryErr)
     }
 
+    pub async fn get_device_health(
+        &self,
+        room_name: RoomName,
+        device_name: DeviceName,
+    ) -> Result<DeviceHealth, IntelligentHouseError> {
+        self.inventory
+            .get_health(&room_name, &device_name)
+            .await
+            .map_err(InventoryErr)
+    }
+
+    pub async fn report_device_seen(
+        &self,
+        room_name: RoomName,
+        device_name: DeviceName,
+        timestamp: Timestamp,
+    ) -> Result<(), IntelligentHouseError> {
+        self.inventory
+            .report_seen(&room_name, &device_name, timestamp)
+            .await
+            .map_err(InventoryErr)
+    }
+
     pub async fn get_house_report(&self) -> Result<String, IntelligentHouseError> {
         //let result = self.house.generate_report(&self.inventory).await?;
         //Ok(result.split("\n").map(|s| s.to_string()).collect())
@@ -121,4 +212,17 @@ impl<T: DeviceInventory + Sync, H: IntelligentHouse> DataService<T, H> {
             .await
             .map_err(HouseErr)
     }
+
+    pub async fn get_device_history(
+        &self,
+        room_name: RoomName,
+        device_name: DeviceName,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<DeviceSeries>, IntelligentHouseError> {
+        self.history
+            .get_series(&room_name, &device_name, from, to)
+            .await
+            .map_err(HistoryErr)
+    }
 }
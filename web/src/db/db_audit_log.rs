@@ -0,0 +1,81 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, to_bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Database;
+
+use house::audit::audit_log::AuditLog;
+use house::audit::domain::{AuditEntry, AuditFilter};
+use house::errors::intelligent_house_error::AuditError;
+
+#[derive(Clone)]
+pub struct DbAuditLog {
+    db: Database,
+}
+
+impl DbAuditLog {
+    pub fn new(db: Database) -> DbAuditLog {
+        DbAuditLog { db }
+    }
+
+    fn query_doc(filter: &AuditFilter) -> Result<Document, AuditError> {
+        let mut query = Document::new();
+        if let Some(actor) = &filter.actor {
+            query.insert("actor", actor.as_str());
+        }
+        if let Some(source) = &filter.source {
+            query.insert("source", to_bson(source).map_err(AuditError::fmt)?);
+        }
+        if let Some(operation) = &filter.operation {
+            query.insert("operation", to_bson(operation).map_err(AuditError::fmt)?);
+        }
+        if let Some(trace_id) = &filter.trace_id {
+            query.insert("trace_id", trace_id.as_str());
+        }
+
+        let mut timestamp = Document::new();
+        if let Some(from) = filter.from {
+            timestamp.insert("$gte", from as i64);
+        }
+        if let Some(to) = filter.to {
+            timestamp.insert("$lte", to as i64);
+        }
+        if !timestamp.is_empty() {
+            query.insert("timestamp", timestamp);
+        }
+
+        Ok(query)
+    }
+}
+
+#[async_trait]
+impl AuditLog for DbAuditLog {
+    async fn record(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        self.db
+            .collection::<AuditEntry>(AUDIT_LOG_TABLE)
+            .insert_one(entry, None)
+            .await
+            .map(|_| ())
+            .map_err(AuditError::fmt)
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        let entries: Vec<AuditEntry> = self
+            .db
+            .collection::<AuditEntry>(AUDIT_LOG_TABLE)
+            .find(
+                Self::query_doc(filter)?,
+                FindOptions::builder().sort(doc! {"timestamp": 1}).build(),
+            )
+            .await
+            .map_err(AuditError::fmt)?
+            .try_collect()
+            .await
+            .map_err(AuditError::fmt)?;
+
+        // targets are matched by prefix, which the filter does better than a regex
+        Ok(filter.apply(entries))
+    }
+}
+
+const AUDIT_LOG_TABLE: &str = "audit_log";
@@ -1,11 +1,14 @@
 use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::string::ToString;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use frunk_core::hlist;
 use futures::TryStreamExt;
-use mongodb::bson::doc;
+use mongodb::bson::{doc, to_bson};
+use mongodb::options::UpdateOptions;
 use mongodb::Database;
+use serde::{Deserialize, Serialize};
 
 use house::devices::power_socket::PowerSocket;
 use house::devices::temperature_sensor::TemperatureSensor;
@@ -14,9 +17,18 @@ use house::errors::intelligent_house_error::InventoryError::{
     InventoryDeviceAlreadyAdded, InventoryDeviceNameDuplicated, InventoryDeviceNotFound,
     InventoryInternalError, InventoryRoomNotFound,
 };
+use house::history::domain::Timestamp;
 use house::house::domain::{DeviceName, RoomName};
 use house::inventory::device_inventory::DeviceInventory;
-use house::inventory::domain::{get_info, DeviceItem, RoomDevices};
+use house::inventory::domain::{get_info, DeviceHealth, DeviceItem, DeviceStatus, RoomDevices};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceHealthRecord {
+    room_name: RoomName,
+    device_name: DeviceName,
+    #[serde(flatten)]
+    health: DeviceHealth,
+}
 
 #[derive(Clone)]
 pub struct DbDeviceInventory {
@@ -58,6 +70,26 @@ impl DbDeviceInventory {
             .map(|_| ())
             .map_err(InventoryError::fmt)
     }
+
+    async fn update_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        update: mongodb::bson::Document,
+    ) -> Result<(), InventoryError> {
+        self.get_device(room_name, device_name).await?;
+
+        self.db
+            .collection::<DeviceHealthRecord>(DEVICE_HEALTH_TABLE)
+            .update_one(
+                doc! {"room_name": room_name.0.as_str(), "device_name": device_name.0.as_str()},
+                update,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(InventoryError::fmt)
+    }
 }
 
 #[async_trait]
@@ -95,6 +127,12 @@ impl DeviceInventory for DbDeviceInventory {
     }
 
     async fn remove_room(&self, room_name: &RoomName) -> Result<(), InventoryError> {
+        self.db
+            .collection::<DeviceHealthRecord>(DEVICE_HEALTH_TABLE)
+            .delete_many(doc! {"room_name": room_name.0.as_str()}, None)
+            .await
+            .map_err(InventoryError::fmt)?;
+
         self.db
             .collection::<RoomDevices>(ROOM_DEVICES_TABLE)
             .delete_one(doc! {"name": room_name.0.as_str()}, None)
@@ -157,6 +195,15 @@ impl DeviceInventory for DbDeviceInventory {
         room_devices.sockets.remove(device_name);
         room_devices.sensors.remove(device_name);
 
+        self.db
+            .collection::<DeviceHealthRecord>(DEVICE_HEALTH_TABLE)
+            .delete_one(
+                doc! {"room_name": room_name.0.as_str(), "device_name": device_name.0.as_str()},
+                None,
+            )
+            .await
+            .map_err(InventoryError::fmt)?;
+
         self.save_devices(room_name, room_devices).await
     }
 
@@ -226,6 +273,108 @@ impl DeviceInventory for DbDeviceInventory {
             (None, Some(sensor)) => Ok(DeviceItem::inject(*sensor)),
         }
     }
+
+    async fn get_health(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<DeviceHealth, InventoryError> {
+        self.get_device(room_name, device_name).await?;
+
+        let record = self
+            .db
+            .collection::<DeviceHealthRecord>(DEVICE_HEALTH_TABLE)
+            .find_one(
+                doc! {"room_name": room_name.0.as_str(), "device_name": device_name.0.as_str()},
+                None,
+            )
+            .await
+            .map_err(InventoryError::fmt)?;
+
+        Ok(record.map(|r| r.health).unwrap_or_default())
+    }
+
+    async fn report_seen(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+        timestamp: Timestamp,
+    ) -> Result<(), InventoryError> {
+        let online = to_bson(&DeviceStatus::Online).map_err(InventoryError::fmt)?;
+        self.update_health(
+            room_name,
+            device_name,
+            doc! {
+                "$set": { "status": online },
+                "$max": { "last_seen": timestamp as i64 },
+                "$setOnInsert": { "errors": 0 },
+            },
+        )
+        .await
+    }
+
+    async fn report_error(
+        &self,
+        room_name: &RoomName,
+        device_name: &DeviceName,
+    ) -> Result<(), InventoryError> {
+        let unknown = to_bson(&DeviceStatus::Unknown).map_err(InventoryError::fmt)?;
+        self.update_health(
+            room_name,
+            device_name,
+            doc! {
+                "$inc": { "errors": 1 },
+                "$setOnInsert": { "status": unknown, "last_seen": null },
+            },
+        )
+        .await
+    }
+
+    async fn expire_health(
+        &self,
+        now: Timestamp,
+        timeout: Duration,
+    ) -> Result<Vec<(RoomName, DeviceName)>, InventoryError> {
+        let online = to_bson(&DeviceStatus::Online).map_err(InventoryError::fmt)?;
+        let offline = to_bson(&DeviceStatus::Offline).map_err(InventoryError::fmt)?;
+        let deadline = now.saturating_sub(timeout.as_millis() as Timestamp) as i64;
+        let filter = doc! {"status": online.clone(), "last_seen": { "$lt": deadline }};
+
+        let collection = self
+            .db
+            .collection::<DeviceHealthRecord>(DEVICE_HEALTH_TABLE);
+
+        let candidates: Vec<DeviceHealthRecord> = collection
+            .find(filter, None)
+            .await
+            .map_err(InventoryError::fmt)?
+            .try_collect()
+            .await
+            .map_err(InventoryError::fmt)?;
+
+        // a device seen meanwhile no longer matches and stays online
+        let mut expired = Vec::new();
+        for record in candidates {
+            let updated = collection
+                .update_one(
+                    doc! {
+                        "room_name": record.room_name.0.as_str(),
+                        "device_name": record.device_name.0.as_str(),
+                        "status": online.clone(),
+                        "last_seen": { "$lt": deadline },
+                    },
+                    doc! {"$set": { "status": offline.clone() }},
+                    None,
+                )
+                .await
+                .map_err(InventoryError::fmt)?;
+            if updated.modified_count > 0 {
+                expired.push((record.room_name, record.device_name));
+            }
+        }
+        Ok(expired)
+    }
 }
 
 const ROOM_DEVICES_TABLE: &str = "room_devices";
+const DEVICE_HEALTH_TABLE: &str = "device_health";
@@ -11,6 +11,7 @@ use house::errors::intelligent_house_error::HouseError::RoomNotFound;
 use house::house::domain::{DeviceName, HouseName, Room, RoomName};
 use house::house::intelligent_house::IntelligentHouse;
 use house::inventory::device_inventory::DeviceInventory;
+use house::inventory::domain::get_report_info;
 
 #[derive(Debug, Clone)]
 pub struct DbIntelligentHouse {
@@ -147,10 +148,8 @@ impl IntelligentHouse for DbIntelligentHouse {
                     .fold(
                         ("".to_string(), room.name.clone()),
                         |(acc_dev_info, rn), device_name| async move {
-                            let _dev_info: String = inventory
-                                .get_info(&rn, &device_name)
-                                .await
-                                .unwrap_or_else(|e| format!("{e}"));
+                            let _dev_info: String =
+                                get_report_info(inventory, &rn, &device_name).await;
 
                             (format!("{acc_dev_info}     {_dev_info}\n"), rn)
                         },
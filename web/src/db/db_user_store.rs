@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, to_bson, Bson};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+
+use house::access::domain::{Action, Role, User, UserName};
+use house::access::user_store::UserStore;
+use house::errors::intelligent_house_error::AccessError;
+use house::errors::intelligent_house_error::AccessError::{
+    AccessUserAlreadyAdded, AccessUserNotFound,
+};
+use house::house::domain::RoomName;
+
+/// A user as stored, room names are values rather than keys so any name is safe in a
+/// field path.
+#[derive(Debug, Serialize, Deserialize)]
+struct UserRecord {
+    name: UserName,
+    role: Role,
+    rooms: Vec<RoomGrant>,
+    password_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RoomGrant {
+    room: RoomName,
+    actions: Vec<Action>,
+}
+
+impl From<User> for UserRecord {
+    fn from(user: User) -> Self {
+        UserRecord {
+            name: user.name,
+            role: user.role,
+            rooms: user
+                .rooms
+                .into_iter()
+                .map(|(room, actions)| RoomGrant { room, actions })
+                .collect(),
+            password_hash: user.password_hash,
+        }
+    }
+}
+
+impl From<UserRecord> for User {
+    fn from(record: UserRecord) -> Self {
+        User {
+            name: record.name,
+            role: record.role,
+            rooms: record
+                .rooms
+                .into_iter()
+                .map(|grant| (grant.room, grant.actions))
+                .collect(),
+            password_hash: record.password_hash,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DbUserStore {
+    db: Database,
+}
+
+impl DbUserStore {
+    pub fn new(db: Database) -> DbUserStore {
+        DbUserStore { db }
+    }
+}
+
+#[async_trait]
+impl UserStore for DbUserStore {
+    async fn get_users(&self) -> Result<Vec<User>, AccessError> {
+        let records: Vec<UserRecord> = self
+            .db
+            .collection::<UserRecord>(USERS_TABLE)
+            .find(None, None)
+            .await
+            .map_err(AccessError::fmt)?
+            .try_collect()
+            .await
+            .map_err(AccessError::fmt)?;
+
+        Ok(records.into_iter().map(User::from).collect())
+    }
+
+    async fn get_user(&self, user_name: &UserName) -> Result<User, AccessError> {
+        self.db
+            .collection::<UserRecord>(USERS_TABLE)
+            .find_one(doc! {"name": user_name.0.as_str()}, None)
+            .await
+            .map_err(AccessError::fmt)?
+            .map(User::from)
+            .ok_or_else(|| AccessUserNotFound(user_name.clone()))
+    }
+
+    async fn add_user(&self, user: User) -> Result<(), AccessError> {
+        if self.get_user(&user.name).await.is_ok() {
+            return Err(AccessUserAlreadyAdded(user.name));
+        }
+
+        self.db
+            .collection::<UserRecord>(USERS_TABLE)
+            .insert_one(UserRecord::from(user), None)
+            .await
+            .map(|_| ())
+            .map_err(AccessError::fmt)
+    }
+
+    async fn remove_user(&self, user_name: &UserName) -> Result<(), AccessError> {
+        let result = self
+            .db
+            .collection::<UserRecord>(USERS_TABLE)
+            .delete_one(doc! {"name": user_name.0.as_str()}, None)
+            .await
+            .map_err(AccessError::fmt)?;
+
+        match result.deleted_count {
+            0 => Err(AccessUserNotFound(user_name.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    async fn grant(
+        &self,
+        user_name: &UserName,
+        room_name: &RoomName,
+        actions: Vec<Action>,
+    ) -> Result<(), AccessError> {
+        // the room name is a `$literal`, a name starting with `$` is no field path then
+        let room = doc! {"$literal": room_name.0.as_str()};
+        let others = doc! {"$filter": {
+            "input": "$rooms",
+            "cond": { "$ne": ["$$this.room", room.clone()] },
+        }};
+        let rooms: Bson = if actions.is_empty() {
+            others.into()
+        } else {
+            let actions = to_bson(&actions).map_err(AccessError::fmt)?;
+            let grant = doc! {"room": room, "actions": {"$literal": actions}};
+            doc! {"$concatArrays": [others, [grant]]}.into()
+        };
+        // one pipeline update, concurrent grants to other rooms are kept
+        let update = vec![doc! {"$set": {"rooms": rooms}}];
+
+        let result = self
+            .db
+            .collection::<UserRecord>(USERS_TABLE)
+            .update_one(doc! {"name": user_name.0.as_str()}, update, None)
+            .await
+            .map_err(AccessError::fmt)?;
+
+        match result.matched_count {
+            0 => Err(AccessUserNotFound(user_name.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+const USERS_TABLE: &str = "users";
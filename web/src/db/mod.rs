@@ -1,2 +1,4 @@
+pub mod db_audit_log;
 pub mod db_device_inventory;
 pub mod db_intelligent_house;
+pub mod db_user_store;
@@ -1,20 +1,58 @@
 use mongodb::Client;
+use serde::Deserialize;
+
+use house::access::domain::{Role, UserName};
+use house::access::rate_limiter::RateLimiter;
+use house::access::sessions::Sessions;
+use house::history::domain::Timestamp;
+use house::history::memory_device_history::MemoryDeviceHistory;
 
 use crate::actions::service::DataService;
+use crate::db::db_audit_log::DbAuditLog;
 use crate::db::db_device_inventory::DbDeviceInventory;
 use crate::db::db_intelligent_house::DbIntelligentHouse;
+use crate::db::db_user_store::DbUserStore;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub data: DataService<DbDeviceInventory, DbIntelligentHouse>,
+    pub data: DataService<DbDeviceInventory, DbIntelligentHouse, MemoryDeviceHistory, DbAuditLog>,
+    pub users: DbUserStore,
+    pub sessions: Sessions,
+    pub limiter: RateLimiter,
 }
 
 impl AppState {
-    pub fn new(db_client: Client) -> Self {
+    pub fn new(
+        db_client: Client,
+        history: MemoryDeviceHistory,
+        sessions: Sessions,
+        limiter: RateLimiter,
+    ) -> Self {
         let inventory = DbDeviceInventory::new(db_client.database("inventory"));
         let house = DbIntelligentHouse::new("Plaza house", db_client.database("house"));
         AppState {
-            data: DataService::create(inventory, house),
+            data: DataService::create(
+                inventory,
+                house,
+                history,
+                DbAuditLog::new(db_client.database("audit")),
+            ),
+            users: DbUserStore::new(db_client.database("access")),
+            sessions,
+            limiter,
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryWindow {
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewUser {
+    pub name: UserName,
+    pub role: Role,
+    pub password: String,
+}
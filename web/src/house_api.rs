@@ -1,29 +1,53 @@
 use std::net::ToSocketAddrs;
 
+use std::time::Duration;
+
 use actix_web::{web, web::Data, App, HttpResponse, HttpServer};
+use house::access::domain::{Credentials, Role, User};
+use house::access::rate_limiter::{RateLimiter, RateLimits};
+use house::access::sessions::Sessions;
+use house::access::user_store::UserStore;
+use house::errors::intelligent_house_error::IntelligentHouseError;
+use house::history::history_recorder::HistoryRecorder;
+use house::history::memory_device_history::MemoryDeviceHistory;
+use house::inventory::health_watchdog::HealthWatchdog;
+use house::simulation::clock::SystemClock;
 use mongodb::Client;
 use tokio::task;
 use tokio::task::JoinHandle;
+use tracing::warn;
 
+use crate::actions::access::*;
 use crate::actions::*;
+use crate::db::db_device_inventory::DbDeviceInventory;
+use crate::db::db_user_store::DbUserStore;
 use crate::domain::AppState;
 use crate::error::HouseApiError;
 use crate::error::HouseApiError::IOError;
+use crate::rate_limit::limited;
+use crate::trace::traced;
+
+const HISTORY_SAMPLING_PERIOD: Duration = Duration::from_secs(1);
+const DEVICE_OFFLINE_TIMEOUT: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_PERIOD: Duration = Duration::from_secs(1);
 
 pub struct HouseAPI {
     server_handle: JoinHandle<()>,
 }
 
 impl HouseAPI {
+    /// Every client is held to the `rate_limits`, see `rate_limit::limited`.
     pub async fn start<Addrs: ToSocketAddrs + Send + 'static>(
         address: Addrs,
         connection: String,
         drop_db: bool,
+        owner: Credentials,
+        rate_limits: RateLimits,
     ) -> Result<Self, HouseApiError> {
         let server_handle: JoinHandle<()> = tokio::spawn(async move {
-            Self::execute(address, connection, drop_db)
+            Self::execute(address, connection, drop_db, owner, rate_limits)
                 .await
-                .unwrap_or_else(|error| eprintln!("house web server: starting failed: {error:?}"));
+                .unwrap_or_else(|error| warn!(?error, "house web server failed"));
         });
 
         Ok(HouseAPI { server_handle })
@@ -33,17 +57,69 @@ impl HouseAPI {
         address: Addrs,
         db_connection: String,
         drop_db: bool,
+        owner: Credentials,
+        rate_limits: RateLimits,
     ) -> Result<(), HouseApiError> {
         let db_client = Client::with_uri_str(db_connection).await?;
         if drop_db {
             db_client.database("inventory").drop(None).await.unwrap();
             db_client.database("house").drop(None).await.unwrap();
+            db_client.database("access").drop(None).await.unwrap();
+            db_client.database("audit").drop(None).await.unwrap();
+        }
+
+        let users = DbUserStore::new(db_client.database("access"));
+        if users.get_user(&owner.user_name).await.is_err() {
+            users
+                .add_user(User::new(owner.user_name, Role::Owner, &owner.password))
+                .await
+                .map_err(IntelligentHouseError::AccessErr)?;
         }
 
+        let history = MemoryDeviceHistory::default();
+        HistoryRecorder::new(
+            DbDeviceInventory::new(db_client.database("inventory")),
+            history.clone(),
+            SystemClock,
+        )
+        .start(HISTORY_SAMPLING_PERIOD);
+
+        HealthWatchdog::new(
+            DbDeviceInventory::new(db_client.database("inventory")),
+            SystemClock,
+            DEVICE_OFFLINE_TIMEOUT,
+        )
+        .start(HEALTH_CHECK_PERIOD);
+
+        let sessions = Sessions::default();
+        // shared by the workers, a client is counted once whichever serves it
+        let limiter = RateLimiter::new(rate_limits);
         let server = HttpServer::new(move || {
             App::new()
-                .app_data(Data::new(AppState::new(db_client.clone())))
+                .wrap_fn(limited)
+                .wrap_fn(traced)
+                .app_data(Data::new(AppState::new(
+                    db_client.clone(),
+                    history.clone(),
+                    sessions.clone(),
+                    limiter.clone(),
+                )))
                 .service(web::resource("/readiness").route(web::get().to(HttpResponse::Ok)))
+                .service(web::resource("/login").route(web::post().to(login)))
+                .service(web::resource("/logout").route(web::post().to(logout)))
+                .service(
+                    web::scope("/users")
+                        .service(
+                            web::resource("")
+                                .route(web::get().to(get_users))
+                                .route(web::post().to(add_user)),
+                        )
+                        .service(web::resource("/{name}").route(web::delete().to(delete_user)))
+                        .service(
+                            web::resource("/{name}/rooms/{room_name}")
+                                .route(web::put().to(grant_room)),
+                        ),
+                )
                 .service(
                     web::scope("/rooms")
                         .service(web::resource("").route(web::get().to(get_rooms)))
@@ -84,10 +160,27 @@ impl HouseAPI {
                                 .service(
                                     web::resource("/{device_name}")
                                         .route(web::delete().to(delete_inventory_device)),
+                                )
+                                .service(
+                                    web::resource("/{device_name}/health")
+                                        .route(web::get().to(get_device_health)),
+                                )
+                                .service(
+                                    web::resource("/{device_name}/seen")
+                                        .route(web::post().to(report_device_seen)),
                                 ),
                         ),
                 )
                 .service(web::resource("/report").route(web::get().to(get_house_report)))
+                .service(
+                    web::scope("/audit")
+                        .service(web::resource("").route(web::get().to(get_audit)))
+                        .service(web::resource("/export").route(web::get().to(export_audit))),
+                )
+                .service(
+                    web::resource("/history/{room_name}/{device_name}")
+                        .route(web::get().to(get_device_history)),
+                )
         })
         .bind(address)?
         .run();
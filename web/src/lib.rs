@@ -3,5 +3,7 @@ pub mod db;
 pub mod domain;
 pub mod error;
 pub mod house_api;
+pub mod rate_limit;
+pub mod trace;
 
 extern crate serde;
@@ -0,0 +1,78 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{AUTHORIZATION, RETRY_AFTER};
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+use futures::future::{ready, LocalBoxFuture};
+use futures::FutureExt;
+use tracing::info;
+
+use house::access::domain::SessionToken;
+use house::access::rate_limiter::Client;
+use house::errors::intelligent_house_error::AccessError::AccessThrottled;
+use house::errors::intelligent_house_error::IntelligentHouseError::AccessErr;
+
+use crate::domain::AppState;
+
+/// Counts every request against the rate limits before it is handled, a throttled one is
+/// answered with `429 Too Many Requests`. Requests are counted for the user of the
+/// session if there is one, or else for the host, and their type is the method and the
+/// route, e.g. `POST /rooms/{name}`.
+pub fn limited<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse<EitherBody<B>>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    let throttled = request.app_data::<Data<AppState>>().and_then(|state| {
+        state
+            .limiter
+            .check(&clients(&request, state), &kind(&request))
+            .err()
+    });
+
+    match throttled {
+        Some(AccessThrottled(retry_after)) => {
+            info!(?retry_after, "request throttled");
+            // a client must not retry before the wait is over, so it is rounded up
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, seconds))
+                .json(AccessErr(AccessThrottled(retry_after)));
+            ready(Ok(request.into_response(response).map_into_right_body())).boxed_local()
+        }
+        _ => service
+            .call(request)
+            .map(|response| response.map(ServiceResponse::map_into_left_body))
+            .boxed_local(),
+    }
+}
+
+fn clients(request: &ServiceRequest, state: &AppState) -> Vec<Client> {
+    let user = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| {
+            state
+                .sessions
+                .get(&SessionToken(token.trim().to_string()))
+                .ok()
+        })
+        .map(Client::User);
+    let host = request
+        .peer_addr()
+        .map(|address| Client::Address(address.ip()));
+    user.into_iter().chain(host).collect()
+}
+
+fn kind(request: &ServiceRequest) -> String {
+    let route = request
+        .match_pattern()
+        .unwrap_or_else(|| request.path().to_string());
+    format!("{} {route}", request.method())
+}
@@ -0,0 +1,53 @@
+use std::future::Future;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::HttpMessage;
+use tracing::{debug, info_span, Instrument};
+
+use house::audit::domain::{is_valid_trace_id, new_trace_id};
+
+/// Carries the trace id of a request in and out, so a caller can follow it in the logs.
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// The trace id of the request being handled, in the request extensions.
+#[derive(Debug, Clone)]
+pub struct TraceId(pub String);
+
+/// Runs every request in a span of its own, under the trace id the caller gave or a new
+/// one, and answers with it.
+pub fn traced<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let trace_id = request
+        .headers()
+        .get(TRACE_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|trace_id| is_valid_trace_id(trace_id))
+        .map(str::to_string)
+        .unwrap_or_else(new_trace_id);
+    let span = info_span!(
+        "request",
+        %trace_id,
+        method = %request.method(),
+        path = %request.path()
+    );
+    request.extensions_mut().insert(TraceId(trace_id.clone()));
+    let response = service.call(request);
+
+    async move {
+        let mut response = response.await?;
+        debug!(status = response.status().as_u16(), "request handled");
+        if let Ok(value) = HeaderValue::from_str(&trace_id) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(TRACE_ID_HEADER), value);
+        }
+        Ok(response)
+    }
+    .instrument(span)
+}